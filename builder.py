@@ -24,6 +24,9 @@ match argv[1]:
         builder.build(project_root)
         builder.run(project_root)
 
+    case "test":
+        builder.test(project_root)
+
     case "run-uefi":
         builder.build(project_root)
         builder.run(project_root, uefi=True)
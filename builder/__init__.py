@@ -6,6 +6,7 @@ from .build import build_buterscotch
 from .clean import clean
 from .img import makeimg
 from .system import system
+from .test import boot_kernel, test_host, test_kernel
 
 profile = environ.get("RUST_PROFILE", "dev")
 
@@ -26,3 +27,9 @@ def run(project_root: Path, uefi: bool = False):
         args += f"-bios {project_root.joinpath("ovmf/OVMF.fd")}"
 
     system(f"qemu-system-x86_64 -M q35 -m 2G -serial stdio -device isa-debug-exit,iobase=0xf4,iosize=0x04 {args} {img}")
+
+
+def test(project_root: Path):
+    fetch_extenal_resources(project_root)
+    test_host(project_root)
+    test_kernel(project_root)
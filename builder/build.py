@@ -8,7 +8,8 @@ def build_buterscotch(project_root: Path, sysroot: Path, profile: str):
     build_cargo(project_root, profile)
 
     print(" * Building Sysroot")
-    make_sysroot(sysroot, project_root, profile)
+    kernel = get_build_dir(project_root, profile).joinpath("butterscotch_kernel")
+    make_sysroot(sysroot, project_root, kernel)
 
 
 def build_cargo(project_root: Path, profile: str):
@@ -19,7 +20,7 @@ def build_cargo(project_root: Path, profile: str):
 def make_sysroot(
     sysroot: Path,
     project_root: Path,
-    profile: str,
+    kernel: Path,
 ):
     # Clear previous sysroot
     if sysroot.exists():
@@ -29,7 +30,7 @@ def make_sysroot(
     boot = sysroot.joinpath("boot")
     boot.mkdir()
 
-    install_kernel(boot, get_build_dir(project_root, profile))
+    install_kernel(boot, kernel)
     install_limine(project_root, boot.joinpath("limine"))
 
     efi = sysroot.joinpath("EFI", "BOOT")
@@ -50,10 +51,8 @@ def install_limine(project_root: Path, dir: Path):
         copy(project_root.joinpath("limine", file), dir.joinpath(file))
 
 
-def install_kernel(boot: Path, build_dir: Path):
-    copy(
-        build_dir.joinpath("butterscotch_kernel"), boot.joinpath("butterscotch.kernel")
-    )
+def install_kernel(boot: Path, kernel: Path):
+    copy(kernel, boot.joinpath("butterscotch.kernel"))
 
 
 def install_efi(project_root: Path, efi: Path):
@@ -2,9 +2,10 @@ from pathlib import Path
 from .system import system
 
 
-def makeimg(project_root: Path, sysroot: Path):
+def makeimg(project_root: Path, sysroot: Path, img: Path | None = None):
     print(" * Building Boot Image")
-    img = project_root.joinpath("butterscotch.img")
+    if img is None:
+        img = project_root.joinpath("butterscotch.img")
     limine = project_root.joinpath("limine/limine")
     if img.exists():
         img.unlink()
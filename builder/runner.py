@@ -0,0 +1,25 @@
+#!/usr/bin/env python3
+"""Cargo runner for the kernel target, see kernel/.cargo/config.toml
+
+Cargo calls this with the path of a kernel binary, which is booted in QEMU.
+Test binaries run headless, and pass when the kernel exits QEMU through
+isa-debug-exit with a success code
+"""
+
+from pathlib import Path
+from sys import argv, path
+
+project_root = Path(__file__).resolve().parent.parent
+path.insert(0, str(project_root))
+
+import builder  # noqa: E402
+
+if len(argv) < 2:
+    print(f"Usage: {argv[0]} [kernel]")
+    exit(-1)
+
+kernel = Path(argv[1]).resolve()
+# Test binaries are built into target/<target>/<profile>/deps, and the kernel itself one level up
+test = kernel.parent.name == "deps"
+
+exit(builder.boot_kernel(project_root, kernel, test))
@@ -1,11 +1,14 @@
 import subprocess
 
 
-def system(cmd: str):
+def system(cmd: str, capture: bool = True):
     output = ""
     try:
-        output = subprocess.check_output(cmd, shell=True)
+        if capture:
+            output = subprocess.check_output(cmd, shell=True)
+        else:
+            subprocess.check_call(cmd, shell=True)
     except subprocess.CalledProcessError:
         print(output)
         print(f"Failed to run `{cmd}`")
-        exit(-1)
\ No newline at end of file
+        exit(-1)
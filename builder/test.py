@@ -0,0 +1,74 @@
+import re
+import subprocess
+from pathlib import Path
+from tempfile import TemporaryDirectory
+
+from .build import make_sysroot
+from .external_resources import fetch_extenal_resources
+from .img import makeimg
+from .system import system
+
+# Crates that don't touch the hardware, and are tested on the host
+HOST_CRATES = ["libk", "fat32", "ramfs"]
+
+# Kernel tests that hang are killed after this many seconds
+TEST_TIMEOUT = 300
+# QEMU exits with `(code << 1) | 1` when the kernel writes `code` to isa-debug-exit,
+# see `QemuExitCode` in kernel/core/src/testing.rs
+QEMU_SUCCESS = (0x10 << 1) | 1
+
+
+def test_host(project_root: Path):
+    print(" --- Running host tests ---")
+    manifest = project_root.joinpath("kernel", "Cargo.toml")
+    packages = " ".join(f"-p {crate}" for crate in HOST_CRATES)
+
+    # Cargo reads kernel/.cargo/config.toml based on the working directory, running
+    # from the project root builds for the host instead of the kernel target
+    system(
+        f"cd {project_root} && cargo test --manifest-path {manifest} {packages}",
+        capture=False,
+    )
+
+
+def test_kernel(project_root: Path):
+    print(" --- Running kernel tests ---")
+    kernel = project_root.joinpath("kernel")
+
+    # Every test binary is booted by builder/runner.py
+    system(f"cd {kernel} && cargo test -p butterscotch_kernel", capture=False)
+
+
+def boot_kernel(project_root: Path, kernel: Path, test: bool) -> int:
+    """Boots `kernel` in QEMU from a throwaway image, returns the exit code for cargo"""
+    fetch_extenal_resources(project_root)
+
+    with TemporaryDirectory() as tmp:
+        sysroot = Path(tmp, "sysroot")
+        img = Path(tmp, "butterscotch.img")
+        make_sysroot(sysroot, project_root, kernel)
+
+        # Boot straight away instead of waiting in the boot menu
+        cfg = sysroot.joinpath("boot", "limine", "limine.cfg")
+        cfg.write_text(re.sub(r"TIMEOUT=\d+", "TIMEOUT=0", cfg.read_text()))
+        makeimg(project_root, sysroot, img)
+
+        args = [
+            "qemu-system-x86_64",
+            "-M", "q35",
+            "-m", "2G",
+            "-serial", "stdio",
+            "-device", "isa-debug-exit,iobase=0xf4,iosize=0x04",
+        ]
+        if not test:
+            return subprocess.run([*args, img]).returncode
+
+        try:
+            result = subprocess.run(
+                [*args, "-display", "none", img], timeout=TEST_TIMEOUT
+            )
+        except subprocess.TimeoutExpired:
+            print(f"Timed out after {TEST_TIMEOUT} seconds")
+            return 1
+
+        return 0 if result.returncode == QEMU_SUCCESS else 1
@@ -1,14 +1,11 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
 
-mod fat32;
-
-use crate::fat32::Fat;
-
-use color_eyre::eyre::Result;
-use pretty_hex::PrettyHex;
-use std::{fs::File, io::Read};
+use color_eyre::eyre::{eyre, Result};
+use fat32::FatFs;
 use libk::Mutex;
+use pretty_hex::PrettyHex;
+use std::{env, fs::File, io::Read};
 
 // struct FileWrapper(File);
 
@@ -41,11 +38,28 @@ use libk::Mutex;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let path = env::args().nth(1).unwrap_or_else(|| "test.txt".into());
+
     let mut arr = Vec::new();
-    File::open("test.img")?.read_to_end(&mut arr);
+    File::open("test.img")?.read_to_end(&mut arr)?;
     let arr = Mutex::new(arr);
     let image = libk::io::ramfile::RamFile::new(&arr, true);
-    let fat_reader = Fat::new(image);
+    let fat = FatFs::new(image).map_err(|e| eyre!("Unable to mount test.img: {e}"))?;
+    println!("{:#?}", fat.info());
+
+    let mut file = fat
+        .open_file(path.as_str(), true)
+        .map_err(|e| eyre!("Unable to open {path}: {e}"))?;
+    let mut contents = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        match libk::io::Read::read(&mut file, &mut buf) {
+            Ok(0) => break,
+            Ok(n) => contents.extend_from_slice(&buf[..n]),
+            Err(e) => return Err(eyre!("Unable to read {path}: {e}")),
+        }
+    }
+    println!("{:?}", contents.hex_dump());
 
     Ok(())
 }
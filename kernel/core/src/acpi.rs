@@ -0,0 +1,224 @@
+//! ACPI table discovery
+//!
+//! Finds the tables through the RSDP Limine hands over, and parses the MADT, which
+//! describes the interrupt controllers. Everything else is left to the code that
+//! needs a table, through `find_table`
+
+use alloc::vec::Vec;
+use libk::Once;
+use x86_64::{structures::paging::PageTableFlags, PhysAddr, VirtAddr};
+
+use crate::{limine_requests::RSDP_REQUEST, memory, memory::address_space::RegionKind};
+
+/// Length of the header every system description table starts with
+const HEADER_SIZE: usize = 36;
+
+static TABLES: Once<Vec<&'static [u8]>> = Once::new();
+static MADT: Once<Option<Madt>> = Once::new();
+
+/// Maps every table listed in the RSDT or XSDT
+///
+/// Called by kernel::init, after the heap is initialized. Finds no tables if the
+/// bootloader didn't find an RSDP
+pub fn init() {
+    TABLES.call_once(|| {
+        let mut tables = Vec::new();
+        if let Some((root, entry_size)) = root_table() {
+            for entry in root[HEADER_SIZE..].chunks_exact(entry_size) {
+                let mut address = [0u8; 8];
+                address[..entry_size].copy_from_slice(entry);
+                if let Some(table) = map_table(PhysAddr::new(u64::from_le_bytes(address))) {
+                    tables.push(table);
+                }
+            }
+        }
+        tables
+    });
+}
+
+/// The first table with `signature`, including its header
+pub fn find_table(signature: &[u8; 4]) -> Option<&'static [u8]> {
+    TABLES
+        .get()?
+        .iter()
+        .find(|table| &table[..4] == signature)
+        .copied()
+}
+
+/// The MADT, None if the firmware has no ACPI tables or no MADT
+pub fn madt() -> Option<&'static Madt> {
+    MADT.call_once(|| Madt::parse(find_table(b"APIC")?))
+        .as_ref()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// First global system interrupt the I/O APIC handles
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ that isn't connected to the global system interrupt with the same number,
+/// or doesn't use the ISA default of edge triggered and active high
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+#[derive(Debug)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    /// Whether the legacy 8259 PICs are present, they have to be masked when using the APIC
+    pub has_8259: bool,
+    pub local_apic_ids: Vec<u32>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+impl Madt {
+    fn parse(table: &[u8]) -> Option<Self> {
+        let mut madt = Madt {
+            local_apic_address: PhysAddr::new(read_u32(table, 36)? as u64),
+            has_8259: read_u32(table, 40)? & 1 != 0,
+            local_apic_ids: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+        };
+
+        let mut entries = table.get(44..)?;
+        while let [kind, len, ..] = *entries {
+            let len = len as usize;
+            let entry = entries.get(..len).filter(|_| len >= 2)?;
+            entries = &entries[len..];
+
+            match kind {
+                // Processor local APIC, only enabled or online capable ones
+                0 if read_u32(entry, 4)? & 0b11 != 0 => madt.local_apic_ids.push(entry[3] as u32),
+                1 => madt.io_apics.push(IoApicEntry {
+                    id: *entry.get(2)?,
+                    address: PhysAddr::new(read_u32(entry, 4)? as u64),
+                    gsi_base: read_u32(entry, 8)?,
+                }),
+                2 => {
+                    let flags = read_u16(entry, 8)?;
+                    madt.overrides.push(InterruptOverride {
+                        irq: entry[3],
+                        gsi: read_u32(entry, 4)?,
+                        active_low: flags & 0b11 == 0b11,
+                        level_triggered: (flags >> 2) & 0b11 == 0b11,
+                    });
+                }
+                // Local APIC address override
+                5 => madt.local_apic_address = PhysAddr::new(read_u64(entry, 4)?),
+                // Processor local x2APIC
+                9 if read_u32(entry, 8)? & 0b11 != 0 => {
+                    madt.local_apic_ids.push(read_u32(entry, 4)?)
+                }
+                _ => {}
+            }
+        }
+
+        Some(madt)
+    }
+
+    /// The global system interrupt ISA IRQ `irq` is connected to, and its override if it has one
+    pub fn isa_irq(&self, irq: u8) -> (u32, Option<&InterruptOverride>) {
+        match self.overrides.iter().find(|o| o.irq == irq) {
+            Some(o) => (o.gsi, Some(o)),
+            None => (irq as u32, None),
+        }
+    }
+}
+
+/// The XSDT, or the RSDT on ACPI 1.0, and the size of its entries
+fn root_table() -> Option<(&'static [u8], usize)> {
+    let rsdp = RSDP_REQUEST.get_response().get()?.address.as_ptr()?;
+    let rsdp = unsafe { core::slice::from_raw_parts(rsdp, 36) };
+    if &rsdp[..8] != b"RSD PTR " || !checksum_ok(&rsdp[..20]) {
+        return None;
+    }
+
+    let revision = rsdp[15];
+    if revision >= 2 && checksum_ok(rsdp) {
+        let xsdt = map_table(PhysAddr::new(read_u64(rsdp, 24)?))?;
+        return Some((xsdt, 8));
+    }
+    let rsdt = map_table(PhysAddr::new(read_u32(rsdp, 16)? as u64))?;
+    Some((rsdt, 4))
+}
+
+/// Maps a whole table, skipping tables with a bad checksum
+fn map_table(phys: PhysAddr) -> Option<&'static [u8]> {
+    let header = map(phys, HEADER_SIZE)?;
+    let len = (read_u32(header, 4)? as usize).max(HEADER_SIZE);
+    unmap(header);
+    let table = map(phys, len)?;
+
+    if !checksum_ok(table) {
+        unmap(table);
+        return None;
+    }
+    Some(table)
+}
+
+/// Tables are usually in memory covered by the direct map, they are mapped
+/// separately when they aren't
+fn map(phys: PhysAddr, len: usize) -> Option<&'static [u8]> {
+    let mut page_allocator = memory::PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut()?;
+
+    let direct = memory::physical_memory_offset() + phys.as_u64();
+    let last = direct + (len as u64 - 1);
+    let virt =
+        if page_allocator.translate(direct).is_some() && page_allocator.translate(last).is_some() {
+            direct
+        } else {
+            page_allocator
+                .map_physical(phys, len as u64, PageTableFlags::PRESENT)
+                .ok()?
+        };
+
+    Some(unsafe { core::slice::from_raw_parts(virt.as_ptr(), len) })
+}
+
+/// Undoes `map`, if the table wasn't in the direct map
+fn unmap(table: &[u8]) {
+    let mut page_allocator = memory::PAGE_ALLOCATOR.lock();
+    let Some(page_allocator) = page_allocator.as_mut() else {
+        return;
+    };
+
+    let virt = VirtAddr::from_ptr(table.as_ptr());
+    let region = page_allocator.address_space().find(virt).copied();
+    if let Some(region) = region.filter(|region| region.kind == RegionKind::Mmio) {
+        page_allocator
+            .vfree(region.start)
+            .expect("Unable to unmap an ACPI table");
+    }
+}
+
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
@@ -0,0 +1,302 @@
+//! Stack backtraces
+//!
+//! The kernel is built with frame pointers, so the saved `rbp` values form a chain
+//! through the stack, with each return address right above them. Addresses are
+//! symbolized with the symbol table of the kernel ELF, which Limine hands over
+
+use core::{fmt, str};
+use x86_64::VirtAddr;
+
+use crate::{
+    eprintln,
+    limine_requests::{KERNEL_ADDRESS_REQUEST, KERNEL_FILE_REQUEST},
+    memory,
+};
+
+/// Deeper backtraces are cut off, in case the chain loops
+const MAX_FRAMES: usize = 64;
+
+/// Prints the backtrace starting at `rip`, in a function whose frame pointer is `rbp`
+pub fn print(rip: u64, rbp: u64) {
+    eprintln!("Backtrace:");
+    eprint_frame(0, rip);
+    for (i, return_address) in frames(rbp).enumerate() {
+        // The call instruction is right before the return address
+        eprint_frame(i + 1, return_address - 1);
+    }
+}
+
+/// Prints the backtrace of the caller
+#[inline(never)]
+pub fn print_current() {
+    let rbp: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp) };
+
+    eprintln!("Backtrace:");
+    for (i, return_address) in frames(rbp).enumerate() {
+        eprint_frame(i, return_address - 1);
+    }
+}
+
+fn eprint_frame(index: usize, address: u64) {
+    match symbolize(address) {
+        Some((name, offset)) => {
+            eprintln!(
+                "{index:>4}: {address:#018x} - {}+{offset:#x}",
+                Demangle(name)
+            )
+        }
+        None => eprintln!("{index:>4}: {address:#018x} - <unknown>"),
+    }
+}
+
+/// Return addresses of the frames in the frame pointer chain starting at `rbp`
+pub fn frames(mut rbp: u64) -> impl Iterator<Item = u64> {
+    (0..MAX_FRAMES).map_while(move |_| {
+        // The return address is right above the saved frame pointer
+        if !is_readable(rbp) || !is_readable(rbp + 8) {
+            return None;
+        }
+        let (next_rbp, return_address) = unsafe {
+            let frame = rbp as *const u64;
+            (frame.read(), frame.add(1).read())
+        };
+
+        // The stack grows down, so callers' frames are always higher up
+        rbp = if next_rbp > rbp { next_rbp } else { 0 };
+        (return_address != 0).then_some(return_address)
+    })
+}
+
+/// Whether `addr` can be read without faulting, gives up if the page allocator is busy,
+/// as the fault might have happened while it was locked
+fn is_readable(addr: u64) -> bool {
+    if addr == 0 || !addr.is_multiple_of(8) {
+        return false;
+    }
+    let Ok(addr) = VirtAddr::try_new(addr) else {
+        return false;
+    };
+
+    memory::PAGE_ALLOCATOR
+        .try_lock()
+        .and_then(|page_allocator| page_allocator.as_ref()?.translate(addr))
+        .is_some()
+}
+
+/// Name of the function containing `address`, and the offset of `address` into it
+pub fn symbolize(address: u64) -> Option<(&'static str, u64)> {
+    let elf = kernel_elf()?;
+    let address = address.checked_sub(load_offset(elf)?)?;
+
+    let symtab = elf.sections().find(|section| section.kind == SHT_SYMTAB)?;
+    let strtab = elf.section(symtab.link as usize)?;
+    let symbols = elf.data.get(symtab.offset..symtab.offset + symtab.size)?;
+
+    symbols
+        .chunks_exact(SYMBOL_SIZE)
+        .filter(|symbol| symbol[4] & 0xf == STT_FUNC)
+        .find_map(|symbol| {
+            let start = read_u64(symbol, 8)?;
+            let size = read_u64(symbol, 16)?;
+            if !(start..start + size.max(1)).contains(&address) {
+                return None;
+            }
+
+            let name_offset = strtab.offset + read_u32(symbol, 0)? as usize;
+            let name = elf.data.get(name_offset..)?;
+            let name = &name[..name.iter().position(|&byte| byte == 0)?];
+            Some((str::from_utf8(name).ok()?, address - start))
+        })
+}
+
+fn kernel_elf() -> Option<Elf> {
+    let file = KERNEL_FILE_REQUEST
+        .get_response()
+        .get()?
+        .kernel_file
+        .get()?;
+    let base = file.base.as_ptr()?;
+    let data = unsafe { core::slice::from_raw_parts(base, file.length as usize) };
+
+    (data.get(..4)? == b"\x7fELF").then_some(Elf { data })
+}
+
+/// How far the kernel was moved from the addresses in the ELF file
+fn load_offset(elf: Elf) -> Option<u64> {
+    let virtual_base = KERNEL_ADDRESS_REQUEST.get_response().get()?.virtual_base;
+    let lowest = elf
+        .program_headers()
+        .filter(|header| read_u32(header, 0) == Some(PT_LOAD))
+        .filter_map(|header| read_u64(header, 0x10))
+        .min()?;
+
+    virtual_base.checked_sub(lowest)
+}
+
+const SHT_SYMTAB: u32 = 2;
+const STT_FUNC: u8 = 2;
+const PT_LOAD: u32 = 1;
+const SYMBOL_SIZE: usize = 24;
+
+/// Just enough of ELF64 to find the symbol table
+#[derive(Clone, Copy)]
+struct Elf {
+    data: &'static [u8],
+}
+
+struct Section {
+    kind: u32,
+    offset: usize,
+    size: usize,
+    link: u32,
+}
+
+impl Elf {
+    fn table(
+        &self,
+        offset: usize,
+        entry_size: usize,
+        count: usize,
+    ) -> impl Iterator<Item = &'static [u8]> {
+        let data = self.data;
+        let table = read_u64(data, offset)
+            .and_then(|start| data.get(start as usize..)?.get(..entry_size * count))
+            .unwrap_or(&[]);
+        table.chunks_exact(entry_size.max(1))
+    }
+
+    fn program_headers(&self) -> impl Iterator<Item = &'static [u8]> {
+        let entry_size = read_u16(self.data, 0x36).unwrap_or(0) as usize;
+        let count = read_u16(self.data, 0x38).unwrap_or(0) as usize;
+        self.table(0x20, entry_size, count)
+    }
+
+    fn sections(&self) -> impl Iterator<Item = Section> {
+        let entry_size = read_u16(self.data, 0x3a).unwrap_or(0) as usize;
+        let count = read_u16(self.data, 0x3c).unwrap_or(0) as usize;
+        self.table(0x28, entry_size, count).filter_map(|header| {
+            Some(Section {
+                kind: read_u32(header, 4)?,
+                offset: read_u64(header, 0x18)? as usize,
+                size: read_u64(header, 0x20)? as usize,
+                link: read_u32(header, 0x28)?,
+            })
+        })
+    }
+
+    fn section(&self, index: usize) -> Option<Section> {
+        self.sections().nth(index)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Displays a symbol name with legacy Rust mangling as a path, without the hash.
+/// Other names are displayed as they are
+pub struct Demangle<'a>(pub &'a str);
+
+impl fmt::Display for Demangle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(mut rest) = self
+            .0
+            .strip_prefix("_ZN")
+            .and_then(|rest| rest.strip_suffix('E'))
+        else {
+            return f.write_str(self.0);
+        };
+
+        let mut first = true;
+        while !rest.is_empty() {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let Some(len) = rest[..digits].parse::<usize>().ok() else {
+                return f.write_str(self.0);
+            };
+            let Some(component) = rest.get(digits..digits + len) else {
+                return f.write_str(self.0);
+            };
+            rest = &rest[digits + len..];
+
+            let is_hash = rest.is_empty()
+                && component.len() == 17
+                && component.starts_with('h')
+                && component[1..].bytes().all(|byte| byte.is_ascii_hexdigit());
+            if is_hash {
+                break;
+            }
+
+            if !first {
+                f.write_str("::")?;
+            }
+            first = false;
+            write_component(f, component)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Undoes the escapes used for characters that aren't allowed in symbols
+fn write_component(f: &mut fmt::Formatter, component: &str) -> fmt::Result {
+    // A leading `$` gets an underscore in front of it
+    let mut rest = match component.strip_prefix('_') {
+        Some(rest) if rest.starts_with('$') => rest,
+        _ => component,
+    };
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            f.write_str("::")?;
+            rest = after;
+            continue;
+        }
+
+        let escape = rest
+            .strip_prefix('$')
+            .and_then(|after| Some((after, after.find('$')?)))
+            .and_then(|(after, end)| Some((unescape(&after[..end])?, &after[end + 1..])));
+        match escape {
+            Some((c, after)) => {
+                write!(f, "{c}")?;
+                rest = after;
+            }
+            None => {
+                let c = rest.chars().next().unwrap();
+                write!(f, "{c}")?;
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn unescape(escape: &str) -> Option<char> {
+    Some(match escape {
+        "SP" => '@',
+        "BP" => '*',
+        "RF" => '&',
+        "LT" => '<',
+        "GT" => '>',
+        "LP" => '(',
+        "RP" => ')',
+        "C" => ',',
+        _ => char::from_u32(u32::from_str_radix(escape.strip_prefix('u')?, 16).ok()?)?,
+    })
+}
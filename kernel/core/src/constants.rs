@@ -1,5 +1,16 @@
 pub const KERNEL_VERSION: &str = "v0.2.0 Alpha";
 
-// Start the heap at a this address to make it easier to recognize
-pub const HEAP_START: usize = 0x_C444_4444_0000;
+// Start the heap at a this address to make it easier to recognize, it is 2MiB aligned
+// so the heap can be mapped with huge pages
+pub const HEAP_START: usize = 0x_C444_4440_0000;
 pub const HEAP_DEFAULT_SIZE: usize = 8 * 1024 * 1024; // 8MiB
+// The heap grows on demand up to this size
+pub const HEAP_MAX_SIZE: usize = 1024 * 1024 * 1024; // 1GiB
+
+// Stack the kernel switches to after init, Limine's stack has no guard page
+pub const KERNEL_STACK_SIZE: u64 = 256 * 1024; // 256KiB
+
+// The address space manager hands out ranges between these addresses, for
+// the framebuffer, MMIO, stacks and vmalloc
+pub const VMALLOC_START: usize = 0x_C555_0000_0000;
+pub const VMALLOC_END: usize = 0x_E555_0000_0000;
@@ -0,0 +1,286 @@
+//! CPU exception handlers
+//!
+//! Every exception enters through a small assembly stub, which saves the general
+//! purpose registers so they can be printed along with the exception. Faults that
+//! can't be recovered from print the registers and a backtrace, and then panic
+
+use core::{arch::global_asm, fmt};
+use x86_64::{
+    registers::control::Cr2,
+    structures::idt::{
+        InterruptDescriptorTable, InterruptStackFrameValue, PageFaultErrorCode, SelectorErrorCode,
+    },
+    VirtAddr,
+};
+
+use crate::{backtrace, eprintln, gdt};
+
+/// General purpose registers at the time of the exception, in the order the stub pushes them
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct Registers {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = [
+            ("RAX", self.rax),
+            ("RBX", self.rbx),
+            ("RCX", self.rcx),
+            ("RDX", self.rdx),
+            ("RSI", self.rsi),
+            ("RDI", self.rdi),
+            ("RBP", self.rbp),
+            ("R8 ", self.r8),
+            ("R9 ", self.r9),
+            ("R10", self.r10),
+            ("R11", self.r11),
+            ("R12", self.r12),
+            ("R13", self.r13),
+            ("R14", self.r14),
+            ("R15", self.r15),
+        ];
+
+        for (i, (name, value)) in registers.iter().enumerate() {
+            let separator = if i % 3 == 2 { "\n" } else { "  " };
+            write!(f, "{name}={value:016x}{separator}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Everything the stub leaves on the stack
+#[repr(C)]
+struct ExceptionFrame {
+    registers: Registers,
+    vector: u64,
+    /// Zero for exceptions that don't push an error code
+    error_code: u64,
+    stack_frame: InterruptStackFrameValue,
+}
+
+// The CPU aligns the stack to 16 bytes and pushes 5 values, the error code, vector
+// and 15 registers keep it aligned for the call
+global_asm!(
+    ".global exception_common",
+    "exception_common:",
+    "push rax",
+    "push rbx",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push rbp",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "push r12",
+    "push r13",
+    "push r14",
+    "push r15",
+    "mov rdi, rsp",
+    "cld",
+    "call {dispatch}",
+    "pop r15",
+    "pop r14",
+    "pop r13",
+    "pop r12",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rbp",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rbx",
+    "pop rax",
+    // Vector and error code
+    "add rsp, 16",
+    "iretq",
+    dispatch = sym exception_dispatch,
+);
+
+/// Defines the entry stub for an exception, pushing a zero in place of the error
+/// code for exceptions that don't have one
+macro_rules! stub {
+    ($name:ident, $vector:literal) => {
+        stub!(@define $name, $vector, "push 0\n");
+    };
+    ($name:ident, $vector:literal, error_code) => {
+        stub!(@define $name, $vector, "");
+    };
+    (@define $name:ident, $vector:literal, $push_error_code:literal) => {
+        global_asm!(concat!(
+            ".global ", stringify!($name), "\n",
+            stringify!($name), ":\n",
+            $push_error_code,
+            "push ", stringify!($vector), "\n",
+            "jmp exception_common\n",
+        ));
+        extern "C" {
+            fn $name();
+        }
+    };
+}
+
+stub!(divide_error_stub, 0);
+stub!(debug_stub, 1);
+stub!(nmi_stub, 2);
+stub!(breakpoint_stub, 3);
+stub!(overflow_stub, 4);
+stub!(bound_range_exceeded_stub, 5);
+stub!(invalid_opcode_stub, 6);
+stub!(device_not_available_stub, 7);
+stub!(double_fault_stub, 8, error_code);
+stub!(invalid_tss_stub, 10, error_code);
+stub!(segment_not_present_stub, 11, error_code);
+stub!(stack_segment_fault_stub, 12, error_code);
+stub!(general_protection_fault_stub, 13, error_code);
+stub!(page_fault_stub, 14, error_code);
+stub!(x87_floating_point_stub, 16);
+stub!(alignment_check_stub, 17, error_code);
+stub!(machine_check_stub, 18);
+stub!(simd_floating_point_stub, 19);
+stub!(virtualization_stub, 20);
+stub!(cp_protection_stub, 21, error_code);
+stub!(hv_injection_stub, 28);
+stub!(vmm_communication_stub, 29, error_code);
+stub!(security_stub, 30, error_code);
+
+/// Points every architectural exception of `idt` at its stub
+///
+/// # Safety
+///
+/// Should only be called by interrupt::init, after gdt::init
+pub unsafe fn install(idt: &mut InterruptDescriptorTable) {
+    let addr = |stub: unsafe extern "C" fn()| VirtAddr::new(stub as usize as u64);
+
+    unsafe {
+        idt.divide_error.set_handler_addr(addr(divide_error_stub));
+        idt.debug.set_handler_addr(addr(debug_stub));
+        idt.breakpoint.set_handler_addr(addr(breakpoint_stub));
+        idt.overflow.set_handler_addr(addr(overflow_stub));
+        idt.bound_range_exceeded
+            .set_handler_addr(addr(bound_range_exceeded_stub));
+        idt.invalid_opcode
+            .set_handler_addr(addr(invalid_opcode_stub));
+        idt.device_not_available
+            .set_handler_addr(addr(device_not_available_stub));
+        idt.invalid_tss.set_handler_addr(addr(invalid_tss_stub));
+        idt.segment_not_present
+            .set_handler_addr(addr(segment_not_present_stub));
+        idt.stack_segment_fault
+            .set_handler_addr(addr(stack_segment_fault_stub));
+        idt.general_protection_fault
+            .set_handler_addr(addr(general_protection_fault_stub));
+        idt.page_fault.set_handler_addr(addr(page_fault_stub));
+        idt.x87_floating_point
+            .set_handler_addr(addr(x87_floating_point_stub));
+        idt.alignment_check
+            .set_handler_addr(addr(alignment_check_stub));
+        idt.simd_floating_point
+            .set_handler_addr(addr(simd_floating_point_stub));
+        idt.virtualization
+            .set_handler_addr(addr(virtualization_stub));
+        idt.cp_protection_exception
+            .set_handler_addr(addr(cp_protection_stub));
+        idt.hv_injection_exception
+            .set_handler_addr(addr(hv_injection_stub));
+        idt.vmm_communication_exception
+            .set_handler_addr(addr(vmm_communication_stub));
+        idt.security_exception.set_handler_addr(addr(security_stub));
+
+        // These run on their own stacks from the TSS
+        idt.double_fault
+            .set_handler_addr(addr(double_fault_stub))
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.non_maskable_interrupt
+            .set_handler_addr(addr(nmi_stub))
+            .set_stack_index(gdt::NMI_IST_INDEX);
+        idt.machine_check
+            .set_handler_addr(addr(machine_check_stub))
+            .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+    }
+}
+
+fn name(vector: u64) -> &'static str {
+    match vector {
+        0 => "Divide Error",
+        1 => "Debug",
+        2 => "Non-maskable Interrupt",
+        3 => "Breakpoint",
+        4 => "Overflow",
+        5 => "Bound Range Exceeded",
+        6 => "Invalid Opcode",
+        7 => "Device Not Available",
+        8 => "Double Fault",
+        10 => "Invalid TSS",
+        11 => "Segment Not Present",
+        12 => "Stack Segment Fault",
+        13 => "General Protection Fault",
+        14 => "Page Fault",
+        16 => "x87 Floating Point",
+        17 => "Alignment Check",
+        18 => "Machine Check",
+        19 => "SIMD Floating Point",
+        20 => "Virtualization",
+        21 => "Control Protection",
+        28 => "Hypervisor Injection",
+        29 => "VMM Communication",
+        30 => "Security",
+        _ => "Unknown",
+    }
+}
+
+extern "C" fn exception_dispatch(frame: &mut ExceptionFrame) {
+    let name = name(frame.vector);
+
+    // Nothing went wrong for these, execution continues after the handler
+    if let 1..=3 = frame.vector {
+        eprintln!("CPU Exception: {}\n{:#?}", name, frame.stack_frame);
+        return;
+    }
+
+    eprintln!("CPU Exception: {name}");
+    match frame.vector {
+        10..=13 => eprintln!(
+            "Error Code: {:?}",
+            SelectorErrorCode::new_truncate(frame.error_code)
+        ),
+        14 => {
+            eprintln!("Accessed Address: {:?}", Cr2::read());
+            eprintln!(
+                "Error Code: {:?}",
+                PageFaultErrorCode::from_bits_truncate(frame.error_code)
+            );
+        }
+        17 | 21 | 29 | 30 => eprintln!("Error Code: {:#x}", frame.error_code),
+        _ => {}
+    }
+    eprintln!("{:#?}", frame.stack_frame);
+    eprintln!("Registers:\n{}", frame.registers);
+    backtrace::print(
+        frame.stack_frame.instruction_pointer.as_u64(),
+        frame.registers.rbp,
+    );
+
+    panic!("CPU Exception: {name}");
+}
@@ -0,0 +1,584 @@
+//! Shell commands for working with files
+
+use core::fmt::{self, Write};
+
+use libk::alloc::format;
+use libk::io::{self, Path};
+use libk::string::String;
+
+use super::{vfs, Metadata};
+use crate::shell::commands::{self, Args, Builtin};
+use crate::shell::{Error, Output, Shell};
+
+static COMMANDS: [Builtin; 15] = [
+    Builtin {
+        name: "cat",
+        usage: "[FILE]...",
+        description: "Prints files, or the input if none are given",
+        run: cat,
+    },
+    Builtin {
+        name: "put",
+        usage: "FILE [TEXT]...",
+        description: "Writes the text to a file, replacing what was in it",
+        run: put,
+    },
+    Builtin {
+        name: "mkdir",
+        usage: "DIRECTORY...",
+        description: "Creates directories",
+        run: mkdir,
+    },
+    Builtin {
+        name: "cd",
+        usage: "[DIRECTORY]",
+        description: "Changes the working directory, to / if none is given",
+        run: cd,
+    },
+    Builtin {
+        name: "pwd",
+        usage: "",
+        description: "Prints the working directory",
+        run: pwd,
+    },
+    Builtin {
+        name: "fsdump",
+        usage: "[DIRECTORY]",
+        description: "Prints everything under a directory as a tree",
+        run: fsdump,
+    },
+    Builtin {
+        name: "ls",
+        usage: "[-l] [PATH]...",
+        description: "Lists directories, -l also shows types and sizes",
+        run: ls,
+    },
+    Builtin {
+        name: "rm",
+        usage: "[-r] PATH...",
+        description: "Removes files, and directories with everything in them if -r is given",
+        run: rm,
+    },
+    Builtin {
+        name: "mv",
+        usage: "SOURCE DESTINATION",
+        description: "Moves a file or directory, into DESTINATION if it is a directory",
+        run: mv,
+    },
+    Builtin {
+        name: "cp",
+        usage: "[-r] SOURCE DESTINATION",
+        description: "Copies a file, or a directory if -r is given",
+        run: cp,
+    },
+    Builtin {
+        name: "touch",
+        usage: "FILE...",
+        description: "Creates empty files, or updates the modification time of existing ones",
+        run: touch,
+    },
+    Builtin {
+        name: "stat",
+        usage: "PATH...",
+        description: "Shows the type and size of files",
+        run: stat,
+    },
+    Builtin {
+        name: "hexdump",
+        usage: "[FILE]",
+        description: "Prints a file, or the input, as hex and ASCII",
+        run: hexdump,
+    },
+    Builtin {
+        name: "wc",
+        usage: "[FILE]...",
+        description: "Counts the lines, words and bytes of files, or the input",
+        run: wc,
+    },
+    Builtin {
+        name: "tee",
+        usage: "[-a] FILE...",
+        description: "Writes the input to files and prints it, -a appends to them",
+        run: tee,
+    },
+];
+
+/// How much of a file is read at a time when streaming it
+const CHUNK_SIZE: usize = 4096;
+
+pub fn register() {
+    commands::register_all(&COMMANDS);
+}
+
+/// Prefixes errors about a file with its path
+fn at(path: &str) -> impl FnOnce(libk::io::Error) -> Error + '_ {
+    move |e| Error::Failed(format!("{path}: {e}"))
+}
+
+fn cat(_: &mut Shell, args: &mut Args, input: &[u8], out: &mut Output) -> Result<(), Error> {
+    let paths = args.rest();
+    if paths.is_empty() {
+        out.write_bytes(input);
+    }
+    for path in paths {
+        for_each_chunk(path, |chunk| out.write_bytes(chunk))?;
+    }
+    Ok(())
+}
+
+/// Reads the file at `path` a chunk at a time, without keeping all of it in memory
+fn for_each_chunk(path: &str, mut f: impl FnMut(&[u8])) -> Result<(), Error> {
+    let mut file = vfs::open(path, true).map_err(at(path))?;
+    let mut buf = [0; CHUNK_SIZE];
+    loop {
+        match file.read(&mut buf).map_err(at(path))? {
+            0 => return Ok(()),
+            n => f(&buf[..n]),
+        }
+    }
+}
+
+fn put(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let path = args.next("FILE")?;
+    let text = args.rest().join(" ");
+    vfs::create(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(at(path))
+}
+
+fn mkdir(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let first = args.next("DIRECTORY")?;
+    for path in core::iter::once(first).chain(args.rest()) {
+        vfs::mkdir(path).map_err(at(path))?;
+    }
+    Ok(())
+}
+
+fn cd(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let path = args.next_optional().unwrap_or("/");
+    args.finish()?;
+    vfs::set_cwd(path).map_err(at(path))
+}
+
+fn pwd(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    args.finish()?;
+    writeln!(out, "{}", vfs::cwd())?;
+    Ok(())
+}
+
+fn fsdump(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    let path = args.next_optional().unwrap_or(".");
+    args.finish()?;
+    dump_tree(path, 0, out)
+}
+
+/// Prints every file and directory under `path`
+fn dump_tree(path: &str, depth: usize, out: &mut Output) -> Result<(), Error> {
+    let entries = vfs::read_dir(path).map_err(at(path))?;
+
+    let indent = depth * 2;
+    for entry in entries {
+        if entry.metadata.is_dir() {
+            writeln!(out, "{:indent$}{}/", "", entry.name)?;
+            dump_tree(&format!("{path}/{}", entry.name), depth + 1, out)?;
+        } else {
+            writeln!(
+                out,
+                "{:indent$}{} ({} bytes)",
+                "", entry.name, entry.metadata.len
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn ls(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    let long = args.flag("-l");
+    let mut paths = args.rest();
+    if paths.is_empty() {
+        paths.push(".");
+    }
+
+    let headers = paths.len() > 1;
+    for (i, path) in paths.into_iter().enumerate() {
+        let metadata = vfs::metadata(path).map_err(at(path))?;
+        if !metadata.is_dir() {
+            write_entry(path, &metadata, long, out)?;
+            continue;
+        }
+
+        if headers {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "{path}:")?;
+        }
+        for entry in vfs::read_dir(path).map_err(at(path))? {
+            write_entry(&entry.name, &entry.metadata, long, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// One line of `ls`, directories end in `/`
+fn write_entry(name: &str, metadata: &Metadata, long: bool, out: &mut Output) -> fmt::Result {
+    let suffix = if metadata.is_dir() { "/" } else { "" };
+    if long {
+        let kind = if metadata.is_dir() { 'd' } else { '-' };
+        writeln!(out, "{kind} {:>10} {name}{suffix}", metadata.len)
+    } else {
+        writeln!(out, "{name}{suffix}")
+    }
+}
+
+fn rm(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let recursive = args.flag("-r");
+    let first = args.next("PATH")?;
+    for path in core::iter::once(first).chain(args.rest()) {
+        if vfs::metadata(path).map_err(at(path))?.is_dir() {
+            if !recursive {
+                return Err(at(path)(io::Error::IsDirectory));
+            }
+            remove_tree(path)?;
+        } else {
+            vfs::remove_file(path).map_err(at(path))?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes `path`, and everything in it if it is a directory
+fn remove_tree(path: &str) -> Result<(), Error> {
+    if !vfs::metadata(path).map_err(at(path))?.is_dir() {
+        return vfs::remove_file(path).map_err(at(path));
+    }
+
+    for entry in vfs::read_dir(path).map_err(at(path))? {
+        remove_tree(&format!("{path}/{}", entry.name))?;
+    }
+    vfs::remove_dir(path).map_err(at(path))
+}
+
+fn mv(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let source = args.next("SOURCE")?;
+    let target = args.next("DESTINATION")?;
+    args.finish()?;
+    let destination = destination(source, target)?;
+    // Names that only differ in case are left to the filesystem, which can rename to them
+    if vfs::resolve(source) == vfs::resolve(&destination) {
+        return Err(same_file(source, target));
+    }
+
+    match vfs::rename(source, &destination) {
+        // Moving to another filesystem has to copy everything over
+        Err(io::Error::CrossesDevices) => {
+            copy_tree(source, &destination)?;
+            remove_tree(source)
+        }
+        result => result.map_err(at(source)),
+    }
+}
+
+fn cp(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let recursive = args.flag("-r");
+    let source = args.next("SOURCE")?;
+    let target = args.next("DESTINATION")?;
+    args.finish()?;
+    let destination = destination(source, target)?;
+    // Checked on the final path, by the filesystem's rules, so that neither `cp /a /`
+    // nor `cp /fat/a /fat/A` truncates the source before it is read
+    if vfs::is_same(source, &destination).map_err(at(source))? {
+        return Err(same_file(source, target));
+    }
+
+    if vfs::metadata(source).map_err(at(source))?.is_dir() {
+        if !recursive {
+            return Err(at(source)(io::Error::IsDirectory));
+        }
+        copy_tree(source, &destination)
+    } else {
+        copy_file(source, &destination)
+    }
+}
+
+/// Where `source` ends up when it is moved or copied to `destination`
+///
+/// Like in other shells, a directory as the destination means inside it
+fn destination(source: &str, destination: &str) -> Result<String, Error> {
+    let source_path = vfs::resolve(source);
+    let mut destination_path = vfs::resolve(destination);
+    if matches!(vfs::metadata(destination), Ok(metadata) if metadata.is_dir()) {
+        let (_, name) = source_path
+            .split_last()
+            .ok_or(at(source)(io::Error::InvalidPath))?;
+        destination_path = destination_path.join(name);
+    }
+
+    // The filesystem decides which names are the same, FAT ignores their case
+    let segments = &destination_path.segments;
+    for depth in 1..segments.len() {
+        let parent = format!("{}", Path::from(&segments[..depth]));
+        if vfs::is_same(source, &parent).map_err(at(source))? {
+            return Err(Error::Failed(format!(
+                "{source}: can't be moved or copied into itself"
+            )));
+        }
+    }
+    Ok(format!("{destination_path}"))
+}
+
+fn same_file(source: &str, destination: &str) -> Error {
+    Error::Failed(format!("{source} and {destination} are the same file"))
+}
+
+/// Copies `source` to `destination`, with everything in it if it is a directory
+fn copy_tree(source: &str, destination: &str) -> Result<(), Error> {
+    if !vfs::metadata(source).map_err(at(source))?.is_dir() {
+        return copy_file(source, destination);
+    }
+
+    vfs::mkdir(destination).map_err(at(destination))?;
+    for entry in vfs::read_dir(source).map_err(at(source))? {
+        copy_tree(
+            &format!("{source}/{}", entry.name),
+            &format!("{destination}/{}", entry.name),
+        )?;
+    }
+    Ok(())
+}
+
+fn copy_file(source: &str, destination: &str) -> Result<(), Error> {
+    let mut file = vfs::create(destination).map_err(at(destination))?;
+    let mut result = Ok(());
+    for_each_chunk(source, |chunk| {
+        if result.is_ok() {
+            result = file.write_all(chunk);
+        }
+    })?;
+    result.map_err(at(destination))
+}
+
+fn touch(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let first = args.next("FILE")?;
+    for path in core::iter::once(first).chain(args.rest()) {
+        match vfs::touch(path) {
+            Ok(()) => {}
+            Err(io::Error::NotFound) => {
+                vfs::create(path).map_err(at(path))?;
+            }
+            Err(e) => return Err(at(path)(e)),
+        }
+    }
+    Ok(())
+}
+
+fn stat(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    let first = args.next("PATH")?;
+    for path in core::iter::once(first).chain(args.rest()) {
+        let metadata = vfs::metadata(path).map_err(at(path))?;
+        let kind = if metadata.is_dir() {
+            "directory"
+        } else {
+            "file"
+        };
+        writeln!(out, "  Path: {}", vfs::resolve(path))?;
+        writeln!(out, "  Type: {kind}")?;
+        writeln!(out, "  Size: {} bytes", metadata.len)?;
+    }
+    Ok(())
+}
+
+fn hexdump(_: &mut Shell, args: &mut Args, input: &[u8], out: &mut Output) -> Result<(), Error> {
+    let path = args.next_optional();
+    args.finish()?;
+    let Some(path) = path else {
+        let mut dump = HexDump::new(input.len() as u64, out)?;
+        dump.add(input, out)?;
+        return Ok(dump.finish(out)?);
+    };
+
+    let len = vfs::metadata(path).map_err(at(path))?.len;
+    let mut dump = HexDump::new(len, out)?;
+    let mut result = Ok(());
+    for_each_chunk(path, |chunk| {
+        if result.is_ok() {
+            result = dump.add(chunk, out);
+        }
+    })?;
+    result?;
+    dump.finish(out)?;
+    Ok(())
+}
+
+/// Bytes in each row of a hex dump
+const HEX_WIDTH: usize = 16;
+/// Bytes in each group of a row
+const HEX_GROUP: usize = 4;
+
+/// Prints bytes the way pretty-hex does by default, 16 per row in groups of 4
+///
+/// Bytes can be added a chunk at a time, a partial row is kept until the next one
+struct HexDump {
+    address_width: usize,
+    /// Address of the first byte in `row`
+    address: u64,
+    row: [u8; HEX_WIDTH],
+    filled: usize,
+}
+
+impl HexDump {
+    /// Starts a dump of `len` bytes by printing its title
+    fn new(len: u64, out: &mut Output) -> Result<Self, fmt::Error> {
+        writeln!(out, "Length: {len} (0x{len:x}) bytes")?;
+
+        let max_address = if len <= HEX_WIDTH as u64 {
+            len
+        } else {
+            len - HEX_WIDTH as u64
+        };
+        let address_width = match max_address {
+            0..=0xffff => 4,
+            0x1_0000..=0xff_ffff => 6,
+            0x100_0000..=0xffff_ffff => 8,
+            _ => 16,
+        };
+
+        Ok(Self {
+            address_width,
+            address: 0,
+            row: [0; HEX_WIDTH],
+            filled: 0,
+        })
+    }
+
+    fn add(&mut self, mut bytes: &[u8], out: &mut Output) -> fmt::Result {
+        while !bytes.is_empty() {
+            let n = bytes.len().min(HEX_WIDTH - self.filled);
+            self.row[self.filled..self.filled + n].copy_from_slice(&bytes[..n]);
+            self.filled += n;
+            bytes = &bytes[n..];
+            if self.filled == HEX_WIDTH {
+                self.write_row(out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints the last row, if it wasn't full
+    fn finish(mut self, out: &mut Output) -> fmt::Result {
+        if self.filled > 0 {
+            self.write_row(out)?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, out: &mut Output) -> fmt::Result {
+        let row = &self.row[..self.filled];
+        write!(
+            out,
+            "{:0width$x}:   ",
+            self.address,
+            width = self.address_width
+        )?;
+        for i in 0..HEX_WIDTH {
+            let delimiter = match i {
+                0 => "",
+                i if i % HEX_GROUP == 0 => "  ",
+                _ => " ",
+            };
+            match row.get(i) {
+                Some(byte) => write!(out, "{delimiter}{byte:02x}")?,
+                None => write!(out, "{delimiter}  ")?,
+            }
+        }
+
+        out.write_str("   ")?;
+        for &byte in row {
+            let printable = byte.is_ascii() && !byte.is_ascii_control();
+            out.write_char(if printable { byte as char } else { '.' })?;
+        }
+        writeln!(out)?;
+
+        self.address += self.filled as u64;
+        self.filled = 0;
+        Ok(())
+    }
+}
+
+/// Line, word and byte counts, kept across chunks
+#[derive(Default)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+    in_word: bool,
+}
+
+impl Counts {
+    fn add(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.lines += 1;
+            }
+            let in_word = !byte.is_ascii_whitespace();
+            if in_word && !self.in_word {
+                self.words += 1;
+            }
+            self.in_word = in_word;
+        }
+        self.bytes += bytes.len();
+    }
+
+    fn write(&self, name: &str, out: &mut Output) -> fmt::Result {
+        writeln!(
+            out,
+            "{:>7} {:>7} {:>7} {name}",
+            self.lines, self.words, self.bytes
+        )
+    }
+}
+
+fn wc(_: &mut Shell, args: &mut Args, input: &[u8], out: &mut Output) -> Result<(), Error> {
+    let paths = args.rest();
+    if paths.is_empty() {
+        let mut counts = Counts::default();
+        counts.add(input);
+        writeln!(
+            out,
+            "{:>7} {:>7} {:>7}",
+            counts.lines, counts.words, counts.bytes
+        )?;
+        return Ok(());
+    }
+
+    let mut total = Counts::default();
+    for path in &paths {
+        let mut counts = Counts::default();
+        for_each_chunk(path, |chunk| counts.add(chunk))?;
+        counts.write(path, out)?;
+
+        total.lines += counts.lines;
+        total.words += counts.words;
+        total.bytes += counts.bytes;
+    }
+    if paths.len() > 1 {
+        total.write("total", out)?;
+    }
+    Ok(())
+}
+
+fn tee(_: &mut Shell, args: &mut Args, input: &[u8], out: &mut Output) -> Result<(), Error> {
+    let append = args.flag("-a");
+    let first = args.next("FILE")?;
+    for path in core::iter::once(first).chain(args.rest()) {
+        let file = if append {
+            vfs::append(path)
+        } else {
+            vfs::create(path)
+        };
+        file.and_then(|mut file| file.write_all(input))
+            .map_err(at(path))?;
+    }
+    out.write_bytes(input);
+    Ok(())
+}
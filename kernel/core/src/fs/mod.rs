@@ -1,20 +1,15 @@
-pub mod ramfs;
+pub mod commands;
+pub mod vfs;
 
-use libk::io::{self, Path, Read, Seek, Write};
+pub use libk::fs::{DirEntry, Directory, File, FileType, Metadata};
+pub use ramfs::RamFsDirectory;
 
-pub trait Directory {
-    fn mkdir<T>(&self, path: T) -> Result<(), io::Error>
-    where
-        T: Into<Path>;
+use libk::alloc::sync::Arc;
 
-    fn open<T>(&self, path: T, read_only: bool) -> Result<impl File, io::Error>
-    where
-        T: Into<Path>;
-
-    fn create<T>(&mut self, path: T) -> Result<impl File, io::Error>
-    where
-        T: Into<Path>;
+/// Mounts an empty ramfs as the root filesystem, and registers the file commands
+///
+/// Called by kernel::init by default
+pub fn init() {
+    vfs::mount("/", Arc::new(RamFsDirectory::new())).expect("Unable to mount root filesystem");
+    commands::register();
 }
-
-pub trait File: Read + Write + Seek {}
-impl<T> File for T where T: Read + Write + Seek {}
@@ -0,0 +1,239 @@
+//! Kernel wide virtual filesystem
+//!
+//! Filesystems are mounted at absolute paths, and every path is handled by the
+//! filesystem with the longest mount point that contains it. Relative paths
+//! start from the current working directory
+
+use super::{DirEntry, Directory, File, Metadata};
+use libk::{
+    alloc::sync::Arc,
+    boxed::Box,
+    io::{self, Path, SeekFrom},
+    vec::Vec,
+    Mutex,
+};
+
+struct Mount {
+    path: Path,
+    fs: Arc<dyn Directory>,
+}
+
+static MOUNTS: Mutex<Vec<Mount>> = Mutex::new(Vec::new());
+static CWD: Mutex<Path> = Mutex::new(Path {
+    segments: Vec::new(),
+});
+
+/// Mounts `fs` at `path`, hiding anything that was at that path before
+pub fn mount(path: &str, fs: Arc<dyn Directory>) -> Result<(), io::Error> {
+    let path = resolve(path);
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|mount| mount.path == path) {
+        return Err(io::Error::AlreadyExists);
+    }
+
+    mounts.push(Mount { path, fs });
+    Ok(())
+}
+
+/// Removes the filesystem mounted at `path`, and returns it
+pub fn unmount(path: &str) -> Result<Arc<dyn Directory>, io::Error> {
+    let path = resolve(path);
+    let mut mounts = MOUNTS.lock();
+    let index = mounts
+        .iter()
+        .position(|mount| mount.path == path)
+        .ok_or(io::Error::NotFound)?;
+
+    Ok(mounts.remove(index).fs)
+}
+
+/// Absolute paths of all mount points, in the order they were mounted
+pub fn mount_points() -> Vec<Path> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| mount.path.clone())
+        .collect()
+}
+
+/// Turns `path` into a normalized absolute path
+pub fn resolve(path: &str) -> Path {
+    CWD.lock().join(path)
+}
+
+/// Finds the filesystem that holds the absolute `path`, and the path relative to its root
+fn lookup(path: &Path) -> Result<(Arc<dyn Directory>, Path), io::Error> {
+    let mounts = MOUNTS.lock();
+
+    mounts
+        .iter()
+        .filter_map(|mount| Some((mount, path.strip_prefix(&mount.path)?)))
+        .max_by_key(|(mount, _)| mount.path.segments.len())
+        .map(|(mount, relative)| (mount.fs.clone(), relative))
+        .ok_or(io::Error::NotFound)
+}
+
+pub fn open(path: &str, read_only: bool) -> Result<Box<dyn File>, io::Error> {
+    let (fs, path) = lookup(&resolve(path))?;
+    fs.open(&path, read_only)
+}
+
+/// Opens the file for writing, creating it if it doesn't exist and truncating it if it does
+pub fn create(path: &str) -> Result<Box<dyn File>, io::Error> {
+    let (fs, path) = lookup(&resolve(path))?;
+    fs.create(&path)
+}
+
+/// Opens the file for writing at its end, creating it if it doesn't exist
+pub fn append(path: &str) -> Result<Box<dyn File>, io::Error> {
+    let mut file = match open(path, false) {
+        Err(io::Error::NotFound) => return create(path),
+        file => file?,
+    };
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+/// Reads the whole file at `path`
+pub fn read(path: &str) -> Result<Vec<u8>, io::Error> {
+    let mut contents = Vec::new();
+    open(path, true)?.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+pub fn mkdir(path: &str) -> Result<(), io::Error> {
+    let (fs, path) = lookup(&resolve(path))?;
+    fs.mkdir(&path)
+}
+
+/// Lists the directory at `path`, including the mount points directly inside it
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, io::Error> {
+    let path = resolve(path);
+    let (fs, relative) = lookup(&path)?;
+    let mut entries = match fs.read_dir(&relative) {
+        Ok(entries) => entries,
+        // Directories that only exist to lead to a mount point are empty
+        Err(io::Error::NotFound) if is_mount_path(&path) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+
+    for mount_point in mount_points() {
+        if let Some((parent, name)) = mount_point.split_last() {
+            if parent == path {
+                entries.retain(|entry| entry.name != name);
+                entries.push(DirEntry {
+                    name: name.into(),
+                    metadata: Metadata::directory(),
+                });
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+pub fn metadata(path: &str) -> Result<Metadata, io::Error> {
+    let path = resolve(path);
+    if is_mount_path(&path) {
+        return Ok(Metadata::directory());
+    }
+
+    let (fs, path) = lookup(&path)?;
+    fs.metadata(&path)
+}
+
+/// Checks if both paths name the same file or directory, by the rules of the
+/// filesystem they are on
+pub fn is_same(a: &str, b: &str) -> Result<bool, io::Error> {
+    let (a, b) = (resolve(a), resolve(b));
+    if is_mount_path(&a) || is_mount_path(&b) {
+        return Ok(a == b);
+    }
+
+    let (a_fs, a) = lookup(&a)?;
+    let (b_fs, b) = lookup(&b)?;
+    if !Arc::ptr_eq(&a_fs, &b_fs) {
+        return Ok(false);
+    }
+    a_fs.is_same(&a, &b)
+}
+
+/// Sets the modification time of `path` to now, if its filesystem keeps one
+pub fn touch(path: &str) -> Result<(), io::Error> {
+    let path = resolve(path);
+    if is_mount_path(&path) {
+        return Ok(());
+    }
+
+    let (fs, path) = lookup(&path)?;
+    fs.touch(&path)
+}
+
+pub fn remove_file(path: &str) -> Result<(), io::Error> {
+    let (fs, path) = lookup(&resolve(path))?;
+    fs.remove_file(&path)
+}
+
+pub fn remove_dir(path: &str) -> Result<(), io::Error> {
+    let path = resolve(path);
+    if is_mount_path(&path) {
+        return Err(io::Error::PermissionsError);
+    }
+
+    let (fs, path) = lookup(&path)?;
+    fs.remove_dir(&path)
+}
+
+/// Moves a file or directory, both paths have to be on the same filesystem
+pub fn rename(from: &str, to: &str) -> Result<(), io::Error> {
+    let from = resolve(from);
+    if is_mount_path(&from) {
+        return Err(io::Error::PermissionsError);
+    }
+
+    let (from_fs, from) = lookup(&from)?;
+    let (to_fs, to) = lookup(&resolve(to))?;
+    if !Arc::ptr_eq(&from_fs, &to_fs) {
+        return Err(io::Error::CrossesDevices);
+    }
+    from_fs.rename(&from, &to)
+}
+
+/// Checks if `path` is a mount point, or a directory leading to one
+fn is_mount_path(path: &Path) -> bool {
+    MOUNTS
+        .lock()
+        .iter()
+        .any(|mount| mount.path.strip_prefix(path).is_some())
+}
+
+/// Checks that `path` exists and is a directory
+pub fn check_dir(path: &str) -> Result<(), io::Error> {
+    check_dir_inner(&resolve(path))
+}
+
+fn check_dir_inner(path: &Path) -> Result<(), io::Error> {
+    if is_mount_path(path) {
+        return Ok(());
+    }
+
+    let (fs, path) = lookup(path)?;
+    match fs.metadata(&path)? {
+        metadata if metadata.is_dir() => Ok(()),
+        _ => Err(io::Error::NotADirectory),
+    }
+}
+
+/// The current working directory
+pub fn cwd() -> Path {
+    CWD.lock().clone()
+}
+
+/// Changes the current working directory, `path` must be a directory
+pub fn set_cwd(path: &str) -> Result<(), io::Error> {
+    let path = resolve(path);
+    check_dir_inner(&path)?;
+    *CWD.lock() = path;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Global descriptor table and task state segment
+//!
+//! Limine's GDT has no TSS, which is needed for the interrupt stack table. The
+//! double fault, NMI and machine check handlers get stacks of their own, so they
+//! still run when the kernel stack has overflowed
+
+use libk::Once;
+use x86_64::{
+    instructions::{
+        segmentation::{Segment, CS, DS, ES, SS},
+        tables::load_tss,
+    },
+    structures::{
+        gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
+        tss::TaskStateSegment,
+    },
+};
+
+use crate::memory;
+
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+const IST_STACK_SIZE: u64 = 5 * 4096;
+
+static TSS: Once<TaskStateSegment> = Once::new();
+static GDT: Once<(GlobalDescriptorTable, Selectors)> = Once::new();
+
+struct Selectors {
+    code: SegmentSelector,
+    data: SegmentSelector,
+    tss: SegmentSelector,
+}
+
+/// Loads the GDT and TSS, and allocates the interrupt stacks
+///
+/// # Safety
+///
+/// Should only be called once, after `memory::init`.
+/// Is called by kernel::init during startup
+pub unsafe fn init() {
+    let tss = TSS.call_once(|| {
+        let mut page_allocator = memory::PAGE_ALLOCATOR.lock();
+        let page_allocator = page_allocator.as_mut().expect("paging is initialized");
+
+        let mut tss = TaskStateSegment::new();
+        for index in [
+            DOUBLE_FAULT_IST_INDEX,
+            NMI_IST_INDEX,
+            MACHINE_CHECK_IST_INDEX,
+        ] {
+            tss.interrupt_stack_table[index as usize] = page_allocator
+                .allocate_stack(IST_STACK_SIZE)
+                .expect("Unable to allocate interrupt stack");
+        }
+        tss
+    });
+
+    let (gdt, selectors) = GDT.call_once(|| {
+        let mut gdt = GlobalDescriptorTable::new();
+        let code = gdt.add_entry(Descriptor::kernel_code_segment());
+        let data = gdt.add_entry(Descriptor::kernel_data_segment());
+        let tss = gdt.add_entry(Descriptor::tss_segment(tss));
+        (gdt, Selectors { code, data, tss })
+    });
+
+    gdt.load();
+    unsafe {
+        // Limine's selectors don't mean anything in our GDT
+        CS::set_reg(selectors.code);
+        SS::set_reg(selectors.data);
+        DS::set_reg(selectors.data);
+        ES::set_reg(selectors.data);
+        load_tss(selectors.tss);
+    }
+}
@@ -0,0 +1,129 @@
+//! Local APIC
+//!
+//! Uses x2APIC mode when the CPU supports it, where the registers are MSRs, and the
+//! memory mapped xAPIC registers otherwise
+
+use core::arch::x86_64::__cpuid;
+use libk::Once;
+use x86_64::{
+    registers::model_specific::Msr, structures::paging::PageTableFlags, PhysAddr, VirtAddr,
+};
+
+use crate::memory;
+
+/// Vector the local APIC uses for spurious interrupts, which don't need an EOI
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+/// First MSR of the x2APIC registers, each xAPIC register is 16 bytes apart
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+const REG_ID: u32 = 0x20;
+const REG_TASK_PRIORITY: u32 = 0x80;
+const REG_EOI: u32 = 0xB0;
+const REG_SPURIOUS: u32 = 0xF0;
+const SPURIOUS_APIC_ENABLE: u32 = 1 << 8;
+
+enum Mode {
+    X2Apic,
+    XApic(VirtAddr),
+}
+
+static MODE: Once<Mode> = Once::new();
+
+/// Enables the local APIC, with the xAPIC registers at `base` if x2APIC isn't
+/// supported. Returns false if the CPU has no APIC or the registers can't be mapped
+///
+/// # Safety
+///
+/// Should only be called once, by interrupt::init
+pub unsafe fn init(base: PhysAddr) -> bool {
+    #[allow(unused_unsafe)]
+    let features = unsafe { __cpuid(1) };
+    let has_apic = features.edx & (1 << 9) != 0;
+    let has_x2apic = features.ecx & (1 << 21) != 0;
+    if !has_apic {
+        return false;
+    }
+
+    let mut apic_base = Msr::new(IA32_APIC_BASE);
+    let mode = if has_x2apic {
+        // xAPIC mode has to be enabled before x2APIC mode
+        unsafe {
+            let value = apic_base.read() | APIC_BASE_ENABLE;
+            apic_base.write(value);
+            apic_base.write(value | APIC_BASE_X2APIC);
+        }
+        Mode::X2Apic
+    } else {
+        let registers = memory::PAGE_ALLOCATOR
+            .lock()
+            .as_mut()
+            .and_then(|page_allocator| {
+                let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+                page_allocator.map_physical(base, 0x400, flags).ok()
+            });
+        let Some(registers) = registers else {
+            return false;
+        };
+
+        unsafe {
+            let value = apic_base.read() | APIC_BASE_ENABLE;
+            apic_base.write(value);
+        }
+        Mode::XApic(registers)
+    };
+    MODE.call_once(|| mode);
+
+    unsafe {
+        write(REG_TASK_PRIORITY, 0);
+        write(REG_SPURIOUS, SPURIOUS_APIC_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+    true
+}
+
+/// Whether interrupts are delivered through the local APIC instead of the PIC
+pub fn is_enabled() -> bool {
+    MODE.get().is_some()
+}
+
+/// ID of the current CPU's local APIC, used as the destination of I/O APIC entries
+pub fn id() -> u32 {
+    match MODE.get() {
+        Some(Mode::X2Apic) => unsafe { read(REG_ID) },
+        Some(Mode::XApic(_)) => unsafe { read(REG_ID) >> 24 },
+        None => 0,
+    }
+}
+
+/// Signals the end of the interrupt that is being handled
+pub fn eoi() {
+    unsafe { write(REG_EOI, 0) }
+}
+
+unsafe fn read(register: u32) -> u32 {
+    match MODE.get() {
+        Some(Mode::X2Apic) => unsafe { Msr::new(X2APIC_MSR_BASE + register / 16).read() as u32 },
+        Some(Mode::XApic(base)) => unsafe {
+            (*base + register as u64).as_ptr::<u32>().read_volatile()
+        },
+        None => 0,
+    }
+}
+
+unsafe fn write(register: u32, value: u32) {
+    match MODE.get() {
+        Some(Mode::X2Apic) => unsafe {
+            Msr::new(X2APIC_MSR_BASE + register / 16).write(value as u64)
+        },
+        Some(Mode::XApic(base)) => unsafe {
+            (*base + register as u64)
+                .as_mut_ptr::<u32>()
+                .write_volatile(value)
+        },
+        None => {}
+    }
+}
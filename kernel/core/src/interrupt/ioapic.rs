@@ -0,0 +1,103 @@
+//! I/O APIC
+//!
+//! Routes external interrupts to the local APIC. Each input, a global system interrupt,
+//! has a redirection entry with the vector and CPU it is delivered to
+
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+use crate::{
+    acpi::IoApicEntry,
+    memory::{address_space::VmError, PageAllocator},
+};
+
+const IOREGSEL: u64 = 0x00;
+const IOWIN: u64 = 0x10;
+
+const REG_VERSION: u32 = 0x01;
+const REG_REDIRECTION_TABLE: u32 = 0x10;
+
+const ACTIVE_LOW: u64 = 1 << 13;
+const LEVEL_TRIGGERED: u64 = 1 << 15;
+const MASKED: u64 = 1 << 16;
+
+pub struct IoApic {
+    registers: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    /// Maps the registers of the I/O APIC described by `entry`, and masks all of its inputs
+    pub fn new(entry: &IoApicEntry, page_allocator: &mut PageAllocator) -> Result<Self, VmError> {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        let registers = page_allocator.map_physical(entry.address, 0x20, flags)?;
+
+        let mut io_apic = IoApic {
+            registers,
+            gsi_base: entry.gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(REG_VERSION) >> 16) & 0xFF) + 1;
+        for gsi in io_apic.gsis() {
+            io_apic.mask(gsi);
+        }
+
+        Ok(io_apic)
+    }
+
+    /// Global system interrupts connected to this I/O APIC
+    pub fn gsis(&self) -> core::ops::Range<u32> {
+        self.gsi_base..self.gsi_base + self.entries
+    }
+
+    /// Delivers `gsi` to the local APIC with ID `destination` as `vector`, and unmasks it
+    pub fn route(
+        &mut self,
+        gsi: u32,
+        vector: u8,
+        destination: u32,
+        active_low: bool,
+        level_triggered: bool,
+    ) {
+        let mut entry = vector as u64 | (destination as u64 & 0xFF) << 56;
+        if active_low {
+            entry |= ACTIVE_LOW;
+        }
+        if level_triggered {
+            entry |= LEVEL_TRIGGERED;
+        }
+        self.write_entry(gsi, entry);
+    }
+
+    pub fn mask(&mut self, gsi: u32) {
+        self.write_entry(gsi, MASKED);
+    }
+
+    fn write_entry(&mut self, gsi: u32, entry: u64) {
+        let register = REG_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        // Mask the entry while it is half written
+        self.write(register, MASKED as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+
+    fn read(&self, register: u32) -> u32 {
+        unsafe {
+            (self.registers + IOREGSEL)
+                .as_mut_ptr::<u32>()
+                .write_volatile(register);
+            (self.registers + IOWIN).as_ptr::<u32>().read_volatile()
+        }
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        unsafe {
+            (self.registers + IOREGSEL)
+                .as_mut_ptr::<u32>()
+                .write_volatile(register);
+            (self.registers + IOWIN)
+                .as_mut_ptr::<u32>()
+                .write_volatile(value);
+        }
+    }
+}
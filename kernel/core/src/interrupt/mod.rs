@@ -0,0 +1,397 @@
+//! Interrupt handling
+//!
+//! External interrupts are delivered through the local and I/O APICs when the
+//! ACPI MADT describes them, and through the legacy 8259 PICs otherwise. Every
+//! vector above the exceptions enters through a common stub, which counts the
+//! interrupt, calls the handlers registered for it with `register_irq` or
+//! `claim_vector`, and signals the end of the interrupt
+
+pub mod apic;
+pub mod ioapic;
+
+use crate::shell::commands::{self, Args, Builtin};
+use crate::shell::{Error, Output, Shell};
+use crate::*;
+use alloc::format;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::fmt::Write;
+use ioapic::IoApic;
+use libk::ptr::addr_of_mut;
+use libk::sync::atomic::{AtomicU64, Ordering};
+use libk::Mutex;
+use pic8259::ChainedPics;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::VirtAddr;
+
+pub const PIC_1_OFFSET: u8 = 32;
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+/// Legacy IRQ lines are always delivered at `PIC_1_OFFSET + line`, vectors from here
+/// on are handed out by `claim_vector`
+pub const FIRST_DYNAMIC_VECTOR: u8 = PIC_2_OFFSET + 8;
+
+/// Number of legacy IRQ lines
+const IRQ_LINES: u8 = 16;
+/// Line the slave PIC is connected to on the master
+const CASCADE_LINE: u8 = 2;
+
+pub static PICS: Mutex<ChainedPics> =
+    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
+
+/// Number of handlers that can share one IRQ line
+const MAX_SHARED_HANDLERS: usize = 4;
+
+/// Called with the vector of the interrupt that is being handled
+pub type Handler = fn(u8);
+
+type Chain = [Option<Handler>; MAX_SHARED_HANDLERS];
+
+/// Handlers of vectors `PIC_1_OFFSET..=255`, every handler of a vector is called in turn
+///
+/// Locked by interrupt handlers, so it must only be locked with interrupts disabled
+static HANDLERS: Mutex<[Chain; 224]> = Mutex::new([[None; MAX_SHARED_HANDLERS]; 224]);
+
+/// Number of times each vector has fired
+static COUNTS: [AtomicU64; 224] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+    [ZERO; 224]
+};
+
+/// I/O APICs found in the MADT, empty when using the PIC
+static IO_APICS: Mutex<Vec<IoApic>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// Not a legacy IRQ line
+    InvalidLine,
+    /// The line already has `MAX_SHARED_HANDLERS` handlers
+    TooManyHandlers,
+    /// No I/O APIC handles the global system interrupt the line is connected to
+    NoRoute,
+    /// The handler was already unregistered
+    NotRegistered,
+}
+
+/// Identifies a handler added by `register_irq`, to remove it with `unregister_irq`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqHandle {
+    line: u8,
+    slot: u8,
+}
+
+impl IrqHandle {
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+
+    /// Vector the line is delivered as
+    pub fn vector(&self) -> u8 {
+        PIC_1_OFFSET + self.line
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VectorInfo {
+    pub vector: u8,
+    /// Legacy IRQ line delivered at this vector
+    pub line: Option<u8>,
+    pub handlers: usize,
+    pub count: u64,
+}
+
+// Stubs for vectors 32 to 255, each 16 bytes apart, push their vector and jump to
+// the common entry. They are in AT&T syntax, which handles the counter as an
+// immediate instead of a memory operand
+global_asm!(
+    ".global irq_stubs",
+    ".balign 16",
+    "irq_stubs:",
+    ".set irq_vector, 32",
+    ".rept 224",
+    ".balign 16",
+    "push $irq_vector",
+    "jmp irq_common",
+    ".set irq_vector, irq_vector + 1",
+    ".endr",
+    options(att_syntax)
+);
+
+// Only the registers the called function may clobber are saved. The CPU aligns
+// the stack to 16 bytes and pushes 5 values, with the vector and 9 registers
+// it needs 8 more bytes to be aligned for the call
+global_asm!(
+    ".global irq_common",
+    "irq_common:",
+    "push rax",
+    "push rcx",
+    "push rdx",
+    "push rsi",
+    "push rdi",
+    "push r8",
+    "push r9",
+    "push r10",
+    "push r11",
+    "mov rdi, [rsp + 72]",
+    "sub rsp, 8",
+    "cld",
+    "call {dispatch}",
+    "add rsp, 8",
+    "pop r11",
+    "pop r10",
+    "pop r9",
+    "pop r8",
+    "pop rdi",
+    "pop rsi",
+    "pop rdx",
+    "pop rcx",
+    "pop rax",
+    // Vector
+    "add rsp, 8",
+    "iretq",
+    dispatch = sym irq_dispatch,
+);
+
+extern "C" {
+    fn irq_stubs();
+}
+
+/// # Safety
+///
+/// Should only be called on one thread, once
+/// Is called by main during startup, after acpi::init
+pub unsafe fn init() {
+    let idt = unsafe { &mut *addr_of_mut!(IDT) };
+
+    unsafe { exception::install(idt) };
+
+    let stubs = irq_stubs as unsafe extern "C" fn() as usize as u64;
+    for vector in PIC_1_OFFSET as usize..=255 {
+        let stub = stubs + (vector - PIC_1_OFFSET as usize) as u64 * 16;
+        unsafe {
+            idt[vector].set_handler_addr(VirtAddr::new(stub));
+        }
+    }
+    idt.load();
+
+    // The PICs are remapped even when using the APIC, so their spurious interrupts
+    // don't look like exceptions
+    let mut pics = PICS.lock();
+    unsafe {
+        pics.initialize();
+        pics.write_masks(0xFF, 0xFF);
+    }
+    drop(pics);
+
+    if let Some(madt) = acpi::madt() {
+        if !madt.io_apics.is_empty() && unsafe { apic::init(madt.local_apic_address) } {
+            let mut page_allocator = memory::PAGE_ALLOCATOR.lock();
+            let page_allocator = page_allocator.as_mut().expect("paging is initialized");
+            let mut io_apics = IO_APICS.lock();
+            for entry in &madt.io_apics {
+                match IoApic::new(entry, page_allocator) {
+                    Ok(io_apic) => io_apics.push(io_apic),
+                    Err(err) => eprintln!("Unable to map I/O APIC {}: {err:?}", entry.id),
+                }
+            }
+        }
+    }
+
+    commands::register(&IRQS_COMMAND).expect("Command names are unique");
+
+    x86_64::instructions::interrupts::enable();
+}
+
+/// Adds `handler` to the handlers of legacy IRQ `line`, unmasking the line if it is
+/// the first one. Handlers of a shared line are called in the order they were added
+pub fn register_irq(line: u8, handler: Handler) -> Result<IrqHandle, IrqError> {
+    if line >= IRQ_LINES {
+        return Err(IrqError::InvalidLine);
+    }
+
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let chain = &mut handlers[line as usize];
+        let slot = chain
+            .iter()
+            .position(Option::is_none)
+            .ok_or(IrqError::TooManyHandlers)?;
+
+        if chain.iter().all(Option::is_none) {
+            set_irq_masked(line, false)?;
+        }
+        chain[slot] = Some(handler);
+        Ok(IrqHandle {
+            line,
+            slot: slot as u8,
+        })
+    })
+}
+
+/// Removes a handler added by `register_irq`, masking the line if it was the last one
+pub fn unregister_irq(handle: IrqHandle) -> Result<(), IrqError> {
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let chain = &mut handlers[handle.line as usize];
+        chain[handle.slot as usize]
+            .take()
+            .ok_or(IrqError::NotRegistered)?;
+
+        if chain.iter().all(Option::is_none) {
+            set_irq_masked(handle.line, true)?;
+        }
+        Ok(())
+    })
+}
+
+/// Finds a free vector that isn't connected to an IRQ line, for interrupts raised
+/// by software or by devices that are programmed with a vector
+pub fn claim_vector(handler: Handler) -> Option<u8> {
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let first = (FIRST_DYNAMIC_VECTOR - PIC_1_OFFSET) as usize;
+        let last = (apic::SPURIOUS_VECTOR - PIC_1_OFFSET) as usize;
+
+        let index = (first..last).find(|&index| handlers[index].iter().all(Option::is_none))?;
+        handlers[index][0] = Some(handler);
+        Some(index as u8 + PIC_1_OFFSET)
+    })
+}
+
+/// Removes the handler of a vector returned by `claim_vector`
+pub fn release_vector(vector: u8) {
+    if vector >= FIRST_DYNAMIC_VECTOR {
+        without_interrupts(|| {
+            HANDLERS.lock()[(vector - PIC_1_OFFSET) as usize] = [None; MAX_SHARED_HANDLERS]
+        });
+    }
+}
+
+/// Number of times `vector` has fired
+pub fn count(vector: u8) -> u64 {
+    vector
+        .checked_sub(PIC_1_OFFSET)
+        .map_or(0, |index| COUNTS[index as usize].load(Ordering::Relaxed))
+}
+
+/// Vectors that have handlers or have fired
+pub fn vectors() -> Vec<VectorInfo> {
+    let handlers = without_interrupts(|| *HANDLERS.lock());
+
+    handlers
+        .iter()
+        .zip(&COUNTS)
+        .enumerate()
+        .map(|(index, (chain, count))| VectorInfo {
+            vector: index as u8 + PIC_1_OFFSET,
+            line: (index < IRQ_LINES as usize).then_some(index as u8),
+            handlers: chain.iter().flatten().count(),
+            count: count.load(Ordering::Relaxed),
+        })
+        .filter(|info| info.handlers > 0 || info.count > 0)
+        .collect()
+}
+
+static IRQS_COMMAND: Builtin = Builtin {
+    name: "irqs",
+    usage: "",
+    description: "Lists the interrupt vectors in use and how often they fired",
+    run: irqs_command,
+};
+
+fn irqs_command(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    args.finish()?;
+    let controller = if apic::is_enabled() { "APIC" } else { "PIC" };
+    writeln!(out, "Controller: {controller}")?;
+    writeln!(out, "Vector  IRQ  Handlers  Count")?;
+    for info in vectors() {
+        let line = info.line.map(|line| format!("{line}")).unwrap_or_default();
+        writeln!(
+            out,
+            "{:>6}  {:>3}  {:>8}  {}",
+            info.vector, line, info.handlers, info.count
+        )?;
+    }
+    Ok(())
+}
+
+/// Signals the interrupt controller that the interrupt at `vector` has been handled
+fn end_of_interrupt(vector: u8) {
+    if apic::is_enabled() {
+        apic::eoi();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(vector) };
+    }
+}
+
+/// Global system interrupt and polarity and trigger mode of legacy IRQ `line`
+fn isa_irq(line: u8) -> (u32, Option<acpi::InterruptOverride>) {
+    match acpi::madt() {
+        Some(madt) => {
+            let (gsi, interrupt_override) = madt.isa_irq(line);
+            (gsi, interrupt_override.copied())
+        }
+        None => (line as u32, None),
+    }
+}
+
+fn set_irq_masked(line: u8, masked: bool) -> Result<(), IrqError> {
+    if apic::is_enabled() {
+        let (gsi, interrupt_override) = isa_irq(line);
+        let mut io_apics = IO_APICS.lock();
+        let io_apic = io_apics
+            .iter_mut()
+            .find(|io_apic| io_apic.gsis().contains(&gsi))
+            .ok_or(IrqError::NoRoute)?;
+
+        if masked {
+            io_apic.mask(gsi);
+        } else {
+            let (active_low, level_triggered) = interrupt_override
+                .map(|o| (o.active_low, o.level_triggered))
+                .unwrap_or_default();
+            let vector = PIC_1_OFFSET + line;
+            io_apic.route(gsi, vector, apic::id(), active_low, level_triggered);
+        }
+    } else {
+        set_pic_masked(line, masked);
+    }
+    Ok(())
+}
+
+fn set_pic_masked(line: u8, masked: bool) {
+    let mut pics = PICS.lock();
+    let mut masks = unsafe { pics.read_masks() };
+
+    let (pic, bit) = ((line / 8) as usize, line % 8);
+    if masked {
+        masks[pic] |= 1 << bit;
+    } else {
+        masks[pic] &= !(1 << bit);
+        if pic == 1 {
+            masks[0] &= !(1 << CASCADE_LINE);
+        }
+    }
+    unsafe { pics.write_masks(masks[0], masks[1]) };
+}
+
+extern "C" fn irq_dispatch(vector: u64) {
+    let vector = vector as u8;
+    let index = (vector - PIC_1_OFFSET) as usize;
+    COUNTS[index].fetch_add(1, Ordering::Relaxed);
+
+    if vector == apic::SPURIOUS_VECTOR && apic::is_enabled() {
+        return;
+    }
+
+    // Copied out so handlers can register and unregister handlers themselves
+    let chain = HANDLERS.lock()[index];
+    for handler in chain.into_iter().flatten() {
+        handler(vector);
+    }
+    end_of_interrupt(vector);
+}
@@ -28,6 +28,9 @@ mod font_constants {
     pub const FONT_WEIGHT: FontWeight = FontWeight::Regular;
 }
 
+/// Index of the backup character in the rendered characters
+const BACKUP_GLYPH: usize = u8::MAX as usize + 1;
+
 /// Gets the raster of the character, or backup character
 fn get_char_raster(c: char) -> RasterizedChar {
     get_raster(
@@ -47,11 +50,25 @@ fn get_char_raster(c: char) -> RasterizedChar {
 
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
 
+/// Progress through an ANSI escape sequence
+#[derive(Clone, Copy)]
+enum Escape {
+    None,
+    /// After an ESC
+    Started,
+    /// Inside an `ESC [` control sequence, with its parameter so far
+    Csi(usize),
+}
+
 /// A VGA Framebuffer based console
+///
+/// Understands the ANSI escape sequences for moving the cursor left and right and
+/// for erasing to the end of the line, which the line editor uses
 pub struct Console {
     rendered_chars: Option<Vec<Vec<Vec<u32>>>>,
     x_pos: usize,
     y_pos: usize,
+    escape: Escape,
     text_color_r: u8,
     text_color_g: u8,
     text_color_b: u8,
@@ -63,6 +80,7 @@ impl Console {
             rendered_chars: None,
             x_pos: BORDER_PADDING,
             y_pos: BORDER_PADDING,
+            escape: Escape::None,
             text_color_r: 255,
             text_color_b: 255,
             text_color_g: 255,
@@ -79,10 +97,13 @@ impl Console {
         }
     }
 
+    /// Renders the Latin-1 characters, followed by the backup character that is drawn
+    /// in place of everything else
     pub fn render_chars(&mut self) -> Vec<Vec<Vec<u32>>> {
-        let mut rendered_chars = Vec::with_capacity(u8::MAX as usize);
-        for i in 0..u8::MAX {
-            let character = get_char_raster(i.into());
+        let mut rendered_chars = Vec::with_capacity(BACKUP_GLYPH + 1);
+        let chars = (0..=u8::MAX).map(char::from);
+        for c in chars.chain([font_constants::BACKUP_CHAR]) {
+            let character = get_char_raster(c);
 
             let v: Vec<Vec<u32>> = (0..CHAR_RASTER_HEIGHT as usize)
                 .map(|i| {
@@ -120,10 +141,36 @@ impl Console {
         if framebuffer::width() == 0 || framebuffer::height() == 0 {
             return;
         }
-        match c as u8 {
-            b'\n' => self.newline(),
-            b'\r' => self.carriage_return(),
-            8 => {
+        match self.escape {
+            Escape::None => {}
+            Escape::Started => {
+                self.escape = if c == '[' {
+                    Escape::Csi(0)
+                } else {
+                    Escape::None
+                };
+                return;
+            }
+            Escape::Csi(param) => {
+                self.escape = match c {
+                    '0'..='9' => Escape::Csi(
+                        param
+                            .saturating_mul(10)
+                            .saturating_add(c as usize - '0' as usize),
+                    ),
+                    c => {
+                        self.control_sequence(param, c);
+                        Escape::None
+                    }
+                };
+                return;
+            }
+        }
+        match c {
+            '\x1b' => self.escape = Escape::Started,
+            '\n' => self.newline(),
+            '\r' => self.carriage_return(),
+            '\x08' => {
                 let w = if let Some(chars) = &self.rendered_chars {
                     chars[b'a' as usize][0].len() + LETTER_SPACING
                 } else {
@@ -137,10 +184,42 @@ impl Console {
                 self.x_pos -= w;
             }
             c => {
-                self.draw(c.into());
+                self.draw(c);
+            }
+        }
+    }
+
+    /// Runs `ESC [ param final`, sequences other than cursor left, cursor right, and
+    /// erase to the end of the line are ignored
+    fn control_sequence(&mut self, param: usize, final_char: char) {
+        let char_width = CHAR_RASTER_WIDTH + LETTER_SPACING;
+        let count = param.max(1);
+
+        match final_char {
+            'D' => {
+                self.x_pos = self
+                    .x_pos
+                    .saturating_sub(count.saturating_mul(char_width))
+                    .max(BORDER_PADDING);
             }
+            'C' => {
+                let last_column = framebuffer::width().saturating_sub(char_width);
+                self.x_pos = self
+                    .x_pos
+                    .saturating_add(count.saturating_mul(char_width))
+                    .min(last_column);
+            }
+            'K' => framebuffer::fill_rect(
+                self.x_pos,
+                self.y_pos,
+                framebuffer::width().saturating_sub(self.x_pos),
+                CHAR_RASTER_HEIGHT.val(),
+                DEFAULT_BACKGROUND_COLOR,
+            ),
+            _ => {}
         }
     }
+
     pub fn draw(&mut self, c: char) {
         let new_xpos = self.x_pos + font_constants::CHAR_RASTER_WIDTH;
         if new_xpos >= framebuffer::width() {
@@ -152,7 +231,7 @@ impl Console {
         }
         // Draw the character by copying bytes from the prerendered buffer
         if let Some(chars) = &self.rendered_chars {
-            let char = &chars[c as usize];
+            let char = chars.get(c as usize).unwrap_or(&chars[BACKUP_GLYPH]);
             for (i, line) in char.iter().enumerate() {
                 for (j, pixel) in line.iter().enumerate() {
                     framebuffer::set_pixel(self.x_pos + j, self.y_pos + i, *pixel)
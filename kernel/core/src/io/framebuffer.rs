@@ -1,8 +1,11 @@
 use libk::Mutex;
 use limine::{Framebuffer, NonNullPtr};
 use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::structures::paging::{PageSize, PageTableFlags, Size2MiB};
+use x86_64::VirtAddr;
 
 use crate::limine_requests::FRAMEBUFFER_REQUEST;
+use crate::memory::{self, address_space::RegionKind};
 
 pub static FRAMEBUFFER: Mutex<Option<FrameBufferWriter>> = Mutex::new(None);
 
@@ -82,8 +85,13 @@ pub fn get_fb_raw() -> Option<&'static NonNullPtr<Framebuffer>> {
 /// Internal struct used to store the state of the framebuffer
 pub struct FrameBufferWriter {
     framebuffer: &'static NonNullPtr<Framebuffer>,
+    /// Where the pixels are written, the remapped framebuffer if mapping it succeeded
+    buffer: *mut u8,
 }
 
+// The buffer is only accessed through the FRAMEBUFFER lock
+unsafe impl Send for FrameBufferWriter {}
+
 impl FrameBufferWriter {
     pub fn new(frame_buffer_request: &limine::FramebufferRequest) -> Self {
         let fb_response = frame_buffer_request.get_response().get().unwrap();
@@ -92,20 +100,24 @@ impl FrameBufferWriter {
         }
 
         let framebuffer = &fb_response.framebuffers()[0];
+        let buffer = remap(framebuffer).unwrap_or(framebuffer.address.as_ptr().unwrap());
 
-        Self { framebuffer }
+        Self {
+            framebuffer,
+            buffer,
+        }
     }
 
     fn set_pixel(&self, x: usize, y: usize, color: u32) {
         let offset = self.framebuffer.pitch as usize * y + x * 4;
 
         unsafe {
-            *(self.framebuffer.address.as_ptr().unwrap().add(offset) as *mut u32) = color;
+            *(self.buffer.add(offset) as *mut u32) = color;
         }
     }
 
     fn fill_rect(&self, x: usize, y: usize, width: usize, height: usize, color: u32) {
-        let buf = self.framebuffer.address.as_ptr().unwrap();
+        let buf = self.buffer;
         for i in y..(y + height) {
             let y_offset = i * self.framebuffer.pitch as usize;
             let buf = unsafe { buf.add(y_offset) };
@@ -126,6 +138,34 @@ impl FrameBufferWriter {
     }
 }
 
+/// Maps the framebuffer at a 2MiB aligned address, so huge pages can be used
+///
+/// Returns None if paging isn't initialized or the mapping failed, the
+/// bootloader's mapping keeps working in that case
+fn remap(framebuffer: &Framebuffer) -> Option<*mut u8> {
+    let virt = VirtAddr::from_ptr(framebuffer.address.as_ptr()?);
+    let phys = x86_64::PhysAddr::new(virt - memory::physical_memory_offset());
+    let size = framebuffer.pitch * framebuffer.height;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    let size = size + phys.as_u64() % 4096;
+    let mut page_allocator = memory::PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut()?;
+    let start = page_allocator
+        .address_space()
+        .allocate(size, Size2MiB::SIZE, RegionKind::Framebuffer)
+        .ok()?;
+    if page_allocator
+        .map_range(start, phys.align_down(4096u64), size, flags)
+        .is_err()
+    {
+        page_allocator.address_space().free(start).ok()?;
+        return None;
+    }
+
+    Some((start + phys.as_u64() % 4096).as_mut_ptr())
+}
+
 pub mod color {
     pub const fn from_rgb(r: u8, g: u8, b: u8) -> u32 {
         ((r as u32) << 16) | ((g as u32) << 8) | (b as u32)
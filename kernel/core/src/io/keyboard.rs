@@ -0,0 +1,265 @@
+//! PS/2 keyboard
+//!
+//! Decodes scancodes with the selected layout, and pushes every press and release
+//! to `libk::io::input` for stdin to read
+
+use core::fmt::Write;
+use libk::alloc::format;
+use libk::io::input::{self, Key, KeyEvent, KeyState, Modifiers};
+use libk::vec::Vec;
+use libk::Mutex;
+use pc_keyboard::{
+    layouts, DecodedKey, HandleControl, KeyCode, KeyState as RawKeyState, Keyboard, KeyboardLayout,
+    ScancodeSet1,
+};
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+use crate::interrupt;
+use crate::shell::commands::{self, Args, Builtin};
+use crate::shell::{Error, Output, Shell};
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+
+/// Locked by the interrupt handler, so it must only be locked with interrupts disabled
+static KEYBOARD: Mutex<State> = Mutex::new(State::new(Layout::Us104));
+
+struct State {
+    keyboard: Keyboard<Layout, ScancodeSet1>,
+    layout: Layout,
+    /// What each key held down was reported as, so its release is reported as the
+    /// same key even if the modifiers changed in between
+    pressed: [Option<Key>; 256],
+    /// pc-keyboard doesn't expose its modifiers, so caps lock is tracked here too
+    caps_lock: bool,
+}
+
+impl State {
+    const fn new(layout: Layout) -> Self {
+        Self {
+            keyboard: Keyboard::new(ScancodeSet1::new(), layout, HandleControl::Ignore),
+            layout,
+            pressed: [None; 256],
+            caps_lock: false,
+        }
+    }
+
+    fn is_held(&self, code: KeyCode) -> bool {
+        self.pressed[code as usize].is_some()
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        Modifiers {
+            shift: self.is_held(KeyCode::LShift) || self.is_held(KeyCode::RShift),
+            ctrl: self.is_held(KeyCode::LControl) || self.is_held(KeyCode::RControl),
+            alt: self.is_held(KeyCode::LAlt),
+            alt_gr: self.is_held(KeyCode::RAltGr),
+            caps_lock: self.caps_lock,
+        }
+    }
+}
+
+static LAYOUT_COMMAND: Builtin = Builtin {
+    name: "layout",
+    usage: "[LAYOUT]",
+    description: "Switches the keyboard layout, or lists the layouts",
+    run: layout_command,
+};
+
+/// Starts delivering key events, and registers the layout command
+///
+/// Called by kernel::init, after interrupt::init
+pub fn init() {
+    interrupt::register_irq(1, interrupt_handler).expect("Unable to register the keyboard IRQ");
+    commands::register(&LAYOUT_COMMAND).expect("Command names are unique");
+
+    // A scancode left in the controller from before the handler existed keeps it
+    // from raising another interrupt, so it is thrown away
+    let mut status: Port<u8> = Port::new(STATUS_PORT);
+    let mut data: Port<u8> = Port::new(DATA_PORT);
+    while unsafe { status.read() } & STATUS_OUTPUT_FULL != 0 {
+        unsafe { data.read() };
+    }
+}
+
+pub fn layout() -> Layout {
+    without_interrupts(|| KEYBOARD.lock().layout)
+}
+
+/// Switches to `layout`, keys that are held down are forgotten
+pub fn set_layout(layout: Layout) {
+    without_interrupts(|| *KEYBOARD.lock() = State::new(layout));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Us104,
+    Uk105,
+    De105,
+    Azerty,
+    Dvorak104,
+    DvorakProgrammer,
+    Colemak,
+    Jis109,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 8] = [
+        Layout::Us104,
+        Layout::Uk105,
+        Layout::De105,
+        Layout::Azerty,
+        Layout::Dvorak104,
+        Layout::DvorakProgrammer,
+        Layout::Colemak,
+        Layout::Jis109,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Us104 => "us",
+            Layout::Uk105 => "uk",
+            Layout::De105 => "de",
+            Layout::Azerty => "azerty",
+            Layout::Dvorak104 => "dvorak",
+            Layout::DvorakProgrammer => "dvp",
+            Layout::Colemak => "colemak",
+            Layout::Jis109 => "jis",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        Layout::ALL.into_iter().find(|layout| layout.name() == name)
+    }
+}
+
+impl KeyboardLayout for Layout {
+    fn map_keycode(
+        &self,
+        keycode: KeyCode,
+        modifiers: &pc_keyboard::Modifiers,
+        handle_ctrl: HandleControl,
+    ) -> DecodedKey {
+        match self {
+            Layout::Us104 => layouts::Us104Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Uk105 => layouts::Uk105Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::De105 => layouts::De105Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Azerty => layouts::Azerty.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Dvorak104 => layouts::Dvorak104Key.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::DvorakProgrammer => {
+                layouts::DVP104Key.map_keycode(keycode, modifiers, handle_ctrl)
+            }
+            Layout::Colemak => layouts::Colemak.map_keycode(keycode, modifiers, handle_ctrl),
+            Layout::Jis109 => layouts::Jis109Key.map_keycode(keycode, modifiers, handle_ctrl),
+        }
+    }
+}
+
+fn layout_command(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    let name = args.next_optional();
+    args.finish()?;
+    match name {
+        Some(name) => {
+            let layout = Layout::from_name(name)
+                .ok_or_else(|| Error::Failed(format!("unknown layout {name}")))?;
+            set_layout(layout);
+        }
+        None => {
+            let names: Vec<&str> = Layout::ALL.iter().map(|l| l.name()).collect();
+            writeln!(out, "Layout: {}", layout().name())?;
+            writeln!(out, "Available: {}", names.join(", "))?;
+        }
+    }
+    Ok(())
+}
+
+fn interrupt_handler(_vector: u8) {
+    let mut port: Port<u8> = Port::new(DATA_PORT);
+    let scancode = unsafe { port.read() };
+
+    let mut state = KEYBOARD.lock();
+    let Ok(Some(event)) = state.keyboard.add_byte(scancode) else {
+        return;
+    };
+    let code = event.code;
+    let pressed = event.state != RawKeyState::Up;
+
+    // Also keeps track of the modifiers, so it is called for releases too
+    let decoded = state.keyboard.process_keyevent(event);
+    // Toggles on every press, like in pc-keyboard, so both agree on it
+    if pressed && code == KeyCode::CapsLock {
+        state.caps_lock = !state.caps_lock;
+    }
+
+    let key = if pressed {
+        let key = match decoded {
+            Some(DecodedKey::Unicode(c)) => Key::from(c),
+            _ => raw_key(code),
+        };
+        state.pressed[code as usize] = Some(key);
+        key
+    } else {
+        state.pressed[code as usize]
+            .take()
+            .unwrap_or_else(|| raw_key(code))
+    };
+    // Taken after the event, so the press of a modifier is reported with it set
+    let modifiers = state.modifiers();
+    drop(state);
+
+    // Dropped if nothing has read the input for a while
+    input::push(KeyEvent {
+        key,
+        state: if pressed {
+            KeyState::Pressed
+        } else {
+            KeyState::Released
+        },
+        modifiers,
+    });
+}
+
+/// The key for keys that don't type anything
+fn raw_key(code: KeyCode) -> Key {
+    match code {
+        KeyCode::Return | KeyCode::NumpadEnter => Key::Enter,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::ArrowUp => Key::Up,
+        KeyCode::ArrowDown => Key::Down,
+        KeyCode::ArrowLeft => Key::Left,
+        KeyCode::ArrowRight => Key::Right,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::F1 => Key::Function(1),
+        KeyCode::F2 => Key::Function(2),
+        KeyCode::F3 => Key::Function(3),
+        KeyCode::F4 => Key::Function(4),
+        KeyCode::F5 => Key::Function(5),
+        KeyCode::F6 => Key::Function(6),
+        KeyCode::F7 => Key::Function(7),
+        KeyCode::F8 => Key::Function(8),
+        KeyCode::F9 => Key::Function(9),
+        KeyCode::F10 => Key::Function(10),
+        KeyCode::F11 => Key::Function(11),
+        KeyCode::F12 => Key::Function(12),
+        KeyCode::LShift
+        | KeyCode::RShift
+        | KeyCode::LControl
+        | KeyCode::RControl
+        | KeyCode::LAlt
+        | KeyCode::RAltGr
+        | KeyCode::LWin
+        | KeyCode::RWin
+        | KeyCode::CapsLock
+        | KeyCode::NumpadLock
+        | KeyCode::ScrollLock => Key::Modifier,
+        _ => Key::Unknown,
+    }
+}
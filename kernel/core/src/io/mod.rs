@@ -1,3 +1,4 @@
 pub mod console;
 pub mod framebuffer;
+pub mod keyboard;
 pub mod serial;
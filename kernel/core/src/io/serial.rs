@@ -1,8 +1,80 @@
-use libk::Mutex;
+//! COM1 serial port
+//!
+//! Used as an output sink for stdout and stderr, and as a second input next to
+//! the keyboard, so the shell can be driven from a terminal
+
+use libk::io::input;
+use libk::io::terminal::TerminalDecoder;
+use libk::{fmt, Mutex};
 use uart_16550::SerialPort;
+use x86_64::instructions::port::Port;
+
+use crate::interrupt;
+
+const COM1: u16 = 0x3F8;
+const COM1_IRQ: u8 = 4;
+
+const DATA_PORT: u16 = COM1;
+const FIFO_CONTROL_PORT: u16 = COM1 + 2;
+const LINE_STATUS_PORT: u16 = COM1 + 5;
+
+/// Enables and clears the FIFOs, with an interrupt for every byte received
+const FIFO_ENABLE_TRIGGER_1: u8 = 0x07;
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
 
-pub static SERIAL1: Mutex<SerialPort> = Mutex::new(unsafe { SerialPort::new(0x3F8) });
+pub static SERIAL1: Mutex<SerialPort> = Mutex::new(unsafe { SerialPort::new(COM1) });
 
+/// Only locked by the interrupt handler
+static DECODER: Mutex<TerminalDecoder> = Mutex::new(TerminalDecoder::new());
+
+/// Sets up the port and starts delivering what is received as key events
+///
+/// Called by kernel::init, after interrupt::init
 pub fn init() {
+    // Also enables the receive interrupt
     SERIAL1.lock().init();
+
+    // Without this, a single key press only arrives after the FIFO times out
+    unsafe { Port::new(FIFO_CONTROL_PORT).write(FIFO_ENABLE_TRIGGER_1) };
+
+    interrupt::register_irq(COM1_IRQ, interrupt_handler)
+        .expect("Unable to register the serial IRQ");
+}
+
+fn interrupt_handler(_vector: u8) {
+    // The ports are read directly instead of through SERIAL1, whose lock may be
+    // held by the code that was interrupted
+    let mut status: Port<u8> = Port::new(LINE_STATUS_PORT);
+    let mut data: Port<u8> = Port::new(DATA_PORT);
+
+    let mut decoder = DECODER.lock();
+    while unsafe { status.read() } & LINE_STATUS_DATA_READY != 0 {
+        let byte = unsafe { data.read() };
+        if let Some(event) = decoder.push(byte) {
+            // Dropped if nothing has read the input for a while
+            input::push(event);
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use fmt::Write;
+
+    // The lock is also taken while printing from interrupt handlers
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _ = SERIAL1.lock().write_fmt(args);
+    });
+}
+
+/// Prints to the serial port only, used for test output
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::io::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
 }
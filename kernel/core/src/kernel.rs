@@ -2,22 +2,32 @@ use crate::io::console::CONSOLE;
 use crate::io::serial;
 use crate::io::serial::SERIAL1;
 use crate::{
-    constants::KERNEL_VERSION,
+    constants::{KERNEL_STACK_SIZE, KERNEL_VERSION},
     io::{
         console::{self},
         framebuffer::{self},
+        keyboard,
     },
     *,
 };
+use libk::arch::asm;
 use libk::println;
 
 /// Performs early kernel initialization
 pub fn init() {
     unsafe {
-        interrupt::init();
         memory::init();
     }
     kernel_allocator::init();
+    acpi::init();
+    unsafe {
+        gdt::init();
+        interrupt::init();
+        time::init();
+    }
+    keyboard::init();
+    fs::init();
+    shell::init();
     framebuffer::init();
     console::clear_screen();
     serial::init();
@@ -31,3 +41,26 @@ pub fn init() {
     println!(" :: Butterscotch OS {KERNEL_VERSION} :: ");
     println!("Copyright 2024 DitherWither");
 }
+
+/// Switches to a newly allocated stack with a guard page, and calls `entry` on it
+///
+/// The boot stack is abandoned, so anything borrowed from it must not be used by `entry`
+pub fn run_on_kernel_stack(entry: extern "C" fn() -> !) -> ! {
+    let stack_top = memory::PAGE_ALLOCATOR
+        .lock()
+        .as_mut()
+        .expect("paging is initialized")
+        .allocate_stack(KERNEL_STACK_SIZE)
+        .expect("Unable to allocate kernel stack");
+
+    unsafe {
+        asm!(
+            "mov rsp, {stack_top}",
+            "xor rbp, rbp",
+            "call {entry}",
+            stack_top = in(reg) stack_top.as_u64(),
+            entry = in(reg) entry,
+            options(noreturn)
+        );
+    }
+}
@@ -1,38 +1,217 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use talc::*;
-use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+use x86_64::{
+    structures::paging::{PageSize, PageTableFlags, Size2MiB},
+    VirtAddr,
+};
 
 use crate::{
-    constants::{HEAP_DEFAULT_SIZE, HEAP_START},
-    memory,
+    constants::{HEAP_DEFAULT_SIZE, HEAP_MAX_SIZE, HEAP_START},
+    memory::{self, frame_allocator::FRAME_SIZE},
+    shell::{
+        commands::{self, Args, Builtin},
+        Error, Output, Shell,
+    },
 };
 
 use libk::Mutex;
 
 #[global_allocator]
-static ALLOCATOR: Talck<Mutex<()>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+static ALLOCATOR: KernelAllocator = KernelAllocator {
+    talc: Talc::new(GrowHeap {
+        heap: Span::empty(),
+    })
+    .lock(),
+    allocated: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+static MEMINFO_COMMAND: Builtin = Builtin {
+    name: "meminfo",
+    usage: "",
+    description: "Prints how much physical memory and heap is used",
+    run: meminfo_command,
+};
 
-// TODO: Use 2MiB pages instead for better performance
-/// Initialize the allocator
+/// Initialize the allocator, and register the meminfo command
 ///
 /// Called by kernel::init by default
 pub fn init() {
     let heap_start = VirtAddr::new(HEAP_START as u64);
-    let heap_end = heap_start + HEAP_DEFAULT_SIZE - 1u64;
     let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
 
     if let Some(page_allocator) = &mut *memory::PAGE_ALLOCATOR.lock() {
         page_allocator
-            .allocate_pages_4kib(heap_start, heap_end, flags)
+            .allocate_range(heap_start, HEAP_DEFAULT_SIZE as u64, flags)
             .expect("Unable to allocate pages");
     }
 
-    unsafe {
-        ALLOCATOR
-            .lock()
-            .claim(Span::from_base_size(
-                heap_start.as_mut_ptr(),
-                HEAP_DEFAULT_SIZE,
-            ))
-            .expect("Unable to claim heap");
+    let mut talc = ALLOCATOR.talc.lock();
+    talc.oom_handler.heap = unsafe {
+        talc.claim(Span::from_base_size(
+            heap_start.as_mut_ptr(),
+            HEAP_DEFAULT_SIZE,
+        ))
+        .expect("Unable to claim heap")
+    };
+    drop(talc);
+
+    commands::register(&MEMINFO_COMMAND).expect("Command names are unique");
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes of memory mapped for the heap
+    pub size: usize,
+    /// Bytes currently handed out, not counting the allocator's own bookkeeping
+    pub allocated: usize,
+    pub free: usize,
+    /// Highest value `allocated` has reached
+    pub peak: usize,
+}
+
+pub fn stats() -> HeapStats {
+    let size = ALLOCATOR.talc.lock().oom_handler.heap.size();
+    let allocated = ALLOCATOR.allocated.load(Ordering::Relaxed);
+
+    HeapStats {
+        size,
+        allocated,
+        free: size.saturating_sub(allocated),
+        peak: ALLOCATOR.peak.load(Ordering::Relaxed),
+    }
+}
+
+fn meminfo_command(
+    _: &mut Shell,
+    args: &mut Args,
+    _: &[u8],
+    out: &mut Output,
+) -> Result<(), Error> {
+    use core::fmt::Write;
+
+    args.finish()?;
+
+    // Writing allocates, which can grow the heap, so the lock can't be held while writing
+    let frames = memory::PAGE_ALLOCATOR
+        .lock()
+        .as_mut()
+        .map(|page_allocator| {
+            let frames = page_allocator.frame_allocator();
+            (
+                frames.usable_frames(),
+                frames.used_frames(),
+                frames.free_frames(),
+            )
+        });
+    if let Some((usable, used, free)) = frames {
+        let kib = |frames: usize| frames * FRAME_SIZE as usize / 1024;
+        writeln!(out, "Usable: {} KiB", kib(usable))?;
+        writeln!(out, "Used:   {} KiB", kib(used))?;
+        writeln!(out, "Free:   {} KiB", kib(free))?;
+    }
+
+    let heap = stats();
+    writeln!(
+        out,
+        "Heap:   {} KiB, {} KiB allocated, {} KiB free, {} KiB peak",
+        heap.size / 1024,
+        heap.allocated / 1024,
+        heap.free / 1024,
+        heap.peak / 1024
+    )?;
+    Ok(())
+}
+
+/// Talc with counters for `stats`
+struct KernelAllocator {
+    talc: Talck<Mutex<()>, GrowHeap>,
+    allocated: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl KernelAllocator {
+    fn grew(&self, bytes: usize) {
+        let allocated = self.allocated.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak.fetch_max(allocated, Ordering::Relaxed);
+    }
+
+    fn shrank(&self, bytes: usize) {
+        self.allocated.fetch_sub(bytes, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.talc.alloc(layout) };
+        if !ptr.is_null() {
+            self.grew(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.talc.dealloc(ptr, layout) };
+        self.shrank(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { self.talc.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            match new_size.checked_sub(layout.size()) {
+                Some(grown) => self.grew(grown),
+                None => self.shrank(layout.size() - new_size),
+            }
+        }
+        new_ptr
+    }
+}
+
+/// Maps more pages after the end of the heap when it runs out of memory,
+/// until it reaches `HEAP_MAX_SIZE`
+struct GrowHeap {
+    heap: Span,
+}
+
+impl OomHandler for GrowHeap {
+    fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
+        let old_heap = talc.oom_handler.heap;
+        let (base, acme) = old_heap.get_base_acme().ok_or(())?;
+        let size = old_heap.size();
+
+        // Leave room for alignment and talc's metadata, and grow in whole 2MiB pages
+        let needed = layout
+            .size()
+            .checked_add(layout.align() + 64)
+            .and_then(|needed| needed.checked_next_multiple_of(Size2MiB::SIZE as usize))
+            .ok_or(())?;
+        let available = HEAP_MAX_SIZE.saturating_sub(size);
+        if needed > available {
+            return Err(());
+        }
+
+        // The page allocator may be locked by the code that is allocating, give up instead of deadlocking
+        let mut page_allocator = memory::PAGE_ALLOCATOR.try_lock().ok_or(())?;
+        let page_allocator = page_allocator.as_mut().ok_or(())?;
+        let start = VirtAddr::from_ptr(acme);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        // Double the heap if possible, so large workloads don't grow it on every allocation
+        let grow_by = [size.max(needed).min(available), needed]
+            .into_iter()
+            .find(|&grow_by| {
+                page_allocator
+                    .allocate_range(start, grow_by as u64, flags)
+                    .is_ok()
+            })
+            .ok_or(())?;
+
+        talc.oom_handler.heap =
+            unsafe { talc.extend(old_heap, Span::from_base_size(base, size + grow_by)) };
+        Ok(())
     }
 }
@@ -0,0 +1,52 @@
+#![no_std]
+#![cfg_attr(test, no_main)]
+#![feature(const_mut_refs)]
+#![feature(lazy_cell)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+pub mod acpi;
+pub mod backtrace;
+pub mod constants;
+pub mod exception;
+pub mod fs;
+pub mod gdt;
+pub mod interrupt;
+pub mod io;
+pub mod kernel;
+pub mod kernel_allocator;
+pub mod limine_requests;
+pub mod memory;
+pub mod shell;
+pub mod testing;
+pub mod time;
+
+pub use kernel::init;
+pub use testing::test_runner;
+
+pub use libk::{dbg, eprint, eprintln, print, println};
+
+pub fn hlt_loop() -> ! {
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Entry point for `cargo test --lib`
+#[cfg(test)]
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    testing::test_panic_handler(info)
+}
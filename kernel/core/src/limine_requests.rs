@@ -5,4 +5,12 @@ pub static _BASE_REVISION: limine::BaseRevision = limine::BaseRevision::new(1);
 pub static MEMMAP_REQUEST: limine::MemmapRequest = limine::MemmapRequest::new(1);
 pub static HHDM_REQUEST: limine::HhdmRequest = limine::HhdmRequest::new(1);
 
+/// Root of the ACPI tables, used to find the interrupt controllers
+pub static RSDP_REQUEST: limine::RsdpRequest = limine::RsdpRequest::new(0);
+
 pub static FRAMEBUFFER_REQUEST: limine::FramebufferRequest = limine::FramebufferRequest::new(1);
+
+/// The kernel ELF and where it was loaded, used to symbolize backtraces
+pub static KERNEL_FILE_REQUEST: limine::KernelFileRequest = limine::KernelFileRequest::new(0);
+pub static KERNEL_ADDRESS_REQUEST: limine::KernelAddressRequest =
+    limine::KernelAddressRequest::new(0);
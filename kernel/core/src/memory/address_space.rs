@@ -0,0 +1,172 @@
+//! Virtual address space manager
+//!
+//! Keeps a sorted list of the regions of the kernel's address space that are in use,
+//! so new mappings are placed in free space instead of at hard-coded addresses.
+//! The list has a fixed size, as it is needed before the heap exists and while growing it
+
+use x86_64::{
+    align_up,
+    structures::paging::{
+        mapper::{MapToError, TranslateError},
+        PageSize, Size4KiB,
+    },
+    VirtAddr,
+};
+
+const MAX_REGIONS: usize = 64;
+
+/// Unmapped space left around allocated regions, so running off the end of one
+/// faults instead of corrupting its neighbour
+pub const GUARD_SIZE: u64 = Size4KiB::SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    KernelImage,
+    /// Limine's higher half direct map of the physical memory
+    DirectMap,
+    Heap,
+    Mmio,
+    Framebuffer,
+    Stack,
+    /// Memory allocated with `PageAllocator::vmalloc`
+    Vmalloc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: VirtAddr,
+    pub size: u64,
+    pub kind: RegionKind,
+}
+
+impl Region {
+    /// Address after the last byte of the region, saturates for regions at the top
+    /// of the address space
+    pub fn end(&self) -> u64 {
+        self.start.as_u64().saturating_add(self.size)
+    }
+
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        (self.start.as_u64()..self.end()).contains(&addr.as_u64())
+    }
+
+    fn overlaps(&self, other: &Region) -> bool {
+        self.start.as_u64() < other.end() && other.start.as_u64() < self.end()
+    }
+}
+
+#[derive(Debug)]
+pub enum VmError {
+    /// The range overlaps this region
+    Overlap(Region),
+    /// No free range is large enough
+    OutOfSpace,
+    /// No region starts at the address
+    NotFound,
+    TooManyRegions,
+    Map(MapToError<Size4KiB>),
+    Unmap(TranslateError),
+}
+
+pub struct AddressSpace {
+    regions: [Region; MAX_REGIONS],
+    len: usize,
+    /// Range that `allocate` hands out regions from
+    window_start: u64,
+    window_end: u64,
+}
+
+impl AddressSpace {
+    /// Creates an empty address space, which allocates from `window_start` up to `window_end`
+    pub const fn new(window_start: VirtAddr, window_end: VirtAddr) -> Self {
+        const UNUSED: Region = Region {
+            start: VirtAddr::zero(),
+            size: 0,
+            kind: RegionKind::Vmalloc,
+        };
+
+        Self {
+            regions: [UNUSED; MAX_REGIONS],
+            len: 0,
+            window_start: window_start.as_u64(),
+            window_end: window_end.as_u64(),
+        }
+    }
+
+    /// Regions in use, sorted by address
+    pub fn regions(&self) -> &[Region] {
+        &self.regions[..self.len]
+    }
+
+    /// Region containing `addr`
+    pub fn find(&self, addr: VirtAddr) -> Option<&Region> {
+        self.regions().iter().find(|region| region.contains(addr))
+    }
+
+    /// Marks a fixed range as used, fails if it overlaps a region that is already in use
+    pub fn reserve(&mut self, start: VirtAddr, size: u64, kind: RegionKind) -> Result<(), VmError> {
+        self.insert(Region { start, size, kind })
+    }
+
+    /// Finds a free range of `size` bytes in the allocation window, aligned to `align` bytes
+    /// and surrounded by guard pages, and marks it as used
+    pub fn allocate(
+        &mut self,
+        size: u64,
+        align: u64,
+        kind: RegionKind,
+    ) -> Result<VirtAddr, VmError> {
+        let size = size.next_multiple_of(Size4KiB::SIZE);
+        let align = align.max(Size4KiB::SIZE);
+
+        let mut candidate = align_up(self.window_start + GUARD_SIZE, align);
+        for region in self.regions() {
+            if region.end() + GUARD_SIZE <= candidate {
+                continue;
+            }
+            if candidate + size + GUARD_SIZE <= region.start.as_u64() {
+                break;
+            }
+            candidate = align_up(region.end() + GUARD_SIZE, align);
+        }
+
+        if candidate + size + GUARD_SIZE > self.window_end {
+            return Err(VmError::OutOfSpace);
+        }
+
+        let start = VirtAddr::new(candidate);
+        self.insert(Region { start, size, kind })?;
+        Ok(start)
+    }
+
+    /// Removes the region starting at `start`, and returns it
+    pub fn free(&mut self, start: VirtAddr) -> Result<Region, VmError> {
+        let index = self
+            .regions()
+            .iter()
+            .position(|region| region.start == start)
+            .ok_or(VmError::NotFound)?;
+        let region = self.regions[index];
+
+        self.regions.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        Ok(region)
+    }
+
+    fn insert(&mut self, region: Region) -> Result<(), VmError> {
+        if let Some(other) = self.regions().iter().find(|other| other.overlaps(&region)) {
+            return Err(VmError::Overlap(*other));
+        }
+        if self.len == MAX_REGIONS {
+            return Err(VmError::TooManyRegions);
+        }
+
+        let index = self
+            .regions()
+            .partition_point(|other| other.start < region.start);
+        self.regions.copy_within(index..self.len, index + 1);
+        self.regions[index] = region;
+        self.len += 1;
+        Ok(())
+    }
+}
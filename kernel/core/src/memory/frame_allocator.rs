@@ -0,0 +1,232 @@
+//! Physical memory manager
+//!
+//! Every 4KiB frame of physical memory has a bit in a bitmap, which is set while
+//! the frame is in use. The bitmap is built once from the Limine memory map, and
+//! is stored in usable memory itself, accessed through the higher half direct map
+
+use libk::slice;
+use limine::{MemmapEntry, MemoryMapEntryType, NonNullPtr};
+use x86_64::{
+    structures::paging::{
+        frame::PhysFrameRange, FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size1GiB,
+        Size2MiB, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+pub const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = u64::BITS as usize;
+
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    /// Frames marked as usable in the memory map, including the ones holding the bitmap
+    usable: usize,
+    free: usize,
+    /// Every word before this one is fully used, searches start here
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Builds the bitmap from the memory map
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that the memory map is valid, that all frames marked
+    /// as `Usable` really are unused, and that the entire physical memory is
+    /// mapped at `physical_memory_offset`. Should only be called once
+    pub unsafe fn new(
+        memory_map: &[NonNullPtr<MemmapEntry>],
+        physical_memory_offset: VirtAddr,
+    ) -> Self {
+        let usable_regions = || {
+            memory_map
+                .iter()
+                .filter(|entry| entry.typ == MemoryMapEntryType::Usable)
+                // Bootloader page-aligns all usable memory, we don't need alignment or rounding code here
+                .map(|entry| (entry.base / FRAME_SIZE, entry.len / FRAME_SIZE))
+        };
+
+        // Frames above the last usable one are never handed out, so they don't need bits
+        let frame_count = usable_regions()
+            .map(|(start, count)| start + count)
+            .max()
+            .unwrap_or(0) as usize;
+        let words = frame_count.div_ceil(BITS_PER_WORD);
+        let bitmap_frames = (words as u64 * 8).div_ceil(FRAME_SIZE);
+
+        let (bitmap_start, _) = usable_regions()
+            .find(|&(_, count)| count >= bitmap_frames)
+            .expect("No usable region is large enough for the frame bitmap");
+        let bitmap_ptr = (physical_memory_offset + bitmap_start * FRAME_SIZE).as_mut_ptr::<u64>();
+        let bitmap = unsafe { slice::from_raw_parts_mut(bitmap_ptr, words) };
+        // Everything that isn't explicitly usable stays marked as used
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = Self {
+            bitmap,
+            usable: 0,
+            free: 0,
+            next: 0,
+        };
+        for (start, count) in usable_regions() {
+            allocator.mark(start as usize, count as usize, false);
+            allocator.usable += count as usize;
+        }
+        allocator.mark(bitmap_start as usize, bitmap_frames as usize, true);
+        allocator.next = 0;
+
+        allocator
+    }
+
+    /// Number of frames that are free to allocate
+    pub fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    /// Number of usable frames that are allocated, including the ones holding the bitmap
+    pub fn used_frames(&self) -> usize {
+        self.usable - self.free
+    }
+
+    /// Number of frames marked as usable by the bootloader
+    pub fn usable_frames(&self) -> usize {
+        self.usable
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / BITS_PER_WORD] & (1 << (frame % BITS_PER_WORD)) != 0
+    }
+
+    /// Marks `count` frames starting at `start` as used or free, and keeps the counters up to date
+    fn mark(&mut self, start: usize, count: usize, used: bool) {
+        for frame in start..start + count {
+            let word = &mut self.bitmap[frame / BITS_PER_WORD];
+            let bit = 1 << (frame % BITS_PER_WORD);
+            match (*word & bit != 0, used) {
+                (false, true) => {
+                    *word |= bit;
+                    self.free -= 1;
+                }
+                (true, false) => {
+                    *word &= !bit;
+                    self.free += 1;
+                }
+                _ => {}
+            }
+        }
+
+        if !used {
+            self.next = self.next.min(start / BITS_PER_WORD);
+        }
+    }
+
+    /// Allocates `count` physically contiguous frames, with the first frame aligned
+    /// to `align` frames. Meant for DMA buffers
+    ///
+    /// `align` must be a power of two
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrameRange> {
+        assert!(align.is_power_of_two(), "Alignment must be a power of two");
+        if count == 0 {
+            return None;
+        }
+
+        let frame_count = self.bitmap.len() * BITS_PER_WORD;
+        let mut start = (self.next * BITS_PER_WORD).next_multiple_of(align);
+
+        while start + count <= frame_count {
+            // Skip past the last used frame in the window, nothing before it can fit
+            match (start..start + count)
+                .rev()
+                .find(|&frame| self.is_used(frame))
+            {
+                Some(used) => start = (used + 1).next_multiple_of(align),
+                None => {
+                    self.mark(start, count, true);
+                    return Some(PhysFrame::range(frame_at(start), frame_at(start + count)));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Frees frames returned by `allocate_contiguous`
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that the frames are unused
+    pub unsafe fn deallocate_contiguous(&mut self, frames: PhysFrameRange) {
+        for frame in frames {
+            unsafe { self.deallocate_frame(frame) };
+        }
+    }
+
+    /// Huge frames are runs of 4KiB frames, aligned to their own size
+    fn allocate_huge_frame<S: PageSize>(&mut self) -> Option<PhysFrame<S>> {
+        let count = (S::SIZE / FRAME_SIZE) as usize;
+        let frames = self.allocate_contiguous(count, count)?;
+        Some(PhysFrame::containing_address(frames.start.start_address()))
+    }
+
+    unsafe fn deallocate_huge_frame<S: PageSize>(&mut self, frame: PhysFrame<S>) {
+        let start = PhysFrame::containing_address(frame.start_address());
+        let count = S::SIZE / FRAME_SIZE;
+        unsafe { self.deallocate_contiguous(PhysFrame::range(start, start + count)) };
+    }
+}
+
+fn frame_at(index: usize) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+}
+
+fn frame_index(frame: PhysFrame) -> usize {
+    (frame.start_address().as_u64() / FRAME_SIZE) as usize
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let word = (self.next..self.bitmap.len()).find(|&word| self.bitmap[word] != u64::MAX)?;
+        self.next = word;
+
+        let frame = word * BITS_PER_WORD + self.bitmap[word].trailing_ones() as usize;
+        self.mark(frame, 1, true);
+        Some(frame_at(frame))
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    /// Panics if the frame is already free, which means it was freed twice
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let index = frame_index(frame);
+        assert!(
+            index < self.bitmap.len() * BITS_PER_WORD && self.is_used(index),
+            "Freeing {frame:?}, which is not allocated"
+        );
+
+        self.mark(index, 1, false);
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        self.allocate_huge_frame()
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        unsafe { self.deallocate_huge_frame(frame) };
+    }
+}
+
+unsafe impl FrameAllocator<Size1GiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size1GiB>> {
+        self.allocate_huge_frame()
+    }
+}
+
+impl FrameDeallocator<Size1GiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size1GiB>) {
+        unsafe { self.deallocate_huge_frame(frame) };
+    }
+}
@@ -0,0 +1,610 @@
+pub mod address_space;
+pub mod frame_allocator;
+
+use core::arch::x86_64::__cpuid;
+use libk::Mutex;
+use limine::{MemmapEntry, NonNullPtr};
+use x86_64::{
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::{MapToError, MappedFrame, TranslateError, TranslateResult},
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+        PageTableFlags, PhysFrame, Size1GiB, Size2MiB, Size4KiB, Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use crate::{
+    constants::{HEAP_MAX_SIZE, HEAP_START, VMALLOC_END, VMALLOC_START},
+    limine_requests::{HHDM_REQUEST, MEMMAP_REQUEST},
+};
+use address_space::{AddressSpace, RegionKind, VmError};
+use frame_allocator::BitmapFrameAllocator;
+
+pub static PAGE_ALLOCATOR: Mutex<Option<PageAllocator>> = Mutex::new(None);
+
+/// Flags for page tables created while mapping, the page flags restrict access further
+const TABLE_FLAGS: PageTableFlags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+
+/// Set on pages mapped to memory we don't own, such as MMIO registers or the framebuffer.
+/// Their frames are not returned to the frame allocator when unmapped
+const BORROWED: PageTableFlags = PageTableFlags::BIT_9;
+
+/// # Safety
+///
+/// Caller must ensure that the limine requests are valid,
+/// and come from the bootloader
+pub unsafe fn init() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    if !page_allocator.is_none() {
+        return;
+    }
+
+    *page_allocator = Some(PageAllocator::init(&MEMMAP_REQUEST, &HHDM_REQUEST));
+}
+
+/// Virtual address at which the bootloader mapped the whole physical memory
+pub fn physical_memory_offset() -> VirtAddr {
+    VirtAddr::new(HHDM_REQUEST.get_response().get().unwrap().offset)
+}
+
+pub struct PageAllocator<'a> {
+    mapper: OffsetPageTable<'a>,
+    frame_allocator: BitmapFrameAllocator,
+    supports_1gib_pages: bool,
+    address_space: AddressSpace,
+}
+
+impl<'a> PageAllocator<'a> {
+    /// Marked private to avoid accidentally constructing multiple instances
+    ///
+    /// # Safety
+    ///
+    /// Caller must ensure that the limine requests are valid,
+    /// and come from the bootloader
+    unsafe fn init(
+        memmap_request: &limine::MemmapRequest,
+        hhdm_request: &limine::HhdmRequest,
+    ) -> Self {
+        // Get memmap
+        let memmap = unsafe {
+            memmap_request
+                .get_response()
+                .as_ptr()
+                .expect("Unable to get memory map")
+                .as_ref()
+                .unwrap()
+        }
+        .memmap();
+
+        // Get physical memory offset
+        let physical_memory_offset = hhdm_request.get_response().get().unwrap().offset;
+        let physical_memory_offset = VirtAddr::new(physical_memory_offset);
+
+        Self {
+            mapper: Self::init_mapper(physical_memory_offset),
+            frame_allocator: BitmapFrameAllocator::new(memmap, physical_memory_offset),
+            supports_1gib_pages: supports_1gib_pages(),
+            address_space: Self::init_address_space(memmap, physical_memory_offset),
+        }
+    }
+
+    /// Maps every page of size `S` between `start` and `end` (inclusive) to newly allocated frames
+    pub fn allocate_pages<S>(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<S>>
+    where
+        S: PageSize,
+        OffsetPageTable<'a>: Mapper<S>,
+        BitmapFrameAllocator: FrameAllocator<S>,
+    {
+        let start_page: Page<S> = Page::containing_address(start);
+        let end_page: Page<S> = Page::containing_address(end);
+
+        let page_range = Page::range_inclusive(start_page, end_page);
+
+        for page in page_range {
+            let frame = self
+                .frame_allocator
+                .allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)?;
+            unsafe {
+                self.mapper
+                    .map_to_with_table_flags(
+                        page,
+                        frame,
+                        flags,
+                        TABLE_FLAGS,
+                        &mut self.frame_allocator,
+                    )?
+                    .flush();
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn allocate_pages_4kib(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        self.allocate_pages(start, end, flags)
+    }
+
+    pub fn allocate_pages_2mib(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size2MiB>> {
+        self.allocate_pages(start, end, flags)
+    }
+
+    /// Panics if the CPU doesn't support 1GiB pages, see `supports_1gib_pages`
+    pub fn allocate_pages_1gib(
+        &mut self,
+        start: VirtAddr,
+        end: VirtAddr,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size1GiB>> {
+        assert!(self.supports_1gib_pages, "1GiB pages are not supported");
+        self.allocate_pages(start, end, flags)
+    }
+
+    /// Maps `size` bytes at `start` to newly allocated memory
+    ///
+    /// Uses the largest page size that fits each part of the range, falling back to
+    /// smaller pages when there is no physically contiguous memory left for a huge page.
+    /// Nothing stays mapped if it fails
+    pub fn allocate_range(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        self.map_range_inner(start, None, size, flags)
+    }
+
+    /// Maps `size` bytes of physical memory at `phys` to `virt`, such as a framebuffer
+    ///
+    /// Uses the largest page size that both addresses are aligned to
+    pub fn map_range(
+        &mut self,
+        virt: VirtAddr,
+        phys: PhysAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        self.map_range_inner(virt, Some(phys), size, flags)
+    }
+
+    /// Maps `size` bytes of device memory at `phys` with caching disabled, and
+    /// returns the address it was mapped at
+    ///
+    /// `phys` doesn't have to be page aligned. Undo with `vfree` on the page the
+    /// returned address is in
+    pub fn map_physical(
+        &mut self,
+        phys: PhysAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<VirtAddr, VmError> {
+        let start = phys.align_down(Size4KiB::SIZE);
+        let offset = phys - start;
+        let size = (offset + size).next_multiple_of(Size4KiB::SIZE);
+
+        let virt = self.map_new_region(size, RegionKind::Mmio, |page_allocator, virt| {
+            page_allocator.map_range(virt, start, size, flags | PageTableFlags::NO_CACHE)
+        })?;
+        Ok(virt + offset)
+    }
+
+    /// Maps `size` bytes of newly allocated memory at a free address, and returns it
+    pub fn vmalloc(&mut self, size: u64, flags: PageTableFlags) -> Result<VirtAddr, VmError> {
+        self.map_new_region(size, RegionKind::Vmalloc, |page_allocator, virt| {
+            page_allocator.allocate_range(virt, size, flags)
+        })
+    }
+
+    /// Unmaps a region returned by `vmalloc` or `map_physical`, or allocated from the address
+    /// space and mapped by the caller, and makes its addresses available again
+    pub fn vfree(&mut self, start: VirtAddr) -> Result<(), VmError> {
+        let region = self.address_space.free(start)?;
+        self.unmap_range(region.start, region.size)
+            .map_err(VmError::Unmap)
+    }
+
+    /// Maps a stack of `size` bytes and returns its top. The address space leaves an
+    /// unmapped guard page below it, so an overflow page faults instead of
+    /// overwriting other memory
+    pub fn allocate_stack(&mut self, size: u64) -> Result<VirtAddr, VmError> {
+        let size = size.next_multiple_of(Size4KiB::SIZE);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+        let bottom = self.map_new_region(size, RegionKind::Stack, |page_allocator, virt| {
+            page_allocator.allocate_range(virt, size, flags)
+        })?;
+        Ok(bottom + size)
+    }
+
+    pub fn address_space(&mut self) -> &mut AddressSpace {
+        &mut self.address_space
+    }
+
+    /// Allocates a region of `size` bytes and maps it with `map`, huge page aligned if it is large enough
+    fn map_new_region(
+        &mut self,
+        size: u64,
+        kind: RegionKind,
+        map: impl FnOnce(&mut Self, VirtAddr) -> Result<(), MapToError<Size4KiB>>,
+    ) -> Result<VirtAddr, VmError> {
+        let align = if size >= Size2MiB::SIZE {
+            Size2MiB::SIZE
+        } else {
+            Size4KiB::SIZE
+        };
+        let virt = self.address_space.allocate(size, align, kind)?;
+
+        if let Err(e) = map(self, virt) {
+            self.address_space
+                .free(virt)
+                .expect("Region was allocated just now");
+            return Err(VmError::Map(e));
+        }
+        Ok(virt)
+    }
+
+    /// Unmaps `size` bytes at `start` and frees the frames behind them, unless they
+    /// were mapped with `map_range` or `map_physical`
+    ///
+    /// Nothing is unmapped if part of the range isn't mapped, or if the range covers only
+    /// part of a huge page
+    pub fn unmap_range(&mut self, start: VirtAddr, size: u64) -> Result<(), TranslateError> {
+        let size = self.check_range(start, size)?;
+
+        let mut offset = 0;
+        while offset < size {
+            let virt = start + offset;
+            let (frame, flags) = self.mapped_page(virt)?;
+            let owned = !flags.contains(BORROWED);
+            match frame {
+                MappedFrame::Size4KiB(_) => self.unmap_page::<Size4KiB>(virt, owned),
+                MappedFrame::Size2MiB(_) => self.unmap_page::<Size2MiB>(virt, owned),
+                MappedFrame::Size1GiB(_) => self.unmap_page::<Size1GiB>(virt, owned),
+            }
+            offset += frame.size();
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the flags of every page in `size` bytes at `start`, e.g. to make a
+    /// region read-only or no-execute
+    ///
+    /// Nothing is changed if part of the range isn't mapped, or if the range covers only
+    /// part of a huge page
+    pub fn protect_range(
+        &mut self,
+        start: VirtAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), TranslateError> {
+        let size = self.check_range(start, size)?;
+
+        let mut offset = 0;
+        while offset < size {
+            let virt = start + offset;
+            let (frame, old_flags) = self.mapped_page(virt)?;
+            let flags = flags | (old_flags & BORROWED);
+            match frame {
+                MappedFrame::Size4KiB(_) => self.update_flags::<Size4KiB>(virt, flags),
+                MappedFrame::Size2MiB(_) => self.update_flags::<Size2MiB>(virt, flags),
+                MappedFrame::Size1GiB(_) => self.update_flags::<Size1GiB>(virt, flags),
+            }
+            offset += frame.size();
+        }
+
+        Ok(())
+    }
+
+    /// Physical address that `addr` is mapped to, works for pages of any size
+    pub fn translate(&self, addr: VirtAddr) -> Option<PhysAddr> {
+        self.mapper.translate_addr(addr)
+    }
+
+    /// Checks that `size` bytes at `start` are mapped, and that every huge page in
+    /// the range is covered completely. Returns the size rounded up to whole pages
+    fn check_range(&self, start: VirtAddr, size: u64) -> Result<u64, TranslateError> {
+        assert!(
+            start.is_aligned(Size4KiB::SIZE),
+            "Unaligned virtual address"
+        );
+
+        let size = size.next_multiple_of(Size4KiB::SIZE);
+        let mut offset = 0;
+        while offset < size {
+            let (frame, _) = self.mapped_page(start + offset)?;
+            if !(start + offset).is_aligned(frame.size()) || size - offset < frame.size() {
+                return Err(TranslateError::ParentEntryHugePage);
+            }
+            offset += frame.size();
+        }
+
+        Ok(size)
+    }
+
+    fn mapped_page(&self, virt: VirtAddr) -> Result<(MappedFrame, PageTableFlags), TranslateError> {
+        match self.mapper.translate(virt) {
+            TranslateResult::Mapped { frame, flags, .. } => Ok((frame, flags)),
+            TranslateResult::NotMapped => Err(TranslateError::PageNotMapped),
+            TranslateResult::InvalidFrameAddress(addr) => {
+                Err(TranslateError::InvalidFrameAddress(addr))
+            }
+        }
+    }
+
+    /// Only called on pages checked by `check_range`, so it can't fail
+    fn unmap_page<S>(&mut self, virt: VirtAddr, owned: bool)
+    where
+        S: PageSize,
+        OffsetPageTable<'a>: Mapper<S>,
+        BitmapFrameAllocator: FrameDeallocator<S>,
+    {
+        let (frame, flush) = self
+            .mapper
+            .unmap(Page::<S>::containing_address(virt))
+            .expect("Page was checked to be mapped");
+        flush.flush();
+
+        if owned {
+            unsafe { self.frame_allocator.deallocate_frame(frame) };
+        }
+    }
+
+    /// Only called on pages checked by `check_range`, so it can't fail
+    fn update_flags<S>(&mut self, virt: VirtAddr, flags: PageTableFlags)
+    where
+        S: PageSize,
+        OffsetPageTable<'a>: Mapper<S>,
+    {
+        unsafe {
+            self.mapper
+                .update_flags(Page::<S>::containing_address(virt), flags)
+                .expect("Page was checked to be mapped")
+                .flush();
+        }
+    }
+
+    fn map_range_inner(
+        &mut self,
+        start: VirtAddr,
+        phys: Option<PhysAddr>,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        assert!(
+            start.is_aligned(Size4KiB::SIZE),
+            "Unaligned virtual address"
+        );
+        assert!(
+            phys.is_none_or(|phys| phys.is_aligned(Size4KiB::SIZE)),
+            "Unaligned physical address"
+        );
+
+        let flags = match phys {
+            Some(_) => flags | BORROWED,
+            None => flags,
+        };
+
+        let size = size.next_multiple_of(Size4KiB::SIZE);
+        let mut offset = 0;
+        while offset < size {
+            let phys = phys.map(|phys| phys + offset);
+            match self.map_largest_page(start + offset, phys, size - offset, flags) {
+                Ok(page_size) => offset += page_size,
+                Err(e) => {
+                    // Don't leave the range half mapped
+                    self.unmap_range(start, offset)
+                        .expect("Pages were mapped just now");
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Maps the largest page that fits at `virt` and in `remaining` bytes, and returns its size
+    fn map_largest_page(
+        &mut self,
+        virt: VirtAddr,
+        phys: Option<PhysAddr>,
+        remaining: u64,
+        flags: PageTableFlags,
+    ) -> Result<u64, MapToError<Size4KiB>> {
+        let fits = |page_size: u64| {
+            remaining >= page_size
+                && virt.is_aligned(page_size)
+                && phys.is_none_or(|phys| phys.is_aligned(page_size))
+        };
+
+        // Page tables emptied by `unmap_range` stay in place, and a huge page can't
+        // replace them, so those addresses fall back to smaller pages
+        let or_smaller = |result| match result {
+            Err(MapToError::PageAlreadyMapped(_)) => Ok(false),
+            result => result,
+        };
+
+        if self.supports_1gib_pages
+            && fits(Size1GiB::SIZE)
+            && or_smaller(self.map_page::<Size1GiB>(virt, phys, flags))?
+        {
+            Ok(Size1GiB::SIZE)
+        } else if fits(Size2MiB::SIZE) && or_smaller(self.map_page::<Size2MiB>(virt, phys, flags))?
+        {
+            Ok(Size2MiB::SIZE)
+        } else if self.map_page::<Size4KiB>(virt, phys, flags)? {
+            Ok(Size4KiB::SIZE)
+        } else {
+            Err(MapToError::FrameAllocationFailed)
+        }
+    }
+
+    /// Maps a single page at `virt`, to `phys` or to a new frame
+    ///
+    /// Returns false if no frame of the right size could be allocated
+    fn map_page<S>(
+        &mut self,
+        virt: VirtAddr,
+        phys: Option<PhysAddr>,
+        flags: PageTableFlags,
+    ) -> Result<bool, MapToError<Size4KiB>>
+    where
+        S: PageSize,
+        OffsetPageTable<'a>: Mapper<S>,
+        BitmapFrameAllocator: FrameAllocator<S> + FrameDeallocator<S>,
+    {
+        let frame = match phys {
+            Some(phys) => PhysFrame::<S>::containing_address(phys),
+            None => match self.frame_allocator.allocate_frame() {
+                Some(frame) => frame,
+                None => return Ok(false),
+            },
+        };
+        let page = Page::<S>::containing_address(virt);
+
+        let result = unsafe {
+            self.mapper.map_to_with_table_flags(
+                page,
+                frame,
+                flags,
+                TABLE_FLAGS,
+                &mut self.frame_allocator,
+            )
+        };
+        match result {
+            Ok(flush) => {
+                flush.flush();
+                Ok(true)
+            }
+            Err(e) => {
+                if phys.is_none() {
+                    unsafe { self.frame_allocator.deallocate_frame(frame) };
+                }
+                Err(to_4kib_error(e))
+            }
+        }
+    }
+
+    /// Whether the CPU supports 1GiB pages, 2MiB pages are always available in long mode
+    pub fn supports_1gib_pages(&self) -> bool {
+        self.supports_1gib_pages
+    }
+
+    pub fn frame_allocator(&mut self) -> &mut BitmapFrameAllocator {
+        &mut self.frame_allocator
+    }
+}
+
+impl<'a> PageAllocator<'a> {
+    /// Reserves the regions that are in use before the kernel starts allocating address space
+    fn init_address_space(
+        memmap: &[NonNullPtr<MemmapEntry>],
+        physical_memory_offset: VirtAddr,
+    ) -> AddressSpace {
+        let mut address_space = AddressSpace::new(
+            VirtAddr::new(VMALLOC_START as u64),
+            VirtAddr::new(VMALLOC_END as u64),
+        );
+
+        // Limine maps at least the first 4GiB, and every memory map entry above that
+        let direct_map_size = memmap
+            .iter()
+            .map(|entry| entry.base + entry.len)
+            .fold(4 * Size1GiB::SIZE, u64::max)
+            .next_multiple_of(Size1GiB::SIZE);
+
+        // The linker script places the kernel in the top 2GiB
+        let kernel_start = VirtAddr::new(0xFFFF_FFFF_8000_0000);
+
+        for (start, size, kind) in [
+            (kernel_start, 2 * Size1GiB::SIZE, RegionKind::KernelImage),
+            (
+                physical_memory_offset,
+                direct_map_size,
+                RegionKind::DirectMap,
+            ),
+            (
+                VirtAddr::new(HEAP_START as u64),
+                HEAP_MAX_SIZE as u64,
+                RegionKind::Heap,
+            ),
+        ] {
+            address_space
+                .reserve(start, size, kind)
+                .expect("Fixed regions overlap");
+        }
+
+        address_space
+    }
+
+    /// # Safety
+    ///
+    /// Marked as safe to limit scope of unsafe, as this is a private function
+    /// called by an unsafe function
+    ///
+    /// Caller must ensure that the entire physical memory is mapped to the virtual
+    /// memory at the physical_memory_offset. This function should only be called once
+    fn init_mapper(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+        let level_4_table = Self::active_level_4_table(physical_memory_offset);
+
+        unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset) }
+    }
+
+    /// Returns the active level 4 page table
+    ///
+    /// Marked as safe to limit scope of unsafe, as this is a private function
+    /// called by an unsafe function
+    ///
+    /// # Safety
+    /// Caller must ensure that the entire physical memory is mapped to the virtual
+    /// memory at the physical_memory_offset. This function should only be called once
+    /// to avoid multiple mutable references to the same memory
+    fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+        let (level_4_table_frame, _) = Cr3::read();
+
+        let physical_address = level_4_table_frame.start_address();
+        let virtual_address = physical_memory_offset + physical_address.as_u64();
+
+        let page_table_ptr: *mut PageTable = virtual_address.as_mut_ptr();
+
+        unsafe { &mut *page_table_ptr }
+    }
+}
+
+/// Mapping errors of huge pages, with the frame converted to the 4KiB frame it starts with
+fn to_4kib_error<S: PageSize>(error: MapToError<S>) -> MapToError<Size4KiB> {
+    match error {
+        MapToError::FrameAllocationFailed => MapToError::FrameAllocationFailed,
+        MapToError::ParentEntryHugePage => MapToError::ParentEntryHugePage,
+        MapToError::PageAlreadyMapped(frame) => {
+            MapToError::PageAlreadyMapped(PhysFrame::containing_address(frame.start_address()))
+        }
+    }
+}
+
+/// Checks the "1GB pages" bit of the extended CPUID features
+#[allow(unused_unsafe)]
+fn supports_1gib_pages() -> bool {
+    const EXTENDED_FEATURES: u32 = 0x8000_0001;
+    const PAGE_1GB: u32 = 1 << 26;
+
+    let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    max_extended_leaf >= EXTENDED_FEATURES
+        && unsafe { __cpuid(EXTENDED_FEATURES) }.edx & PAGE_1GB != 0
+}
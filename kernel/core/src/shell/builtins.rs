@@ -0,0 +1,98 @@
+//! Commands that belong to the shell itself
+
+use core::fmt::Write;
+
+use libk::alloc::format;
+use libk::vec::Vec;
+
+use super::commands::{self, Args, Builtin};
+use super::{parser, Error, Output, Shell};
+use crate::io::console;
+
+static BUILTINS: [Builtin; 5] = [
+    Builtin {
+        name: "help",
+        usage: "[COMMAND]",
+        description: "Lists the commands, or shows how to use one",
+        run: help,
+    },
+    Builtin {
+        name: "echo",
+        usage: "[TEXT]...",
+        description: "Prints the arguments",
+        run: echo,
+    },
+    Builtin {
+        name: "clear",
+        usage: "",
+        description: "Clears the screen",
+        run: clear,
+    },
+    Builtin {
+        name: "env",
+        usage: "",
+        description: "Lists the variables, which are set with NAME=value",
+        run: env,
+    },
+    Builtin {
+        name: "unset",
+        usage: "NAME...",
+        description: "Removes variables",
+        run: unset,
+    },
+];
+
+pub fn register() {
+    commands::register_all(&BUILTINS);
+}
+
+fn help(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    let name = args.next_optional();
+    args.finish()?;
+    if let Some(name) = name {
+        let command = commands::find(name).ok_or(Error::UnknownCommand)?;
+        writeln!(out, "usage: {}", commands::synopsis(command))?;
+        writeln!(out, "{}", command.description())?;
+        return Ok(());
+    }
+
+    let commands = commands::all();
+    let width = commands.iter().map(|c| c.name().len()).max().unwrap_or(0);
+    for command in commands {
+        writeln!(out, "{:width$}  {}", command.name(), command.description())?;
+    }
+    Ok(())
+}
+
+fn echo(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    writeln!(out, "{}", args.rest().join(" "))?;
+    Ok(())
+}
+
+fn clear(_: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    args.finish()?;
+    console::clear_screen();
+    Ok(())
+}
+
+fn env(shell: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    args.finish()?;
+    for (name, value) in shell.variables() {
+        writeln!(out, "{name}={value}")?;
+    }
+    Ok(())
+}
+
+fn unset(shell: &mut Shell, args: &mut Args, _: &[u8], _: &mut Output) -> Result<(), Error> {
+    let names: Vec<&str> = args.rest();
+    if names.is_empty() {
+        return Err(Error::Usage("missing NAME".into()));
+    }
+    if let Some(name) = names.iter().find(|name| !parser::is_variable_name(name)) {
+        return Err(Error::Usage(format!("`{name}` is not a variable name")));
+    }
+    for name in names {
+        shell.remove_variable(name);
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Registry of the shell's commands
+//!
+//! Subsystems register the commands that belong to them when they are
+//! initialized, usually as a [`Builtin`]. Commands take their arguments through
+//! [`Args`], which reports missing or invalid ones as usage errors
+
+use core::fmt::Display;
+use core::str::FromStr;
+
+use libk::alloc::format;
+use libk::string::String;
+use libk::vec::Vec;
+use libk::Mutex;
+
+use super::{Error, Output, Shell};
+
+/// Sorted by name
+static COMMANDS: Mutex<Vec<&'static dyn Command>> = Mutex::new(Vec::new());
+
+pub trait Command: Sync {
+    fn name(&self) -> &str;
+
+    /// The arguments the command takes, like `FILE [TEXT]...`
+    fn usage(&self) -> &str {
+        ""
+    }
+
+    /// One line saying what the command does
+    fn description(&self) -> &str;
+
+    /// Runs the command with the data given to it through a pipe or `<`
+    ///
+    /// Commands call [`Args::finish`] once they have taken their arguments, before doing
+    /// anything, so that a wrong number of them fails without side effects. Arguments
+    /// that are still left in `args` afterwards are reported as unexpected too
+    fn run(
+        &self,
+        shell: &mut Shell,
+        args: &mut Args,
+        input: &[u8],
+        out: &mut Output,
+    ) -> Result<(), Error>;
+}
+
+/// A command that is a plain function
+pub struct Builtin {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub description: &'static str,
+    pub run: fn(&mut Shell, &mut Args, &[u8], &mut Output) -> Result<(), Error>,
+}
+
+impl Command for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn usage(&self) -> &str {
+        self.usage
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn run(
+        &self,
+        shell: &mut Shell,
+        args: &mut Args,
+        input: &[u8],
+        out: &mut Output,
+    ) -> Result<(), Error> {
+        (self.run)(shell, args, input, out)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlreadyRegistered;
+
+/// Makes `command` available in the shell, its name must not be taken
+pub fn register(command: &'static dyn Command) -> Result<(), AlreadyRegistered> {
+    let mut commands = COMMANDS.lock();
+    match commands.binary_search_by(|c| c.name().cmp(command.name())) {
+        Ok(_) => Err(AlreadyRegistered),
+        Err(index) => {
+            commands.insert(index, command);
+            Ok(())
+        }
+    }
+}
+
+/// Registers every command in `commands`, panics if a name is taken
+pub fn register_all(commands: &'static [Builtin]) {
+    for command in commands {
+        register(command).expect("Command names are unique");
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static dyn Command> {
+    let commands = COMMANDS.lock();
+    commands
+        .binary_search_by(|c| c.name().cmp(name))
+        .ok()
+        .map(|index| commands[index])
+}
+
+/// Every registered command, sorted by name
+pub fn all() -> Vec<&'static dyn Command> {
+    COMMANDS.lock().clone()
+}
+
+/// The command's name followed by its usage
+pub fn synopsis(command: &dyn Command) -> String {
+    match command.usage() {
+        "" => String::from(command.name()),
+        usage => format!("{} {usage}", command.name()),
+    }
+}
+
+/// The arguments a command was given, without its own name
+pub struct Args<'a> {
+    args: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(args: &'a [String]) -> Self {
+        Self {
+            args: args.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Removes `flag`, like `-r`, from the arguments, returns whether it was there
+    pub fn flag(&mut self, flag: &str) -> bool {
+        let len = self.args.len();
+        self.args.retain(|arg| *arg != flag);
+        self.args.len() != len
+    }
+
+    /// Takes the next argument, `name` says what it is in the error if it is missing
+    pub fn next(&mut self, name: &str) -> Result<&'a str, Error> {
+        self.next_optional()
+            .ok_or_else(|| Error::Usage(format!("missing {name}")))
+    }
+
+    pub fn next_optional(&mut self) -> Option<&'a str> {
+        if self.args.is_empty() {
+            None
+        } else {
+            Some(self.args.remove(0))
+        }
+    }
+
+    /// Takes the next argument and parses it
+    pub fn parse<T>(&mut self, name: &str) -> Result<T, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let arg = self.next(name)?;
+        parse(arg, name)
+    }
+
+    pub fn parse_optional<T>(&mut self, name: &str) -> Result<Option<T>, Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.next_optional().map(|arg| parse(arg, name)).transpose()
+    }
+
+    /// Takes all the arguments that are left
+    pub fn rest(&mut self) -> Vec<&'a str> {
+        core::mem::take(&mut self.args)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Fails if any arguments weren't taken
+    pub fn finish(&self) -> Result<(), Error> {
+        match self.args.first() {
+            None => Ok(()),
+            Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+                Err(Error::Usage(format!("unknown option `{arg}`")))
+            }
+            Some(arg) => Err(Error::Usage(format!("unexpected argument `{arg}`"))),
+        }
+    }
+}
+
+fn parse<T>(arg: &str, name: &str) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    arg.parse()
+        .map_err(|e| Error::Usage(format!("invalid {name} `{arg}`: {e}")))
+}
@@ -0,0 +1,314 @@
+//! The kernel shell
+//!
+//! Lines are parsed by [`parser`], and the commands in them are looked up in the
+//! registry in [`commands`]. Commands write into an [`Output`], so what they print
+//! can be sent to the terminal, a file, or the next command in a pipeline
+
+mod builtins;
+pub mod commands;
+pub mod parser;
+
+use crate::fs::vfs;
+use crate::*;
+use alloc::collections::BTreeMap;
+use libk::alloc::format;
+use libk::fmt;
+use libk::io;
+use libk::io::line_editor::{Completions, LineEditor};
+use libk::string::String;
+use libk::vec::Vec;
+
+use self::commands::Args;
+use self::parser::{Condition, Operator, Redirect};
+
+/// Number of lines kept in the history
+const HISTORY_LEN: usize = 64;
+
+/// Exit status of a command that succeeded
+pub const SUCCESS: u8 = 0;
+/// Exit status of a command that failed
+pub const FAILURE: u8 = 1;
+/// Exit status for wrong arguments or syntax errors
+pub const USAGE: u8 = 2;
+/// Exit status for commands that don't exist
+pub const NOT_FOUND: u8 = 127;
+
+/// Registers the commands that belong to the shell itself
+///
+/// Called by kernel::init by default
+pub fn init() {
+    builtins::register();
+}
+
+pub fn run_shell() {
+    println!();
+    let mut shell = Shell::new();
+    let mut editor = LineEditor::new(HISTORY_LEN);
+    editor.set_completer(complete);
+    loop {
+        let line = editor.read_line(&format!("{} $ ", vfs::cwd()));
+        shell.run_line(&line);
+    }
+}
+
+/// Why a command failed
+#[derive(Debug)]
+pub enum Error {
+    /// The arguments don't match the command's usage, holds what is wrong with them
+    Usage(String),
+    UnknownCommand,
+    Io(io::Error),
+    /// Any other failure, described by the message
+    Failed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message) => f.write_str(message),
+            Error::UnknownCommand => write!(f, "command not found"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl Error {
+    /// The exit status of a command that failed with this error
+    pub fn status(&self) -> u8 {
+        match self {
+            Error::Usage(_) => USAGE,
+            Error::UnknownCommand => NOT_FOUND,
+            Error::Io(_) | Error::Failed(_) => FAILURE,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Writing to an [`Output`] can't fail, this only lets `write!` be used with `?`
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Error::Io(io::Error::WriteError)
+    }
+}
+
+/// What a command prints
+#[derive(Debug, Default)]
+pub struct Output {
+    bytes: Vec<u8>,
+}
+
+impl Output {
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+impl fmt::Write for Output {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The state kept between the lines given to the shell
+#[derive(Debug)]
+pub struct Shell {
+    variables: BTreeMap<String, String>,
+    /// Exit status of the last pipeline, `$?`
+    status: u8,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            variables: BTreeMap::new(),
+            status: SUCCESS,
+        }
+    }
+
+    /// Runs every command in `line`, returns the exit status of the last one that ran
+    ///
+    /// Errors are printed to stderr
+    pub fn run_line(&mut self, line: &str) -> u8 {
+        let list = match parser::parse(line) {
+            Ok(list) => list,
+            Err(e) => {
+                eprintln!("sh: {e}");
+                self.status = USAGE;
+                return self.status;
+            }
+        };
+
+        for (condition, pipeline) in &list {
+            let run = match condition {
+                Condition::Always => true,
+                Condition::IfSucceeded => self.status == SUCCESS,
+                Condition::IfFailed => self.status != SUCCESS,
+            };
+            if run {
+                self.status = self.run_pipeline(pipeline);
+            }
+        }
+        self.status
+    }
+
+    /// Exit status of the last pipeline
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    pub fn variable(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(format!("{}", self.status)),
+            name => self.variables.get(name).cloned(),
+        }
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: String) {
+        self.variables.insert(String::from(name), value);
+    }
+
+    pub fn remove_variable(&mut self, name: &str) {
+        self.variables.remove(name);
+    }
+
+    /// Every variable that was set, sorted by name
+    pub fn variables(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Runs the commands, giving each the output of the one before it
+    ///
+    /// Returns the exit status of the last command
+    fn run_pipeline(&mut self, pipeline: &[parser::Command]) -> u8 {
+        let mut data = Vec::new();
+        let mut status = SUCCESS;
+        for command in pipeline {
+            (status, data) = match self.run_command(command, data) {
+                Ok(output) => (SUCCESS, output),
+                Err(status) => (status, Vec::new()),
+            };
+        }
+
+        if !data.is_empty() {
+            print!("{}", String::from_utf8_lossy(&data));
+        }
+        status
+    }
+
+    /// Runs `command` with `input`, returns its output or its exit status if it failed
+    fn run_command(&mut self, command: &parser::Command, input: Vec<u8>) -> Result<Vec<u8>, u8> {
+        // Leading `NAME=value` words set variables
+        let mut words = command.words.as_slice();
+        while let Some((name, value)) = words.first().and_then(|word| word.as_assignment()) {
+            let value = value.expand(|name| self.variable(name));
+            self.set_variable(name, value);
+            words = &words[1..];
+        }
+
+        let args: Vec<String> = words
+            .iter()
+            .map(|word| word.expand(|name| self.variable(name)))
+            .collect();
+
+        self.run_redirected(&args, &command.redirects, input)
+            .map_err(|e| {
+                let name = args.first().map_or("sh", String::as_str);
+                eprintln!("{name}: {e}");
+                if let (Error::Usage(_), Some(command)) = (&e, commands::find(name)) {
+                    eprintln!("usage: {}", commands::synopsis(command));
+                }
+                e.status()
+            })
+    }
+
+    fn run_redirected(
+        &mut self,
+        args: &[String],
+        redirects: &[Redirect],
+        mut input: Vec<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        // Like in other shells, every file is opened, but only the last of each
+        // direction is used
+        let mut output_file = None;
+        for redirect in redirects {
+            let path = redirect.file.expand(|name| self.variable(name));
+            match redirect.operator {
+                Operator::Input => input = vfs::read(&path)?,
+                Operator::Append => output_file = Some(vfs::append(&path)?),
+                _ => output_file = Some(vfs::create(&path)?),
+            }
+        }
+
+        let mut output = Output::default();
+        if let Some((name, args)) = args.split_first() {
+            let command = commands::find(name).ok_or(Error::UnknownCommand)?;
+            let mut args = Args::new(args);
+            command.run(self, &mut args, &input, &mut output)?;
+            args.finish()?;
+        }
+
+        match output_file {
+            Some(mut file) => {
+                file.write_all(&output.bytes)?;
+                Ok(Vec::new())
+            }
+            None => Ok(output.bytes),
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Completes command names where a command can start, and paths everywhere else
+fn complete(before: &str) -> Completions {
+    let is_separator = |c: char| c.is_whitespace() || "|&;<>".contains(c);
+    let start = before
+        .char_indices()
+        .rfind(|&(_, c)| is_separator(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let word = &before[start..];
+    let command_position = before[..start]
+        .trim_end()
+        .chars()
+        .last()
+        .is_none_or(|c| "|&;".contains(c));
+
+    let candidates = if command_position {
+        commands::all()
+            .into_iter()
+            .map(|command| command.name())
+            .filter(|name| name.starts_with(word))
+            .map(String::from)
+            .collect()
+    } else {
+        // Entries of the directory the word is in, that start with the rest of it
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => word.split_at(i + 1),
+            None => ("", word),
+        };
+        let entries = vfs::read_dir(if dir.is_empty() { "." } else { dir }).unwrap_or_default();
+        entries
+            .into_iter()
+            .filter(|entry| entry.name.starts_with(prefix))
+            .map(|entry| {
+                let slash = if entry.metadata.is_dir() { "/" } else { "" };
+                format!("{dir}{}{slash}", entry.name)
+            })
+            .collect()
+    };
+
+    Completions { start, candidates }
+}
@@ -0,0 +1,319 @@
+//! Parser for the shell's command language
+//!
+//! A line is split into words and operators, which are then grouped like this:
+//!
+//! ```text
+//! list     = pipeline { (";" | "&&" | "||") pipeline } [ ";" ]
+//! pipeline = command { "|" command }
+//! command  = { word | redirect }
+//! redirect = (">" | ">>" | "<") word
+//! ```
+//!
+//! Text in `'` is taken as it is, `"` still expands variables and lets `\` escape
+//! `"`, `\` and `$`, and outside of quotes `\` escapes any character. Variables,
+//! `$NAME` or `${NAME}`, are kept in the words so they are expanded only when
+//! the command runs
+
+use core::fmt;
+use core::iter::Peekable;
+use core::str::Chars;
+use libk::string::String;
+use libk::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    UnterminatedQuote,
+    /// A `${` without its `}`
+    UnterminatedVariable,
+    /// A `\` at the end of the line
+    TrailingEscape,
+    /// An operator without a command on one of its sides
+    MissingCommand(Operator),
+    /// A redirection without a file name after it
+    MissingFileName(Operator),
+    /// A character that means something to other shells, but not to this one
+    Unsupported(char),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote => write!(f, "unterminated quote"),
+            ParseError::UnterminatedVariable => write!(f, "missing `}}` after `${{`"),
+            ParseError::TrailingEscape => write!(f, "nothing to escape after `\\`"),
+            ParseError::MissingCommand(op) => write!(f, "missing command next to `{op}`"),
+            ParseError::MissingFileName(op) => write!(f, "missing file name after `{op}`"),
+            ParseError::Unsupported(c) => write!(f, "`{c}` is not supported"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Pipe,
+    And,
+    Or,
+    Semicolon,
+    /// `<`
+    Input,
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Pipe => "|",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::Semicolon => ";",
+            Operator::Input => "<",
+            Operator::Output => ">",
+            Operator::Append => ">>",
+        })
+    }
+}
+
+/// A word, as pieces of text and the variables between them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word {
+    pub parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Text(String),
+    Variable(String),
+}
+
+impl Word {
+    /// The word with its variables replaced, by nothing if `lookup` doesn't know them
+    pub fn expand(&self, lookup: impl Fn(&str) -> Option<String>) -> String {
+        let mut expanded = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => expanded.push_str(text),
+                Part::Variable(name) => expanded.extend(lookup(name)),
+            }
+        }
+        expanded
+    }
+
+    /// Splits `NAME=value` into the name and the value
+    ///
+    /// Only words where the name and `=` are unquoted text count
+    pub fn as_assignment(&self) -> Option<(&str, Word)> {
+        let Some(Part::Text(text)) = self.parts.first() else {
+            return None;
+        };
+        let (name, value) = text.split_once('=')?;
+        if !is_variable_name(name) {
+            return None;
+        }
+
+        let mut parts = Vec::from([Part::Text(String::from(value))]);
+        parts.extend_from_slice(&self.parts[1..]);
+        Some((name, Word { parts }))
+    }
+
+    fn push(&mut self, c: char) {
+        match self.parts.last_mut() {
+            Some(Part::Text(text)) => text.push(c),
+            _ => self.parts.push(Part::Text(String::from(c))),
+        }
+    }
+}
+
+/// When a pipeline runs, depending on how the one before it went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    /// After `&&`
+    IfSucceeded,
+    /// After `||`
+    IfFailed,
+}
+
+/// Commands connected with `|`
+pub type Pipeline = Vec<Command>;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Command {
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+impl Command {
+    fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.redirects.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    /// One of `Input`, `Output` and `Append`
+    pub operator: Operator,
+    pub file: Word,
+}
+
+/// Parses a line into the pipelines in it, each with when it should run
+pub fn parse(line: &str) -> Result<Vec<(Condition, Pipeline)>, ParseError> {
+    let mut list = Vec::new();
+    let mut condition = Condition::Always;
+    let mut pipeline = Pipeline::new();
+    let mut command = Command::default();
+    // The operator that ended the last command, which needs another command after it
+    let mut last_operator = None;
+
+    let mut tokens = tokenize(line)?.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => command.words.push(word),
+            Token::Operator(operator @ (Operator::Input | Operator::Output | Operator::Append)) => {
+                let Some(Token::Word(file)) = tokens.next() else {
+                    return Err(ParseError::MissingFileName(operator));
+                };
+                command.redirects.push(Redirect { operator, file });
+            }
+            Token::Operator(operator) => {
+                if command.is_empty() {
+                    return Err(ParseError::MissingCommand(operator));
+                }
+                pipeline.push(core::mem::take(&mut command));
+                last_operator = Some(operator);
+
+                let next_condition = match operator {
+                    Operator::Pipe => continue,
+                    Operator::And => Condition::IfSucceeded,
+                    Operator::Or => Condition::IfFailed,
+                    _ => Condition::Always,
+                };
+                list.push((condition, core::mem::take(&mut pipeline)));
+                condition = next_condition;
+                continue;
+            }
+        }
+        last_operator = None;
+    }
+
+    match last_operator {
+        // Only `;` can end a line
+        Some(operator) if operator != Operator::Semicolon => {
+            Err(ParseError::MissingCommand(operator))
+        }
+        _ => {
+            if !command.is_empty() {
+                pipeline.push(command);
+                list.push((condition, pipeline));
+            }
+            Ok(list)
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Word(Word),
+    Operator(Operator),
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    // None between words, so quotes can make empty words
+    let mut word: Option<Word> = None;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => tokens.extend(word.take().map(Token::Word)),
+            '|' | '&' | ';' | '<' | '>' => {
+                tokens.extend(word.take().map(Token::Word));
+                let doubled = chars.next_if_eq(&c).is_some();
+                let operator = match (c, doubled) {
+                    ('|', false) => Operator::Pipe,
+                    ('|', true) => Operator::Or,
+                    ('&', true) => Operator::And,
+                    (';', false) => Operator::Semicolon,
+                    ('<', false) => Operator::Input,
+                    ('>', false) => Operator::Output,
+                    ('>', true) => Operator::Append,
+                    _ => return Err(ParseError::Unsupported(c)),
+                };
+                tokens.push(Token::Operator(operator));
+            }
+            '\\' => {
+                let escaped = chars.next().ok_or(ParseError::TrailingEscape)?;
+                word.get_or_insert_with(Word::default).push(escaped);
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(Word::default);
+                loop {
+                    match chars.next().ok_or(ParseError::UnterminatedQuote)? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(Word::default);
+                loop {
+                    match chars.next().ok_or(ParseError::UnterminatedQuote)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or(ParseError::UnterminatedQuote)? {
+                            c @ ('"' | '\\' | '$') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        '$' => variable(&mut chars, word)?,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '$' => variable(&mut chars, word.get_or_insert_with(Word::default))?,
+            c => word.get_or_insert_with(Word::default).push(c),
+        }
+    }
+
+    tokens.extend(word.map(Token::Word));
+    Ok(tokens)
+}
+
+/// Adds the variable after a `$` to `word`, or the `$` itself if no name follows
+fn variable(chars: &mut Peekable<Chars>, word: &mut Word) -> Result<(), ParseError> {
+    let mut name = String::new();
+    if chars.next_if_eq(&'{').is_some() {
+        loop {
+            match chars.next().ok_or(ParseError::UnterminatedVariable)? {
+                '}' => break,
+                c => name.push(c),
+            }
+        }
+    } else if chars.next_if_eq(&'?').is_some() {
+        name.push('?');
+    } else {
+        while let Some(c) = chars.next_if(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+            name.push(c);
+        }
+    }
+
+    if is_variable_name(&name) || name == "?" {
+        word.parts.push(Part::Variable(name));
+    } else {
+        // Not a variable after all, so it stays text
+        word.push('$');
+        name.chars().for_each(|c| word.push(c));
+    }
+    Ok(())
+}
+
+pub fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
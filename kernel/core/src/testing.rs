@@ -0,0 +1,77 @@
+//! Kernel side of the test framework
+//!
+//! Tests run inside QEMU, results are printed over the serial port, and QEMU is
+//! closed through the isa-debug-exit device with an exit code telling the
+//! runner whether the tests passed
+
+use crate::{hlt_loop, serial_print, serial_println};
+use libk::panic::PanicInfo;
+use x86_64::instructions::port::Port;
+
+/// The isa-debug-exit device QEMU is started with, see `builder/runner.py`
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// QEMU exits with `(code << 1) | 1`, so these can't be confused with QEMU's own failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+    unsafe {
+        Port::new(ISA_DEBUG_EXIT_PORT).write(exit_code as u32);
+    }
+
+    // Only reached when not running under QEMU
+    hlt_loop()
+}
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T> Testable for T
+where
+    T: Fn(),
+{
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) -> ! {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+
+    exit_qemu(QemuExitCode::Success)
+}
+
+/// Panic handler for test builds, the test that was running failed
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+
+    exit_qemu(QemuExitCode::Failed)
+}
+
+/// Panic handler for tests that are supposed to panic
+///
+/// Such tests are built with `harness = false`, run the code that should panic
+/// from `_start`, and call `should_panic_failed` if it returns
+pub fn should_panic_handler(_info: &PanicInfo) -> ! {
+    serial_println!("[ok]");
+
+    exit_qemu(QemuExitCode::Success)
+}
+
+pub fn should_panic_failed() -> ! {
+    serial_println!("[test did not panic]");
+
+    exit_qemu(QemuExitCode::Failed)
+}
@@ -0,0 +1,87 @@
+//! High precision event timer
+//!
+//! Only the main counter is used, as a clocksource. The timers it can raise
+//! interrupts with are left disabled
+
+use x86_64::{
+    structures::paging::{PageSize, PageTableFlags, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+use crate::{acpi, memory};
+
+const REG_CAPABILITIES: u64 = 0x00;
+const REG_CONFIG: u64 = 0x10;
+const REG_MAIN_COUNTER: u64 = 0xF0;
+
+const CAPABILITY_64_BIT: u64 = 1 << 13;
+const CONFIG_ENABLE: u64 = 1 << 0;
+
+const FEMTOS_PER_SECOND: u64 = 1_000_000_000_000_000;
+
+pub struct Hpet {
+    registers: VirtAddr,
+    /// Frequency of the main counter in Hz
+    pub frequency: u64,
+}
+
+impl Hpet {
+    /// Maps and enables the HPET from the ACPI HPET table, if there is one with a
+    /// 64 bit counter
+    pub fn new() -> Option<Self> {
+        let table = acpi::find_table(b"HPET")?;
+        let address = u64::from_le_bytes(table.get(44..52)?.try_into().ok()?);
+
+        let registers = memory::PAGE_ALLOCATOR
+            .lock()
+            .as_mut()?
+            .map_physical(
+                PhysAddr::new(address),
+                0x400,
+                PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+            )
+            .ok()?;
+        let mut hpet = Hpet {
+            registers,
+            frequency: 0,
+        };
+
+        let capabilities = hpet.read(REG_CAPABILITIES);
+        let period_femtos = capabilities >> 32;
+        if capabilities & CAPABILITY_64_BIT == 0 || period_femtos == 0 {
+            hpet.unmap();
+            return None;
+        }
+        hpet.frequency = FEMTOS_PER_SECOND / period_femtos;
+
+        let config = hpet.read(REG_CONFIG);
+        hpet.write(REG_CONFIG, config | CONFIG_ENABLE);
+        Some(hpet)
+    }
+
+    /// Unmaps the registers of an HPET that won't be used
+    pub fn unmap(self) {
+        let page = self.registers.align_down(Size4KiB::SIZE);
+        if let Some(page_allocator) = memory::PAGE_ALLOCATOR.lock().as_mut() {
+            page_allocator
+                .vfree(page)
+                .expect("Unable to unmap the HPET registers");
+        }
+    }
+
+    pub fn counter(&self) -> u64 {
+        self.read(REG_MAIN_COUNTER)
+    }
+
+    fn read(&self, register: u64) -> u64 {
+        unsafe { (self.registers + register).as_ptr::<u64>().read_volatile() }
+    }
+
+    fn write(&mut self, register: u64, value: u64) {
+        unsafe {
+            (self.registers + register)
+                .as_mut_ptr::<u64>()
+                .write_volatile(value)
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Time keeping
+//!
+//! The PIT drives a tick counter, which is the first clocksource and the one the
+//! others are calibrated against. Once calibrated, the TSC or the HPET takes over
+//! reading the time, as they have a much finer resolution than a tick. Timers are
+//! kept in a heap ordered by deadline, and run from the tick interrupt. The
+//! wall-clock time is read from the RTC at boot, and counted on by the monotonic clock
+
+pub mod hpet;
+pub mod pit;
+pub mod rtc;
+pub mod tsc;
+
+use alloc::collections::BinaryHeap;
+use core::cmp::Ordering as CmpOrdering;
+use core::fmt::Write;
+use hpet::Hpet;
+use libk::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use libk::time::{DateTime, Duration, Instant, SystemTime};
+use libk::{Mutex, Once};
+use x86_64::instructions::{hlt, interrupts::without_interrupts};
+
+use crate::shell::commands::{self, Args, Builtin};
+use crate::shell::{Error, Output, Shell};
+use crate::{eprintln, interrupt};
+
+/// Rate of the tick interrupt
+pub const TICK_HZ: u64 = 1000;
+
+/// The PIT can only divide its frequency by whole numbers, so ticks are slightly
+/// shorter than `1 / TICK_HZ` seconds
+const PIT_DIVISOR: u16 = ((pit::BASE_FREQUENCY + TICK_HZ / 2) / TICK_HZ) as u16;
+
+/// Ticks the TSC and HPET are measured over
+const CALIBRATION_TICKS: u64 = 50;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static CLOCKSOURCE: Once<Clocksource> = Once::new();
+static HPET: Once<Hpet> = Once::new();
+
+static TIMERS: Mutex<BinaryHeap<Timer>> = Mutex::new(BinaryHeap::new());
+static NEXT_TIMER_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClocksourceKind {
+    Pit,
+    Tsc,
+    Hpet,
+}
+
+/// A counter running at `frequency`, which read `base_count` when the tick
+/// counter was at `base_nanos`
+struct Clocksource {
+    kind: ClocksourceKind,
+    frequency: u64,
+    base_count: u64,
+    base_nanos: u64,
+}
+
+impl Clocksource {
+    fn nanos(&self) -> u64 {
+        let count = match self.kind {
+            ClocksourceKind::Tsc => tsc::read(),
+            ClocksourceKind::Hpet => HPET.get().map_or(0, Hpet::counter),
+            ClocksourceKind::Pit => return ticks_to_nanos(TICKS.load(Ordering::Relaxed)),
+        };
+
+        let elapsed = count.wrapping_sub(self.base_count) as u128;
+        self.base_nanos + (elapsed * NANOS_PER_SECOND / self.frequency as u128) as u64
+    }
+}
+
+/// Starts the tick interrupt, picks the most precise clocksource available, sets
+/// the wall-clock time from the RTC, and registers the time commands
+///
+/// # Safety
+///
+/// Should only be called once, by kernel::init after interrupt::init
+pub unsafe fn init() {
+    unsafe { pit::start(PIT_DIVISOR) };
+    interrupt::register_irq(0, tick).expect("Unable to register the timer IRQ");
+    libk::time::set_clock(now_nanos);
+
+    calibrate();
+
+    let date = rtc::read();
+    match date.to_system_time() {
+        Some(now) => libk::time::set_system_time(now),
+        None => eprintln!("RTC has an invalid date: {date}"),
+    }
+
+    commands::register_all(&COMMANDS);
+}
+
+/// Nanoseconds since the tick interrupt was started
+pub fn now_nanos() -> u64 {
+    match CLOCKSOURCE.get() {
+        Some(clocksource) => clocksource.nanos(),
+        None => ticks_to_nanos(TICKS.load(Ordering::Relaxed)),
+    }
+}
+
+/// Number of tick interrupts so far
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn clocksource() -> ClocksourceKind {
+    CLOCKSOURCE
+        .get()
+        .map_or(ClocksourceKind::Pit, |clocksource| clocksource.kind)
+}
+
+fn ticks_to_nanos(ticks: u64) -> u64 {
+    (ticks as u128 * PIT_DIVISOR as u128 * NANOS_PER_SECOND / pit::BASE_FREQUENCY as u128) as u64
+}
+
+/// Measures the TSC and HPET against the tick counter, and switches to the TSC if
+/// it is invariant, or to the HPET if its frequency is right
+fn calibrate() {
+    let hpet = Hpet::new();
+    let tsc_is_invariant = tsc::is_invariant();
+    if hpet.is_none() && !tsc_is_invariant {
+        return;
+    }
+
+    let start_tick = wait_for_tick();
+    let tsc_start = tsc::read();
+    let hpet_start = hpet.as_ref().map(Hpet::counter);
+
+    let mut end_tick = start_tick;
+    while end_tick - start_tick < CALIBRATION_TICKS {
+        end_tick = wait_for_tick();
+    }
+    let tsc_end = tsc::read();
+    let hpet_end = hpet.as_ref().map(Hpet::counter);
+
+    let base_nanos = ticks_to_nanos(end_tick);
+    let elapsed = (base_nanos - ticks_to_nanos(start_tick)) as u128;
+    let frequency = |start: u64, end: u64| {
+        (end.wrapping_sub(start) as u128 * NANOS_PER_SECOND / elapsed) as u64
+    };
+
+    let clocksource = if tsc_is_invariant {
+        if let Some(hpet) = hpet {
+            hpet.unmap();
+        }
+        Clocksource {
+            kind: ClocksourceKind::Tsc,
+            frequency: frequency(tsc_start, tsc_end),
+            base_count: tsc_end,
+            base_nanos,
+        }
+    } else {
+        let (Some(hpet), Some(hpet_start), Some(hpet_end)) = (hpet, hpet_start, hpet_end) else {
+            return;
+        };
+
+        // The reported frequency is exact, but a broken HPET can report the wrong one
+        let measured = frequency(hpet_start, hpet_end);
+        if measured.abs_diff(hpet.frequency) > hpet.frequency / 100 {
+            hpet.unmap();
+            return;
+        }
+
+        let frequency = hpet.frequency;
+        HPET.call_once(|| hpet);
+        Clocksource {
+            kind: ClocksourceKind::Hpet,
+            frequency,
+            base_count: hpet_end,
+            base_nanos,
+        }
+    };
+
+    if clocksource.frequency > 0 {
+        CLOCKSOURCE.call_once(|| clocksource);
+    }
+}
+
+/// Waits for the next tick and returns the tick count, so measurements start right
+/// at the beginning of a tick
+fn wait_for_tick() -> u64 {
+    let start = TICKS.load(Ordering::Relaxed);
+    loop {
+        let ticks = TICKS.load(Ordering::Relaxed);
+        if ticks != start {
+            return ticks;
+        }
+        hlt();
+    }
+}
+
+/// Called from the tick interrupt with the context it was added with. It must not
+/// allocate, as the interrupted code may hold the heap's lock
+pub type TimerCallback = fn(usize);
+
+/// Identifies a timer added by `add_timer`, to cancel it with `cancel_timer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer {
+    deadline: Instant,
+    id: TimerId,
+    callback: TimerCallback,
+    context: usize,
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    /// Reversed, so the max-heap pops the earliest deadline first, timers with the
+    /// same deadline run in the order they were added
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (other.deadline, other.id.0).cmp(&(self.deadline, self.id.0))
+    }
+}
+
+/// Calls `callback` with `context` from the first tick after `deadline`
+pub fn add_timer(deadline: Instant, callback: TimerCallback, context: usize) -> TimerId {
+    let id = TimerId(NEXT_TIMER_ID.fetch_add(1, Ordering::Relaxed));
+    let timer = Timer {
+        deadline,
+        id,
+        callback,
+        context,
+    };
+
+    without_interrupts(|| TIMERS.lock().push(timer));
+    id
+}
+
+/// Removes a timer that hasn't fired yet, returns whether it was found
+pub fn cancel_timer(id: TimerId) -> bool {
+    without_interrupts(|| {
+        let mut timers = TIMERS.lock();
+        let len = timers.len();
+        timers.retain(|timer| timer.id != id);
+        timers.len() != len
+    })
+}
+
+/// Waits until `duration` has passed
+///
+/// Interrupts must be enabled, any number of callers can sleep at the same time
+pub fn sleep(duration: Duration) {
+    let woken = AtomicBool::new(false);
+    add_timer(
+        Instant::now() + duration,
+        wake,
+        &woken as *const AtomicBool as usize,
+    );
+
+    while !woken.load(Ordering::Acquire) {
+        hlt();
+    }
+}
+
+fn wake(context: usize) {
+    let woken = unsafe { &*(context as *const AtomicBool) };
+    woken.store(true, Ordering::Release);
+}
+
+/// Handler for the PIT
+fn tick(_vector: u8) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    let now = Instant::now();
+
+    loop {
+        // Unlocked while the callback runs, so it can cancel timers
+        let timer = {
+            let mut timers = TIMERS.lock();
+            match timers.peek() {
+                Some(timer) if timer.deadline <= now => timers.pop(),
+                _ => None,
+            }
+        };
+        let Some(timer) = timer else {
+            break;
+        };
+        (timer.callback)(timer.context);
+    }
+}
+
+static COMMANDS: [Builtin; 2] = [
+    Builtin {
+        name: "date",
+        usage: "",
+        description: "Prints the date and time in UTC",
+        run: date,
+    },
+    Builtin {
+        name: "uptime",
+        usage: "",
+        description: "Prints how long ago the kernel started, and the clocksource",
+        run: uptime,
+    },
+];
+
+fn date(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    args.finish()?;
+    writeln!(out, "{} UTC", DateTime::from(SystemTime::now()))?;
+    Ok(())
+}
+
+fn uptime(_: &mut Shell, args: &mut Args, _: &[u8], out: &mut Output) -> Result<(), Error> {
+    args.finish()?;
+    let uptime = Instant::now().since_boot();
+    let seconds = uptime.as_secs();
+    writeln!(
+        out,
+        "Up {}:{:02}:{:02}.{:03}, clocksource {:?}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        uptime.subsec_millis(),
+        clocksource()
+    )?;
+    Ok(())
+}
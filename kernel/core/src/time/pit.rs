@@ -0,0 +1,30 @@
+//! Programmable interval timer
+//!
+//! Channel 0 raises IRQ 0 at a fixed rate, which drives the tick counter
+
+use x86_64::instructions::port::Port;
+
+/// Frequency of the oscillator the PIT divides
+pub const BASE_FREQUENCY: u64 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+
+/// Channel 0, low then high byte of the divisor, mode 2 (rate generator), binary
+const RATE_GENERATOR: u8 = 0x34;
+
+/// Makes channel 0 fire every `divisor` oscillations
+///
+/// # Safety
+///
+/// Should only be called by time::init
+pub unsafe fn start(divisor: u16) {
+    let mut command: Port<u8> = Port::new(COMMAND);
+    let mut channel_0: Port<u8> = Port::new(CHANNEL_0);
+
+    unsafe {
+        command.write(RATE_GENERATOR);
+        channel_0.write(divisor as u8);
+        channel_0.write((divisor >> 8) as u8);
+    }
+}
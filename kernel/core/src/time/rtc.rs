@@ -0,0 +1,118 @@
+//! CMOS real time clock
+//!
+//! Only read once at boot, the monotonic clock counts on from there
+
+use libk::time::DateTime;
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+use crate::acpi;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+const STATUS_B_24_HOUR: u8 = 1 << 1;
+const STATUS_B_BINARY: u8 = 1 << 2;
+const HOURS_PM: u8 = 1 << 7;
+
+/// Offset of the RTC century register in the ACPI FADT
+const FADT_CENTURY: usize = 108;
+
+/// The values of the time registers, as they are stored
+#[derive(PartialEq, Eq)]
+struct RawTime {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: Option<u8>,
+}
+
+/// Reads the current date and time, which is assumed to be UTC
+pub fn read() -> DateTime {
+    let century_register = acpi::find_table(b"FACP")
+        .and_then(|fadt| fadt.get(FADT_CENTURY).copied())
+        .filter(|&register| register != 0);
+
+    without_interrupts(|| {
+        // The registers can change while they are being read, so read them until
+        // they are the same twice in a row
+        let mut time = read_raw(century_register);
+        loop {
+            let again = read_raw(century_register);
+            if again == time {
+                break;
+            }
+            time = again;
+        }
+
+        decode(time, read_register(REG_STATUS_B))
+    })
+}
+
+fn decode(time: RawTime, status_b: u8) -> DateTime {
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let value = |raw: u8| {
+        if binary {
+            raw
+        } else {
+            (raw >> 4) * 10 + (raw & 0x0F)
+        }
+    };
+
+    let mut hour = value(time.hours & !HOURS_PM);
+    if status_b & STATUS_B_24_HOUR == 0 {
+        // 12 AM is midnight and 12 PM is noon
+        let pm = time.hours & HOURS_PM != 0;
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+
+    // Without a century register, the year is assumed to be in this century
+    let century = time.century.map_or(20, value) as u16;
+
+    DateTime {
+        year: century * 100 + value(time.year) as u16,
+        month: value(time.month),
+        day: value(time.day),
+        hour,
+        minute: value(time.minutes),
+        second: value(time.seconds),
+    }
+}
+
+fn read_raw(century_register: Option<u8>) -> RawTime {
+    while read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0 {
+        core::hint::spin_loop();
+    }
+
+    RawTime {
+        seconds: read_register(REG_SECONDS),
+        minutes: read_register(REG_MINUTES),
+        hours: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: century_register.map(read_register),
+    }
+}
+
+fn read_register(register: u8) -> u8 {
+    let mut address: Port<u8> = Port::new(CMOS_ADDRESS);
+    let mut data: Port<u8> = Port::new(CMOS_DATA);
+
+    unsafe {
+        address.write(register);
+        data.read()
+    }
+}
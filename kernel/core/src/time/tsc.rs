@@ -0,0 +1,20 @@
+//! Time stamp counter
+
+use core::arch::x86_64::{__cpuid, _rdtsc};
+
+/// Whether the TSC runs at a constant rate in every power state, so it can be used
+/// as a clocksource
+#[allow(unused_unsafe)]
+pub fn is_invariant() -> bool {
+    const POWER_MANAGEMENT: u32 = 0x8000_0007;
+    const INVARIANT_TSC: u32 = 1 << 8;
+
+    let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    max_extended_leaf >= POWER_MANAGEMENT
+        && unsafe { __cpuid(POWER_MANAGEMENT) }.edx & INVARIANT_TSC != 0
+}
+
+#[allow(unused_unsafe)]
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{
+    constants::HEAP_START,
+    hlt_loop, init,
+    memory::{
+        address_space::{AddressSpace, RegionKind, VmError, GUARD_SIZE},
+        PAGE_ALLOCATOR,
+    },
+};
+use x86_64::{structures::paging::PageTableFlags, VirtAddr};
+
+const WINDOW_START: u64 = 0x_5555_0000_0000;
+const WINDOW_END: u64 = 0x_5555_0010_0000;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+fn address_space() -> AddressSpace {
+    AddressSpace::new(VirtAddr::new(WINDOW_START), VirtAddr::new(WINDOW_END))
+}
+
+#[test_case]
+fn overlapping_reservations_fail() {
+    let mut space = address_space();
+    let start = VirtAddr::new(0x1000_0000);
+    space.reserve(start, 0x4000, RegionKind::Mmio).unwrap();
+
+    assert!(matches!(
+        space.reserve(start + 0x3000u64, 0x1000, RegionKind::Stack),
+        Err(VmError::Overlap(region)) if region.start == start
+    ));
+    space
+        .reserve(start + 0x4000u64, 0x1000, RegionKind::Stack)
+        .unwrap();
+    assert_eq!(space.regions().len(), 2);
+}
+
+#[test_case]
+fn allocations_are_guarded_and_aligned() {
+    let mut space = address_space();
+    let a = space.allocate(0x1800, 0, RegionKind::Vmalloc).unwrap();
+    let b = space
+        .allocate(0x1000, 0x10000, RegionKind::Vmalloc)
+        .unwrap();
+
+    assert!(a.as_u64() >= WINDOW_START + GUARD_SIZE);
+    assert!(b.as_u64() >= a.as_u64() + 0x2000 + GUARD_SIZE);
+    assert!(b.is_aligned(0x10000u64));
+    assert_eq!(space.find(a + 0x1fffu64).unwrap().size, 0x2000);
+    assert!(space.find(a + 0x2000u64).is_none());
+}
+
+#[test_case]
+fn freed_ranges_are_reused() {
+    let mut space = address_space();
+    let a = space.allocate(0x1000, 0, RegionKind::Vmalloc).unwrap();
+    space.allocate(0x1000, 0, RegionKind::Vmalloc).unwrap();
+
+    assert_eq!(space.free(a).unwrap().start, a);
+    assert!(matches!(space.free(a), Err(VmError::NotFound)));
+    assert_eq!(space.allocate(0x1000, 0, RegionKind::Vmalloc).unwrap(), a);
+}
+
+#[test_case]
+fn full_window_fails() {
+    let mut space = address_space();
+    assert!(matches!(
+        space.allocate(WINDOW_END - WINDOW_START, 0, RegionKind::Vmalloc),
+        Err(VmError::OutOfSpace)
+    ));
+}
+
+#[test_case]
+fn kernel_regions_are_reserved() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let space = page_allocator.as_mut().unwrap().address_space();
+
+    let heap = space.find(VirtAddr::new(HEAP_START as u64)).unwrap();
+    assert_eq!(heap.kind, RegionKind::Heap);
+    let code = VirtAddr::from_ptr(address_space as *const ());
+    assert_eq!(space.find(code).unwrap().kind, RegionKind::KernelImage);
+}
+
+#[test_case]
+fn vmalloc_maps_memory() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let free = page_allocator.frame_allocator().free_frames();
+
+    let start = page_allocator.vmalloc(3 * 4096, flags).unwrap();
+    let region: *mut u8 = start.as_mut_ptr();
+    unsafe {
+        region.add(3 * 4096 - 1).write_volatile(7);
+        assert_eq!(region.add(3 * 4096 - 1).read_volatile(), 7);
+    }
+
+    page_allocator.vfree(start).unwrap();
+    assert_eq!(page_allocator.translate(start), None);
+    assert!(page_allocator.address_space().find(start).is_none());
+    // Page tables created for the mapping stay around
+    assert!(page_allocator.frame_allocator().free_frames() <= free);
+    assert!(page_allocator.frame_allocator().free_frames() + 4 >= free);
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{hlt_loop, init, println, serial_println};
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn println_after_init() {
+    println!("println reaches the console and serial sinks");
+}
+
+#[test_case]
+fn serial_println() {
+    serial_println!("serial_println reaches the serial port");
+}
@@ -0,0 +1,108 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{
+    backtrace::{self, Demangle},
+    hlt_loop, init,
+};
+use core::fmt::{self, Write};
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+/// Formats into a fixed buffer, the tests shouldn't depend on the heap
+struct Buffer {
+    bytes: [u8; 128],
+    len: usize,
+}
+
+impl Buffer {
+    fn format(args: fmt::Arguments) -> Self {
+        let mut buffer = Buffer {
+            bytes: [0; 128],
+            len: 0,
+        };
+        buffer.write_fmt(args).unwrap();
+        buffer
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Breakpoints are reported and execution continues
+#[test_case]
+fn breakpoint_returns() {
+    x86_64::instructions::interrupts::int3();
+}
+
+#[test_case]
+fn demangles_legacy_symbols() {
+    let demangled = |name| Buffer::format(format_args!("{}", Demangle(name)));
+
+    assert_eq!(
+        demangled("_ZN19butterscotch_kernel9backtrace5print17h0123456789abcdefE").as_str(),
+        "butterscotch_kernel::backtrace::print"
+    );
+    assert_eq!(
+        demangled("_ZN4core3fmt5write17h0123456789abcdefE.llvm.42").as_str(),
+        "_ZN4core3fmt5write17h0123456789abcdefE.llvm.42"
+    );
+    assert_eq!(
+        demangled("_ZN70_$LT$alloc..vec..Vec$LT$T$C$A$GT$$u20$as$u20$core..ops..drop..Drop$GT$4drop17h0123456789abcdefE").as_str(),
+        "<alloc::vec::Vec<T,A> as core::ops::drop::Drop>::drop"
+    );
+    assert_eq!(demangled("memcpy").as_str(), "memcpy");
+}
+
+#[inline(never)]
+fn symbolized() {}
+
+#[test_case]
+fn symbolizes_kernel_functions() {
+    let address = symbolized as fn() as usize as u64;
+    let (name, offset) = backtrace::symbolize(address + 1).expect("kernel symbols are available");
+
+    assert!(Buffer::format(format_args!("{}", Demangle(name)))
+        .as_str()
+        .ends_with("exceptions::symbolized"));
+    assert_eq!(offset, 1);
+}
+
+#[test_case]
+fn frame_pointers_form_a_chain() {
+    assert!(backtrace::frames(current_rbp()).count() >= 2);
+}
+
+#[inline(always)]
+fn current_rbp() -> u64 {
+    let rbp: u64;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) rbp) };
+    rbp
+}
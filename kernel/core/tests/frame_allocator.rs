@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{hlt_loop, init, memory::PAGE_ALLOCATOR};
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB};
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn counts_add_up() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let frames = page_allocator.as_mut().unwrap().frame_allocator();
+
+    assert!(frames.free_frames() > 0);
+    assert_eq!(
+        frames.free_frames() + frames.used_frames(),
+        frames.usable_frames()
+    );
+}
+
+#[test_case]
+fn freed_frames_are_reused() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let frames = page_allocator.as_mut().unwrap().frame_allocator();
+    let free = frames.free_frames();
+
+    let a: PhysFrame = frames.allocate_frame().unwrap();
+    let b: PhysFrame = frames.allocate_frame().unwrap();
+    assert_ne!(a, b);
+    assert_eq!(frames.free_frames(), free - 2);
+
+    unsafe { frames.deallocate_frame(a) };
+    assert_eq!(frames.free_frames(), free - 1);
+    assert_eq!(frames.allocate_frame(), Some(a));
+
+    unsafe {
+        frames.deallocate_frame(a);
+        frames.deallocate_frame(b);
+    }
+    assert_eq!(frames.free_frames(), free);
+}
+
+#[test_case]
+fn contiguous_allocation_is_aligned() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let frames = page_allocator.as_mut().unwrap().frame_allocator();
+    let free = frames.free_frames();
+
+    // Leave a hole, so the aligned run can't start at the first free frame
+    let hole: PhysFrame = frames.allocate_frame().unwrap();
+    let range = frames.allocate_contiguous(16, 16).unwrap();
+    assert_eq!(range.start.start_address().as_u64() % (16 * 4096), 0);
+    assert_eq!(range.end - range.start, 16);
+    assert_eq!(frames.free_frames(), free - 17);
+
+    unsafe {
+        frames.deallocate_contiguous(range);
+        frames.deallocate_frame(hole);
+    }
+    assert_eq!(frames.free_frames(), free);
+}
+
+#[test_case]
+fn oversized_contiguous_allocation_fails() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let frames = page_allocator.as_mut().unwrap().frame_allocator();
+
+    assert!(frames
+        .allocate_contiguous(frames.usable_frames() + 1, 1)
+        .is_none());
+}
+
+#[test_case]
+fn huge_frames_are_aligned() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let frames = page_allocator.as_mut().unwrap().frame_allocator();
+    let free = frames.free_frames();
+
+    let frame: PhysFrame<Size2MiB> = frames.allocate_frame().unwrap();
+    assert!(frame.start_address().is_aligned(Size2MiB::SIZE));
+    assert_eq!(frames.free_frames(), free - 512);
+
+    unsafe { frames.deallocate_frame(frame) };
+    assert_eq!(frames.free_frames(), free);
+}
@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, vec::Vec};
+use butterscotch_kernel::{
+    constants::{HEAP_DEFAULT_SIZE, HEAP_MAX_SIZE},
+    hlt_loop, init, kernel_allocator,
+};
+use core::mem::size_of;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn simple_allocation() {
+    let a = Box::new(41);
+    let b = Box::new(13);
+    assert_eq!(*a, 41);
+    assert_eq!(*b, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let vec: Vec<u64> = (0..n).collect();
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+/// Allocates more than the whole heap in total, which only works if freed memory is reused
+#[test_case]
+fn freed_memory_is_reused() {
+    for i in 0..HEAP_DEFAULT_SIZE / size_of::<usize>() {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+#[test_case]
+fn long_lived_allocation_survives() {
+    let long_lived = Box::new(1);
+    for i in 0..HEAP_DEFAULT_SIZE / size_of::<usize>() {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+    assert_eq!(*long_lived, 1);
+}
+
+/// Allocations larger than the initial heap grow it
+#[test_case]
+fn heap_grows() {
+    let size = 2 * HEAP_DEFAULT_SIZE;
+    let mut vec: Vec<u8> = Vec::with_capacity(size);
+    vec.resize(size, 0xAB);
+    assert!(vec.iter().all(|&byte| byte == 0xAB));
+
+    let stats = kernel_allocator::stats();
+    assert!(stats.size > size);
+    assert!(stats.allocated >= size);
+    assert!(stats.peak >= stats.allocated);
+}
+
+#[test_case]
+fn heap_stops_at_limit() {
+    let mut vec: Vec<u8> = Vec::new();
+    assert!(vec.try_reserve_exact(HEAP_MAX_SIZE).is_err());
+    assert!(kernel_allocator::stats().size <= HEAP_MAX_SIZE);
+}
@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{hlt_loop, init, interrupt, time};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use libk::time::Duration;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn breakpoint_returns() {
+    x86_64::instructions::interrupts::int3();
+}
+
+#[test_case]
+fn interrupts_are_enabled() {
+    assert!(x86_64::instructions::interrupts::are_enabled());
+}
+
+/// Only returns if the timer interrupt keeps firing
+#[test_case]
+fn sleep_returns() {
+    time::sleep(Duration::from_millis(10));
+}
+
+static VECTOR_FIRED: AtomicBool = AtomicBool::new(false);
+
+fn vector_handler(_vector: u8) {
+    VECTOR_FIRED.store(true, Ordering::Relaxed);
+}
+
+#[test_case]
+fn claimed_vectors_are_dispatched() {
+    let vector = interrupt::claim_vector(vector_handler).expect("no free vector");
+    assert_eq!(vector, interrupt::FIRST_DYNAMIC_VECTOR);
+
+    unsafe { core::arch::asm!("int 48") };
+    assert!(VECTOR_FIRED.load(Ordering::Relaxed));
+
+    interrupt::release_vector(vector);
+    assert_eq!(interrupt::claim_vector(vector_handler), Some(vector));
+    interrupt::release_vector(vector);
+}
+
+static SHARED_CALLS: AtomicU64 = AtomicU64::new(0);
+
+fn shared_handler(_vector: u8) {
+    SHARED_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test_case]
+fn shared_irqs_call_every_handler() {
+    let first = interrupt::register_irq(5, shared_handler).expect("unable to register");
+    let second = interrupt::register_irq(5, shared_handler).expect("unable to register");
+    assert_eq!(first.vector(), 37);
+    let count = interrupt::count(37);
+
+    unsafe { core::arch::asm!("int 37") };
+    assert_eq!(SHARED_CALLS.load(Ordering::Relaxed), 2);
+    assert!(interrupt::count(37) > count);
+
+    interrupt::unregister_irq(first).unwrap();
+    interrupt::unregister_irq(second).unwrap();
+    assert_eq!(
+        interrupt::unregister_irq(first),
+        Err(interrupt::IrqError::NotRegistered)
+    );
+}
+
+#[test_case]
+fn invalid_irq_lines_are_rejected() {
+    assert_eq!(
+        interrupt::register_irq(16, shared_handler),
+        Err(interrupt::IrqError::InvalidLine)
+    );
+}
+
+#[test_case]
+fn timer_interrupts_are_counted() {
+    let count = interrupt::count(interrupt::PIC_1_OFFSET);
+    time::sleep(Duration::from_millis(10));
+    assert!(interrupt::count(interrupt::PIC_1_OFFSET) > count);
+}
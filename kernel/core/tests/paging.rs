@@ -0,0 +1,249 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{
+    hlt_loop, init,
+    memory::{physical_memory_offset, PAGE_ALLOCATOR},
+};
+use x86_64::{
+    structures::paging::{
+        mapper::{MapToError, TranslateError},
+        FrameAllocator, FrameDeallocator, PageTableFlags, PhysFrame,
+    },
+    VirtAddr,
+};
+
+/// Far away from the heap, so nothing else maps it
+const TEST_REGION: u64 = 0x_5555_0000_0000;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+fn map(start: u64, len: u64) -> Result<(), MapToError<x86_64::structures::paging::Size4KiB>> {
+    let start = VirtAddr::new(start);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    PAGE_ALLOCATOR
+        .lock()
+        .as_mut()
+        .expect("paging is initialized")
+        .allocate_pages_4kib(start, start + (len - 1), flags)
+}
+
+#[test_case]
+fn mapped_pages_are_writable() {
+    map(TEST_REGION, 3 * 4096).unwrap();
+
+    let region = TEST_REGION as *mut u64;
+    for i in 0..3 * 512 {
+        unsafe { region.add(i).write_volatile(i as u64) };
+    }
+    for i in 0..3 * 512 {
+        assert_eq!(unsafe { region.add(i).read_volatile() }, i as u64);
+    }
+}
+
+#[test_case]
+fn mapping_twice_fails() {
+    let start = TEST_REGION + 0x10_0000;
+    map(start, 4096).unwrap();
+
+    assert!(matches!(
+        map(start, 4096),
+        Err(MapToError::PageAlreadyMapped(_))
+    ));
+}
+
+/// Every page gets its own frame, writes to one page don't show up in another
+#[test_case]
+fn pages_get_distinct_frames() {
+    let start = TEST_REGION + 0x20_0000;
+    map(start, 2 * 4096).unwrap();
+
+    let first = start as *mut u8;
+    let second = (start + 4096) as *mut u8;
+    unsafe {
+        first.write_volatile(0xAA);
+        second.write_volatile(0x55);
+        assert_eq!(first.read_volatile(), 0xAA);
+    }
+}
+
+#[test_case]
+fn huge_pages_are_writable() {
+    let start = VirtAddr::new(TEST_REGION + 0x4000_0000);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    PAGE_ALLOCATOR
+        .lock()
+        .as_mut()
+        .unwrap()
+        .allocate_pages_2mib(start, start + 0x3F_FFFFu64, flags)
+        .unwrap();
+
+    let region: *mut u64 = start.as_mut_ptr();
+    let last = 0x40_0000 / 8 - 1;
+    unsafe {
+        region.write_volatile(1);
+        region.add(last).write_volatile(2);
+        assert_eq!(region.read_volatile(), 1);
+        assert_eq!(region.add(last).read_volatile(), 2);
+    }
+}
+
+/// An unaligned range is mapped with 4KiB pages up to the first 2MiB boundary
+#[test_case]
+fn mixed_page_sizes() {
+    let start = VirtAddr::new(TEST_REGION + 0x8000_0000 - 0x1000);
+    let size = 0x20_2000u64;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    PAGE_ALLOCATOR
+        .lock()
+        .as_mut()
+        .unwrap()
+        .allocate_range(start, size, flags)
+        .unwrap();
+
+    let region: *mut u8 = start.as_mut_ptr();
+    for offset in (0..size as usize).step_by(0x1000) {
+        unsafe {
+            region.add(offset).write_volatile(offset as u8 ^ 0x5A);
+            assert_eq!(region.add(offset).read_volatile(), offset as u8 ^ 0x5A);
+        }
+    }
+}
+
+#[test_case]
+fn translate_follows_mappings() {
+    let start = TEST_REGION + 0x30_0000;
+    map(start, 4096).unwrap();
+
+    let page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_ref().unwrap();
+    let phys = page_allocator.translate(VirtAddr::new(start)).unwrap();
+    assert_eq!(
+        page_allocator.translate(VirtAddr::new(start + 0x123)),
+        Some(phys + 0x123u64)
+    );
+    assert_eq!(page_allocator.translate(VirtAddr::new(start + 4096)), None);
+}
+
+#[test_case]
+fn unmapping_frees_frames() {
+    let start = VirtAddr::new(TEST_REGION + 0x40_0000);
+    map(start.as_u64(), 3 * 4096).unwrap();
+
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut().unwrap();
+    let free = page_allocator.frame_allocator().free_frames();
+
+    page_allocator.unmap_range(start, 3 * 4096).unwrap();
+    assert_eq!(page_allocator.frame_allocator().free_frames(), free + 3);
+    assert_eq!(page_allocator.translate(start), None);
+    assert!(matches!(
+        page_allocator.unmap_range(start, 4096),
+        Err(TranslateError::PageNotMapped)
+    ));
+}
+
+#[test_case]
+fn huge_pages_are_unmapped_whole() {
+    let start = VirtAddr::new(TEST_REGION + 0xC000_0000);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut().unwrap();
+    page_allocator
+        .allocate_pages_2mib(start, start + 0x1F_FFFFu64, flags)
+        .unwrap();
+
+    assert!(matches!(
+        page_allocator.unmap_range(start, 4096),
+        Err(TranslateError::ParentEntryHugePage)
+    ));
+    assert!(page_allocator.translate(start).is_some());
+
+    let free = page_allocator.frame_allocator().free_frames();
+    page_allocator.unmap_range(start, 0x20_0000).unwrap();
+    assert_eq!(page_allocator.frame_allocator().free_frames(), free + 512);
+}
+
+/// Page tables left behind by 4KiB pages don't stop the range from being mapped again
+#[test_case]
+fn remapping_after_small_pages() {
+    let start = VirtAddr::new(TEST_REGION + 0xC020_0000);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    map(start.as_u64() + 0x1000, 4096).unwrap();
+
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut().unwrap();
+    page_allocator.unmap_range(start + 0x1000u64, 4096).unwrap();
+
+    page_allocator
+        .allocate_range(start, 0x20_0000, flags)
+        .unwrap();
+    unsafe { (start + 0x1000u64).as_mut_ptr::<u8>().write_volatile(0x42) };
+    assert_eq!(
+        unsafe { (start + 0x1000u64).as_ptr::<u8>().read_volatile() },
+        0x42
+    );
+    page_allocator.unmap_range(start, 0x20_0000).unwrap();
+}
+
+#[test_case]
+fn protecting_keeps_the_mapping() {
+    let start = VirtAddr::new(TEST_REGION + 0x50_0000);
+    map(start.as_u64(), 2 * 4096).unwrap();
+    unsafe { start.as_mut_ptr::<u8>().write_volatile(0x42) };
+
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut().unwrap();
+    let phys = page_allocator.translate(start);
+    // Read-only
+    page_allocator
+        .protect_range(start, 2 * 4096, PageTableFlags::PRESENT)
+        .unwrap();
+
+    assert_eq!(page_allocator.translate(start), phys);
+    assert_eq!(unsafe { start.as_ptr::<u8>().read_volatile() }, 0x42);
+    assert!(matches!(
+        page_allocator.protect_range(start, 3 * 4096, PageTableFlags::PRESENT),
+        Err(TranslateError::PageNotMapped)
+    ));
+}
+
+/// Physical mappings see the same memory as the direct map, and unmapping them
+/// leaves the frames alone
+#[test_case]
+fn physical_mappings_are_borrowed() {
+    let mut page_allocator = PAGE_ALLOCATOR.lock();
+    let page_allocator = page_allocator.as_mut().unwrap();
+    let frame: PhysFrame = page_allocator.frame_allocator().allocate_frame().unwrap();
+    let phys = frame.start_address();
+    let direct: *mut u64 = (physical_memory_offset() + phys.as_u64() + 8u64).as_mut_ptr();
+    unsafe { direct.write_volatile(0xDEAD_BEEF) };
+
+    let virt = page_allocator
+        .map_physical(phys + 8u64, 8, PageTableFlags::PRESENT)
+        .unwrap();
+    assert_eq!(virt.as_u64() % 4096, 8);
+    assert_eq!(unsafe { virt.as_ptr::<u64>().read_volatile() }, 0xDEAD_BEEF);
+
+    let free = page_allocator.frame_allocator().free_frames();
+    page_allocator.vfree(virt.align_down(4096u64)).unwrap();
+    assert_eq!(page_allocator.frame_allocator().free_frames(), free);
+    assert_eq!(page_allocator.translate(virt), None);
+
+    unsafe { page_allocator.frame_allocator().deallocate_frame(frame) };
+}
@@ -0,0 +1,321 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use butterscotch_kernel::fs::vfs;
+use butterscotch_kernel::shell::parser::{self, Condition, Operator, ParseError};
+use butterscotch_kernel::shell::{self, commands, Shell};
+use butterscotch_kernel::{hlt_loop, init};
+use fat32::FatFs;
+use libk::io::ramfile::RamFile;
+use libk::Mutex;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    vfs::mkdir("/sh").unwrap();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+fn contents(path: &str) -> String {
+    String::from_utf8(vfs::read(path).unwrap()).unwrap()
+}
+
+/// The words of the only command in `line`, with variables left out
+fn words(line: &str) -> Vec<String> {
+    let list = parser::parse(line).unwrap();
+    assert_eq!(list.len(), 1);
+    list[0].1[0]
+        .words
+        .iter()
+        .map(|word| word.expand(|_| None))
+        .collect()
+}
+
+#[test_case]
+fn quotes_and_escapes() {
+    assert_eq!(
+        words(r#"put a\ b "c \"d\" \n" 'e $f \' '' x"y"z"#),
+        ["put", "a b", "c \"d\" \\n", "e $f \\", "", "xyz"]
+    );
+}
+
+#[test_case]
+fn structure_of_lists() {
+    let list = parser::parse("a | b > out; c && d || e;").unwrap();
+    let conditions: Vec<Condition> = list.iter().map(|(condition, _)| *condition).collect();
+    assert_eq!(
+        conditions,
+        [
+            Condition::Always,
+            Condition::Always,
+            Condition::IfSucceeded,
+            Condition::IfFailed
+        ]
+    );
+    assert_eq!(list[0].1.len(), 2);
+    assert_eq!(list[0].1[1].redirects[0].operator, Operator::Output);
+    assert!(parser::parse("   ").unwrap().is_empty());
+}
+
+#[test_case]
+fn syntax_errors() {
+    let cases = [
+        ("echo 'open", ParseError::UnterminatedQuote),
+        ("echo ${open", ParseError::UnterminatedVariable),
+        ("echo \\", ParseError::TrailingEscape),
+        ("| a", ParseError::MissingCommand(Operator::Pipe)),
+        ("a &&", ParseError::MissingCommand(Operator::And)),
+        ("a ;; b", ParseError::Unsupported(';')),
+        ("a > ", ParseError::MissingFileName(Operator::Output)),
+        ("a & b", ParseError::Unsupported('&')),
+    ];
+    for (line, error) in cases {
+        assert_eq!(parser::parse(line), Err(error));
+    }
+}
+
+#[test_case]
+fn pipes_and_redirection() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.run_line("put /sh/greeting 'hello   world'  again"), 0);
+    assert_eq!(contents("/sh/greeting"), "hello   world again");
+
+    assert_eq!(shell.run_line("cat /sh/greeting | cat | cat > /sh/copy"), 0);
+    assert_eq!(contents("/sh/copy"), "hello   world again");
+
+    assert_eq!(
+        shell.run_line("echo one > /sh/log; echo two >> /sh/log; cat < /sh/log > /sh/log2"),
+        shell::SUCCESS
+    );
+    assert_eq!(contents("/sh/log2"), "one\ntwo\n");
+}
+
+#[test_case]
+fn conditional_sequencing() {
+    let mut shell = Shell::new();
+    assert_eq!(
+        shell.run_line("echo a > /sh/and && false-command && echo b > /sh/and"),
+        shell::NOT_FOUND
+    );
+    assert_eq!(contents("/sh/and"), "a\n");
+
+    assert_eq!(
+        shell.run_line("cat /sh/missing || echo fallback > /sh/or"),
+        shell::SUCCESS
+    );
+    assert_eq!(contents("/sh/or"), "fallback\n");
+}
+
+#[test_case]
+fn variables_are_expanded() {
+    let mut shell = Shell::new();
+    assert_eq!(
+        shell.run_line("NAME=world FILE=/sh/vars; echo \"hello $NAME\" '$NAME' ${NAME}s > $FILE"),
+        shell::SUCCESS
+    );
+    assert_eq!(contents("/sh/vars"), "hello world $NAME worlds\n");
+    assert_eq!(shell.variable("NAME").as_deref(), Some("world"));
+
+    shell.run_line("unset NAME; echo [$NAME] $? > /sh/vars");
+    assert_eq!(contents("/sh/vars"), "[] 0\n");
+}
+
+#[test_case]
+fn exit_statuses() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.run_line("cat /sh/missing"), shell::FAILURE);
+    assert_eq!(shell.run_line("put"), shell::USAGE);
+    assert_eq!(shell.run_line("pwd extra"), shell::USAGE);
+    // Arguments are checked before the command does anything
+    shell.run_line("put /sh/stay here");
+    assert_eq!(shell.run_line("mv /sh/stay /sh/moved extra"), shell::USAGE);
+    assert_eq!(contents("/sh/stay"), "here");
+    assert_eq!(shell.run_line("echo 'open"), shell::USAGE);
+    assert_eq!(shell.run_line("help date > /sh/help"), shell::SUCCESS);
+    assert_eq!(
+        contents("/sh/help"),
+        "usage: date\nPrints the date and time in UTC\n"
+    );
+}
+
+#[test_case]
+fn subsystems_register_commands() {
+    let names: Vec<&str> = commands::all().iter().map(|c| c.name()).collect();
+    for name in ["cat", "date", "help", "irqs", "layout", "meminfo"] {
+        assert!(names.contains(&name));
+    }
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(commands::register(commands::find("cat").unwrap()).is_err());
+}
+
+#[test_case]
+fn listing_and_stat() {
+    let mut shell = Shell::new();
+    shell.run_line("mkdir /sh/ls /sh/ls/dir; put /sh/ls/file abc");
+    assert_eq!(shell.run_line("ls /sh/ls > /sh/ls.out"), shell::SUCCESS);
+    assert_eq!(contents("/sh/ls.out"), "dir/\nfile\n");
+
+    shell.run_line("ls -l /sh/ls > /sh/ls.out");
+    assert_eq!(
+        contents("/sh/ls.out"),
+        "d          0 dir/\n-          3 file\n"
+    );
+
+    shell.run_line("stat /sh/ls/file > /sh/ls.out");
+    assert_eq!(
+        contents("/sh/ls.out"),
+        "  Path: /sh/ls/file\n  Type: file\n  Size: 3 bytes\n"
+    );
+    assert_eq!(shell.run_line("ls /sh/ls/missing"), shell::FAILURE);
+}
+
+#[test_case]
+fn copying_moving_and_removing() {
+    let mut shell = Shell::new();
+    shell.run_line("mkdir /sh/cp /sh/cp/a /sh/cp/b; put /sh/cp/a/file contents");
+
+    assert_eq!(shell.run_line("cp /sh/cp/a /sh/cp/c"), shell::FAILURE);
+    assert_eq!(shell.run_line("cp -r /sh/cp/a /sh/cp/c"), shell::SUCCESS);
+    assert_eq!(contents("/sh/cp/c/file"), "contents");
+    assert_eq!(shell.run_line("cp -r /sh/cp /sh/cp/b"), shell::FAILURE);
+
+    // A directory as the destination means inside it
+    assert_eq!(shell.run_line("mv /sh/cp/c/file /sh/cp/b"), shell::SUCCESS);
+    assert_eq!(contents("/sh/cp/b/file"), "contents");
+    assert!(vfs::read("/sh/cp/c/file").is_err());
+
+    // Copying or moving a file onto itself must not truncate it
+    assert_eq!(shell.run_line("cp /sh/cp/b/file /sh/cp/b"), shell::FAILURE);
+    assert_eq!(shell.run_line("mv /sh/cp/b/file /sh/cp/b/"), shell::FAILURE);
+    assert_eq!(contents("/sh/cp/b/file"), "contents");
+
+    assert_eq!(shell.run_line("rm /sh/cp/a"), shell::FAILURE);
+    assert_eq!(
+        shell.run_line("rm -r /sh/cp/a /sh/cp/b/file"),
+        shell::SUCCESS
+    );
+    assert!(vfs::metadata("/sh/cp/a").is_err());
+    assert!(vfs::metadata("/sh/cp/b/file").is_err());
+}
+
+/// An empty FAT12 volume of 128 sectors, with one of them for each FAT
+fn fat_image() -> Vec<u8> {
+    let mut image = vec![0u8; 128 * 512];
+    image[11..13].copy_from_slice(&512u16.to_le_bytes());
+    image[13] = 1; // Sectors per cluster
+    image[14..16].copy_from_slice(&1u16.to_le_bytes()); // Reserved sectors
+    image[16] = 2; // FATs
+    image[17..19].copy_from_slice(&16u16.to_le_bytes()); // Root entries
+    image[19..21].copy_from_slice(&128u16.to_le_bytes()); // Sectors
+    image[21] = 0xF8;
+    image[22..24].copy_from_slice(&1u16.to_le_bytes()); // Sectors per FAT
+    image[510..512].copy_from_slice(&[0x55, 0xAA]);
+    for fat in [512, 1024] {
+        image[fat..fat + 3].copy_from_slice(&[0xF8, 0xFF, 0xFF]);
+    }
+    image
+}
+
+#[test_case]
+fn copying_onto_itself_by_another_name() {
+    let disk = RamFile::new(Arc::new(Mutex::new(fat_image())), false);
+    vfs::mount("/fat", Arc::new(FatFs::new(disk).unwrap())).unwrap();
+    let mut shell = Shell::new();
+    shell.run_line("put /fat/a.txt data; mkdir /fat/dir");
+
+    // FAT ignores the case of names, so these are the same file
+    assert_eq!(shell.run_line("cp /fat/a.txt /fat/A.TXT"), shell::FAILURE);
+    assert_eq!(contents("/fat/a.txt"), "data");
+    assert_eq!(shell.run_line("cp -r /fat/dir /fat/DIR"), shell::FAILURE);
+
+    // But renaming to another case is fine
+    assert_eq!(shell.run_line("mv /fat/a.txt /fat/A.TXT"), shell::SUCCESS);
+    assert_eq!(contents("/fat/a.txt"), "data");
+    vfs::unmount("/fat").unwrap();
+}
+
+#[test_case]
+fn touch_keeps_contents() {
+    let mut shell = Shell::new();
+    shell.run_line("put /sh/touched kept");
+    assert_eq!(shell.run_line("touch /sh/touched /sh/new"), shell::SUCCESS);
+    assert_eq!(contents("/sh/touched"), "kept");
+    assert_eq!(contents("/sh/new"), "");
+}
+
+#[test_case]
+fn hexdump_matches_pretty_hex() {
+    let mut shell = Shell::new();
+    shell.run_line("echo 'Hello, hexdump!' 12 | hexdump > /sh/hex");
+    assert_eq!(
+        contents("/sh/hex"),
+        "Length: 19 (0x13) bytes\n\
+         0000:   48 65 6c 6c  6f 2c 20 68  65 78 64 75  6d 70 21 20   Hello, hexdump! \n\
+         0010:   31 32 0a                                             12.\n"
+    );
+}
+
+#[test_case]
+fn hexdump_streams_files() {
+    // Longer than a chunk, and not a whole number of rows
+    let bytes = vec![b'a'; 4100];
+    vfs::create("/sh/hex_long")
+        .unwrap()
+        .write_all(&bytes)
+        .unwrap();
+
+    let mut shell = Shell::new();
+    shell.run_line("hexdump /sh/hex_long > /sh/hex_file");
+    shell.run_line("cat /sh/hex_long | hexdump > /sh/hex_input");
+    let dump = contents("/sh/hex_file");
+    assert_eq!(dump, contents("/sh/hex_input"));
+
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(lines.len(), 1 + 257);
+    assert_eq!(lines[0], "Length: 4100 (0x1004) bytes");
+    assert_eq!(
+        lines[256],
+        format!(
+            "0ff0:   61 61 61 61  61 61 61 61  61 61 61 61  61 61 61 61   {}",
+            "a".repeat(16)
+        )
+    );
+    assert_eq!(
+        lines[257],
+        format!("1000:   61 61 61 61{}   aaaa", " ".repeat(39))
+    );
+}
+
+#[test_case]
+fn wc_and_tee() {
+    let mut shell = Shell::new();
+    shell.run_line("echo one two > /sh/wc; echo three | tee -a /sh/wc /sh/tee > /sh/wc.out");
+    assert_eq!(contents("/sh/wc"), "one two\nthree\n");
+    assert_eq!(contents("/sh/tee"), "three\n");
+    assert_eq!(contents("/sh/wc.out"), "three\n");
+
+    shell.run_line("wc /sh/wc /sh/tee > /sh/wc.out");
+    assert_eq!(
+        contents("/sh/wc.out"),
+        "      2       3      14 /sh/wc\n      1       1       6 /sh/tee\n      3       4      20 total\n"
+    );
+    shell.run_line("cat /sh/wc | wc > /sh/wc.out");
+    assert_eq!(contents("/sh/wc.out"), "      2       3      14\n");
+}
@@ -0,0 +1,26 @@
+//! Checks that a failed assertion panics, runs without the test harness
+//! because the test passes by panicking
+
+#![no_std]
+#![no_main]
+
+use butterscotch_kernel::{init, serial_print, testing};
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+
+    serial_print!("should_panic::should_fail...\t");
+    should_fail();
+
+    testing::should_panic_failed()
+}
+
+fn should_fail() {
+    assert_eq!(0, 1);
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    testing::should_panic_handler(info)
+}
@@ -0,0 +1,34 @@
+//! Checks that overflowing a kernel stack hits the guard page and ends up in the
+//! double fault handler, which runs on its own stack and panics
+
+#![no_std]
+#![no_main]
+
+use butterscotch_kernel::{init, kernel::run_on_kernel_stack, serial_print, testing};
+use core::hint::black_box;
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+
+    serial_print!("stack_overflow::stack_overflow...\t");
+    run_on_kernel_stack(overflow)
+}
+
+extern "C" fn overflow() -> ! {
+    stack_overflow();
+
+    testing::should_panic_failed()
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    // Keeps the recursion from being turned into a loop
+    black_box(0);
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    testing::should_panic_handler(info)
+}
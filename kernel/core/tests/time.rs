@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(butterscotch_kernel::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use butterscotch_kernel::{hlt_loop, init, time};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use libk::time::{DateTime, Duration, Instant, SystemTime};
+
+#[no_mangle]
+unsafe extern "C" fn _start() -> ! {
+    init();
+    test_main();
+
+    hlt_loop()
+}
+
+#[panic_handler]
+fn panic(info: &libk::panic::PanicInfo) -> ! {
+    butterscotch_kernel::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn clock_is_monotonic() {
+    let mut last = Instant::now();
+    for _ in 0..1000 {
+        let now = Instant::now();
+        assert!(now >= last);
+        last = now;
+    }
+}
+
+#[test_case]
+fn sleep_waits_for_the_duration() {
+    let start = Instant::now();
+    let ticks = time::ticks();
+    time::sleep(Duration::from_millis(20));
+
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert!(time::ticks() - ticks >= 19);
+}
+
+/// Each timer records the order it fired in, at the index it was given as context
+static ORDER: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+static FIRED: AtomicUsize = AtomicUsize::new(0);
+
+fn record(index: usize) {
+    let position = FIRED.fetch_add(1, Ordering::Relaxed) + 1;
+    ORDER[index].store(position, Ordering::Relaxed);
+}
+
+#[test_case]
+fn timers_fire_in_deadline_order() {
+    let now = Instant::now();
+    time::add_timer(now + Duration::from_millis(6), record, 2);
+    time::add_timer(now + Duration::from_millis(2), record, 0);
+    time::add_timer(now + Duration::from_millis(4), record, 1);
+    let cancelled = time::add_timer(now + Duration::from_millis(3), record, 1);
+    assert!(time::cancel_timer(cancelled));
+    assert!(!time::cancel_timer(cancelled));
+
+    time::sleep(Duration::from_millis(10));
+    assert_eq!(FIRED.load(Ordering::Relaxed), 3);
+    for (index, position) in ORDER.iter().enumerate() {
+        assert_eq!(position.load(Ordering::Relaxed), index + 1);
+    }
+}
+
+#[test_case]
+fn wall_clock_follows_the_rtc() {
+    let rtc = time::rtc::read()
+        .to_system_time()
+        .expect("invalid RTC date");
+    let now = SystemTime::now();
+
+    assert!(DateTime::from(now).year >= 2024);
+    let difference = match now.duration_since(rtc) {
+        Ok(difference) => difference,
+        Err(err) => err.duration(),
+    };
+    assert!(difference <= Duration::from_secs(2));
+}
@@ -0,0 +1,235 @@
+use libk::io;
+
+/// Size of the boot sector that holds the BPB, regardless of the sector size
+pub const BOOT_SECTOR_SIZE: usize = 512;
+
+pub(crate) fn le_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buf[offset], buf[offset + 1]])
+}
+
+pub(crate) fn le_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn array<const N: usize>(buf: &[u8], offset: usize) -> [u8; N] {
+    let mut res = [0u8; N];
+    res.copy_from_slice(&buf[offset..offset + N]);
+    res
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// The BIOS Parameter Block shared by all FAT variants
+#[derive(Debug, Clone)]
+pub struct FatBPB {
+    pub oem_ident: [u8; 8],
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sector_count: u16,
+    pub table_count: u8,
+    pub root_entry_count: u16,
+    pub total_sectors_16: u16,
+    pub media_type: u8,
+    pub sectors_per_fat: u16,
+    pub sectors_per_track: u16,
+    pub head_side_count: u16,
+    pub hidden_sector_count: u32,
+    pub total_sectors_32: u32,
+}
+
+impl FatBPB {
+    pub fn parse(boot_sector: &[u8; BOOT_SECTOR_SIZE]) -> Self {
+        Self {
+            oem_ident: array(boot_sector, 3),
+            bytes_per_sector: le_u16(boot_sector, 11),
+            sectors_per_cluster: boot_sector[13],
+            reserved_sector_count: le_u16(boot_sector, 14),
+            table_count: boot_sector[16],
+            root_entry_count: le_u16(boot_sector, 17),
+            total_sectors_16: le_u16(boot_sector, 19),
+            media_type: boot_sector[21],
+            sectors_per_fat: le_u16(boot_sector, 22),
+            sectors_per_track: le_u16(boot_sector, 24),
+            head_side_count: le_u16(boot_sector, 26),
+            hidden_sector_count: le_u32(boot_sector, 28),
+            total_sectors_32: le_u32(boot_sector, 32),
+        }
+    }
+
+    pub fn total_sectors(&self) -> u32 {
+        if self.total_sectors_16 == 0 {
+            self.total_sectors_32
+        } else {
+            self.total_sectors_16 as u32
+        }
+    }
+}
+
+/// Extended BPB used by FAT12 and FAT16
+#[derive(Debug, Clone)]
+pub struct Fat16EBPB {
+    pub bios_drive_num: u8,
+    pub boot_signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub fat_type_label: [u8; 8],
+}
+
+impl Fat16EBPB {
+    pub fn parse(boot_sector: &[u8; BOOT_SECTOR_SIZE]) -> Self {
+        Self {
+            bios_drive_num: boot_sector[36],
+            boot_signature: boot_sector[38],
+            volume_id: le_u32(boot_sector, 39),
+            volume_label: array(boot_sector, 43),
+            fat_type_label: array(boot_sector, 54),
+        }
+    }
+}
+
+/// Extended BPB used by FAT32
+#[derive(Debug, Clone)]
+pub struct Fat32EBPB {
+    pub sectors_per_fat: u32,
+    pub flags: u16,
+    pub fat_version: u16,
+    pub root_cluster: u32,
+    pub fsinfo: u16,
+    pub backup_bs_sector: u16,
+    pub drive_number: u8,
+    pub boot_signature: u8,
+    pub volume_id: u32,
+    pub volume_label: [u8; 11],
+    pub fat_type_label: [u8; 8],
+}
+
+impl Fat32EBPB {
+    pub fn parse(boot_sector: &[u8; BOOT_SECTOR_SIZE]) -> Self {
+        Self {
+            sectors_per_fat: le_u32(boot_sector, 36),
+            flags: le_u16(boot_sector, 40),
+            fat_version: le_u16(boot_sector, 42),
+            root_cluster: le_u32(boot_sector, 44),
+            fsinfo: le_u16(boot_sector, 48),
+            backup_bs_sector: le_u16(boot_sector, 50),
+            drive_number: boot_sector[64],
+            boot_signature: boot_sector[66],
+            volume_id: le_u32(boot_sector, 67),
+            volume_label: array(boot_sector, 71),
+            fat_type_label: array(boot_sector, 82),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FatInfo {
+    Fat12 { bpb: FatBPB, ebpb: Fat16EBPB },
+    Fat16 { bpb: FatBPB, ebpb: Fat16EBPB },
+    Fat32 { bpb: FatBPB, ebpb: Fat32EBPB },
+}
+
+impl FatInfo {
+    /// Parse and validate the boot sector of a FAT volume
+    ///
+    /// The FAT variant is decided by the cluster count, as required by the specification,
+    /// the labels in the extended BPB are not trusted
+    pub fn parse(boot_sector: &[u8; BOOT_SECTOR_SIZE]) -> Result<Self, io::Error> {
+        if boot_sector[510] != 0x55 || boot_sector[511] != 0xAA {
+            return Err(io::Error::InvalidData);
+        }
+
+        let bpb = FatBPB::parse(boot_sector);
+
+        if !matches!(bpb.bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || !bpb.sectors_per_cluster.is_power_of_two()
+            || bpb.reserved_sector_count == 0
+            || bpb.table_count == 0
+        {
+            return Err(io::Error::InvalidData);
+        }
+
+        let ebpb32 = Fat32EBPB::parse(boot_sector);
+        let sectors_per_fat = if bpb.sectors_per_fat == 0 {
+            ebpb32.sectors_per_fat
+        } else {
+            bpb.sectors_per_fat as u32
+        };
+        if sectors_per_fat == 0 {
+            return Err(io::Error::InvalidData);
+        }
+
+        let root_dir_sectors = root_dir_sectors(&bpb);
+        let meta_sectors = bpb.reserved_sector_count as u32
+            + bpb.table_count as u32 * sectors_per_fat
+            + root_dir_sectors;
+        let data_sectors = bpb
+            .total_sectors()
+            .checked_sub(meta_sectors)
+            .ok_or(io::Error::InvalidData)?;
+        let total_clusters = data_sectors / bpb.sectors_per_cluster as u32;
+
+        let info = if total_clusters < 4085 {
+            FatInfo::Fat12 {
+                ebpb: Fat16EBPB::parse(boot_sector),
+                bpb,
+            }
+        } else if total_clusters < 65525 {
+            FatInfo::Fat16 {
+                ebpb: Fat16EBPB::parse(boot_sector),
+                bpb,
+            }
+        } else {
+            // FAT32 has no fixed root directory, and must use the 32 bit FAT size
+            if bpb.root_entry_count != 0 || bpb.sectors_per_fat != 0 {
+                return Err(io::Error::InvalidData);
+            }
+            FatInfo::Fat32 { bpb, ebpb: ebpb32 }
+        };
+
+        Ok(info)
+    }
+
+    pub fn get_bpb(&self) -> &FatBPB {
+        match self {
+            FatInfo::Fat12 { bpb, .. } => bpb,
+            FatInfo::Fat16 { bpb, .. } => bpb,
+            FatInfo::Fat32 { bpb, .. } => bpb,
+        }
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        match self {
+            FatInfo::Fat12 { .. } => FatType::Fat12,
+            FatInfo::Fat16 { .. } => FatType::Fat16,
+            FatInfo::Fat32 { .. } => FatType::Fat32,
+        }
+    }
+
+    pub fn sectors_per_fat(&self) -> u32 {
+        match self {
+            FatInfo::Fat32 { ebpb, .. } => ebpb.sectors_per_fat,
+            _ => self.get_bpb().sectors_per_fat as u32,
+        }
+    }
+
+    pub fn volume_label(&self) -> [u8; 11] {
+        match self {
+            FatInfo::Fat12 { ebpb, .. } | FatInfo::Fat16 { ebpb, .. } => ebpb.volume_label,
+            FatInfo::Fat32 { ebpb, .. } => ebpb.volume_label,
+        }
+    }
+}
+
+pub(crate) fn root_dir_sectors(bpb: &FatBPB) -> u32 {
+    (bpb.root_entry_count as u32 * 32).div_ceil(bpb.bytes_per_sector as u32)
+}
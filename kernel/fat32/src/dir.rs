@@ -0,0 +1,613 @@
+use libk::{
+    alloc::format,
+    char::decode_utf16,
+    cmp::Ordering,
+    fs::Metadata,
+    io::{self, Read, Seek, Write},
+    string::String,
+    time::{DateTime, SystemTime},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    bpb::{le_u16, le_u32},
+    volume::{DirLocation, Volume},
+};
+
+pub(crate) const ENTRY_SIZE: usize = 32;
+
+/// Maximum length of a long file name in UTF-16 code units
+const MAX_NAME_LEN: usize = 255;
+/// Number of UTF-16 code units stored in a single long file name entry
+const LFN_CHARS_PER_ENTRY: usize = 13;
+/// Byte offsets of the name characters inside a long file name entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] =
+    [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+const LFN_LAST_ENTRY: u8 = 0x40;
+
+const DELETED_MARKER: u8 = 0xE5;
+const END_MARKER: u8 = 0x00;
+
+/// NTRes flags used by Windows NT and Linux to store all-lowercase 8.3 names
+const NTRES_LOWER_BASE: u8 = 0x08;
+const NTRES_LOWER_EXT: u8 = 0x10;
+
+/// Years FAT timestamps can hold, starting at the FAT epoch
+const FIRST_YEAR: u16 = 1980;
+const LAST_YEAR: u16 = FIRST_YEAR + 127;
+
+pub(crate) mod attr {
+    pub const READ_ONLY: u8 = 0x01;
+    pub const HIDDEN: u8 = 0x02;
+    pub const SYSTEM: u8 = 0x04;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+    pub const LONG_NAME: u8 = READ_ONLY | HIDDEN | SYSTEM | VOLUME_ID;
+}
+
+/// A raw 32 byte short directory entry
+#[derive(Debug, Clone)]
+pub(crate) struct RawEntry(pub [u8; ENTRY_SIZE]);
+
+impl RawEntry {
+    pub fn new(short_name: [u8; 11], attributes: u8, first_cluster: u32) -> Self {
+        let mut entry = Self([0u8; ENTRY_SIZE]);
+        entry.0[..11].copy_from_slice(&short_name);
+        entry.0[11] = attributes;
+        let (date, time) = timestamp(SystemTime::now());
+        entry.0[14..16].copy_from_slice(&time.to_le_bytes());
+        entry.0[16..18].copy_from_slice(&date.to_le_bytes());
+        entry.set_first_cluster(first_cluster);
+        entry.touch();
+        entry
+    }
+
+    pub fn short_name(&self) -> [u8; 11] {
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&self.0[..11]);
+        name
+    }
+
+    pub fn attributes(&self) -> u8 {
+        self.0[11]
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes() & attr::DIRECTORY != 0
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.0[0] == DELETED_MARKER
+    }
+
+    pub fn first_cluster(&self) -> u32 {
+        ((le_u16(&self.0, 20) as u32) << 16) | le_u16(&self.0, 26) as u32
+    }
+
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        self.0[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+        self.0[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    }
+
+    pub fn size(&self) -> u32 {
+        le_u32(&self.0, 28)
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        self.0[28..32].copy_from_slice(&size.to_le_bytes());
+    }
+
+    /// Sets the modification timestamp and the access date to now
+    pub fn touch(&mut self) {
+        let (date, time) = timestamp(SystemTime::now());
+        self.0[18..20].copy_from_slice(&date.to_le_bytes());
+        self.0[22..24].copy_from_slice(&time.to_le_bytes());
+        self.0[24..26].copy_from_slice(&date.to_le_bytes());
+    }
+
+    /// Location of the directory this entry refers to
+    ///
+    /// `..` entries use cluster 0 to refer to the root directory
+    pub fn dir_location(&self, root: DirLocation) -> DirLocation {
+        match self.first_cluster() {
+            0 => root,
+            cluster => DirLocation::Chain(cluster),
+        }
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        if self.is_dir() {
+            Metadata::directory()
+        } else {
+            Metadata::file(self.size() as u64)
+        }
+    }
+
+    /// The human readable form of the 8.3 name
+    fn display_name(&self) -> String {
+        let mut raw = self.short_name();
+        if raw[0] == 0x05 {
+            // 0xE5 is a valid KANJI lead byte, and is stored as 0x05 to not look deleted
+            raw[0] = DELETED_MARKER;
+        }
+        let ntres = self.0[12];
+
+        let mut name = String::new();
+        for &c in trim_spaces(&raw[..8]) {
+            push_short_char(&mut name, c, ntres & NTRES_LOWER_BASE != 0);
+        }
+        let ext = trim_spaces(&raw[8..]);
+        if !ext.is_empty() {
+            name.push('.');
+            for &c in ext {
+                push_short_char(&mut name, c, ntres & NTRES_LOWER_EXT != 0);
+            }
+        }
+        name
+    }
+}
+
+fn trim_spaces(name: &[u8]) -> &[u8] {
+    let len = name.iter().rposition(|&c| c != b' ').map_or(0, |i| i + 1);
+    &name[..len]
+}
+
+fn push_short_char(name: &mut String, c: u8, lowercase: bool) {
+    // Short names use an OEM code page, anything outside of ASCII is shown as a replacement
+    let c = if c.is_ascii() {
+        c as char
+    } else {
+        char::REPLACEMENT_CHARACTER
+    };
+    name.push(if lowercase { c.to_ascii_lowercase() } else { c });
+}
+
+/// A parsed directory entry, along with where it is stored on disk
+#[derive(Debug, Clone)]
+pub(crate) struct DirItem {
+    pub name: String,
+    pub entry: RawEntry,
+    /// Disk offset of the short entry
+    pub offset: u64,
+    /// Disk offsets of the long file name entries belonging to this item
+    pub lfn_offsets: Vec<u64>,
+}
+
+impl DirItem {
+    pub fn matches(&self, name: &str) -> bool {
+        // FAT names are case insensitive, the short name is an alias as well
+        self.name.eq_ignore_ascii_case(name) || self.entry.display_name().eq_ignore_ascii_case(name)
+    }
+}
+
+/// Every entry of a directory, and which of its slots are free
+struct DirScan {
+    items: Vec<DirItem>,
+    slots: Vec<(u64, bool)>,
+}
+
+/// Long file name entries collected while walking a directory
+struct PendingLfn {
+    checksum: u8,
+    expected: u8,
+    chars: Vec<u16>,
+    offsets: Vec<u64>,
+}
+
+fn lfn_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name.iter().fold(0u8, |sum, &c| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c)
+    })
+}
+
+impl<D> Volume<D>
+where
+    D: Read + Write + Seek,
+{
+    fn scan_dir(&mut self, dir: DirLocation) -> Result<DirScan, io::Error> {
+        let mut items = Vec::new();
+        let mut slots = Vec::new();
+        let mut pending: Option<PendingLfn> = None;
+        let mut ended = false;
+
+        for (start, len) in self.dir_regions(dir)? {
+            let mut region = vec![0u8; len as usize];
+            self.read_at(start, &mut region)?;
+
+            for (i, raw) in region.chunks_exact(ENTRY_SIZE).enumerate() {
+                let offset = start + (i * ENTRY_SIZE) as u64;
+
+                // Everything after the end marker is free, even if it holds garbage
+                ended |= raw[0] == END_MARKER;
+                let free = ended || raw[0] == DELETED_MARKER;
+                slots.push((offset, free));
+                if free {
+                    pending = None;
+                    continue;
+                }
+
+                let mut entry = RawEntry([0u8; ENTRY_SIZE]);
+                entry.0.copy_from_slice(raw);
+
+                if entry.attributes() & attr::LONG_NAME == attr::LONG_NAME {
+                    pending = Self::collect_lfn(pending.take(), &entry, offset);
+                    continue;
+                }
+
+                let lfn = pending.take();
+                if entry.attributes() & attr::VOLUME_ID != 0 {
+                    continue;
+                }
+                let short_name = entry.short_name();
+                if &short_name == b".          " || &short_name == b"..         " {
+                    continue;
+                }
+
+                let (name, lfn_offsets) = match lfn {
+                    Some(lfn) if lfn.expected == 0 && lfn.checksum == lfn_checksum(&short_name) => {
+                        let len = lfn
+                            .chars
+                            .iter()
+                            .position(|&c| c == 0)
+                            .unwrap_or(lfn.chars.len());
+                        let name = decode_utf16(lfn.chars[..len].iter().copied())
+                            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                            .collect();
+                        (name, lfn.offsets)
+                    }
+                    // Orphaned long names are ignored, as described in the specification
+                    _ => (entry.display_name(), Vec::new()),
+                };
+
+                items.push(DirItem {
+                    name,
+                    entry,
+                    offset,
+                    lfn_offsets,
+                });
+            }
+        }
+
+        Ok(DirScan { items, slots })
+    }
+
+    /// Adds a long file name entry to the pending long name
+    ///
+    /// Entries are stored last part first, with the ordinal counting down to 1
+    fn collect_lfn(
+        pending: Option<PendingLfn>,
+        entry: &RawEntry,
+        offset: u64,
+    ) -> Option<PendingLfn> {
+        let ordinal = entry.0[0];
+        let checksum = entry.0[13];
+        let index = (ordinal & !LFN_LAST_ENTRY) as usize;
+        if index == 0 || index * LFN_CHARS_PER_ENTRY > MAX_NAME_LEN + LFN_CHARS_PER_ENTRY {
+            return None;
+        }
+
+        let mut pending = if ordinal & LFN_LAST_ENTRY != 0 {
+            PendingLfn {
+                checksum,
+                expected: index as u8,
+                chars: vec![0xFFFF; index * LFN_CHARS_PER_ENTRY],
+                offsets: Vec::new(),
+            }
+        } else {
+            pending?
+        };
+
+        if pending.expected as usize != index || pending.checksum != checksum {
+            return None;
+        }
+
+        let base = (index - 1) * LFN_CHARS_PER_ENTRY;
+        for (i, &char_offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            pending.chars[base + i] = le_u16(&entry.0, char_offset);
+        }
+        pending.expected -= 1;
+        pending.offsets.push(offset);
+        Some(pending)
+    }
+
+    /// Lists every entry in the directory, excluding `.`, `..` and the volume label
+    pub fn read_dir_items(&mut self, dir: DirLocation) -> Result<Vec<DirItem>, io::Error> {
+        Ok(self.scan_dir(dir)?.items)
+    }
+
+    pub fn find(&mut self, dir: DirLocation, name: &str) -> Result<Option<DirItem>, io::Error> {
+        Ok(self
+            .read_dir_items(dir)?
+            .into_iter()
+            .find(|item| item.matches(name)))
+    }
+
+    /// Walks `path` from the root directory, and returns the directory it ends in
+    pub fn resolve_dir(&mut self, path: &[String]) -> Result<DirLocation, io::Error> {
+        let root = self.layout.root;
+        let mut dir = root;
+        for segment in path {
+            match self.find(dir, segment)? {
+                Some(item) if item.entry.is_dir() => dir = item.entry.dir_location(root),
+                Some(_) => return Err(io::Error::NotADirectory),
+                None => return Err(io::Error::NotFound),
+            }
+        }
+        Ok(dir)
+    }
+
+    pub fn write_entry(&mut self, offset: u64, entry: &RawEntry) -> Result<(), io::Error> {
+        self.write_at(offset, &entry.0)
+    }
+
+    pub fn read_entry(&mut self, offset: u64) -> Result<RawEntry, io::Error> {
+        let mut entry = RawEntry([0u8; ENTRY_SIZE]);
+        self.read_at(offset, &mut entry.0)?;
+        Ok(entry)
+    }
+
+    /// Creates a new entry named `name` in `dir`
+    ///
+    /// `entry` holds everything except for the short name, which is generated here
+    pub fn insert_entry(
+        &mut self,
+        dir: DirLocation,
+        name: &str,
+        mut entry: RawEntry,
+    ) -> Result<DirItem, io::Error> {
+        let name_utf16 = validate_name(name)?;
+        let scan = self.scan_dir(dir)?;
+        if scan.items.iter().any(|item| item.matches(name)) {
+            return Err(io::Error::AlreadyExists);
+        }
+
+        let (short_name, needs_lfn) = match exact_short_name(name) {
+            Some(short_name) => (short_name, false),
+            None => {
+                let existing: Vec<[u8; 11]> = scan
+                    .items
+                    .iter()
+                    .map(|item| item.entry.short_name())
+                    .collect();
+                (generate_short_name(name, &existing)?, true)
+            }
+        };
+        entry.0[..11].copy_from_slice(&short_name);
+        // The lowercase flags belong to whatever name the entry had before
+        entry.0[12] = 0;
+
+        let mut raw_entries = if needs_lfn {
+            lfn_entries(&name_utf16, lfn_checksum(&short_name))
+        } else {
+            Vec::new()
+        };
+        raw_entries.push(entry.clone());
+
+        let offsets = self.free_slots(dir, scan.slots, raw_entries.len())?;
+        for (offset, raw) in offsets.iter().zip(&raw_entries) {
+            self.write_entry(*offset, raw)?;
+        }
+
+        let (&offset, lfn_offsets) = offsets.split_last().expect("Entry has at least one slot");
+        Ok(DirItem {
+            name: name.into(),
+            entry,
+            offset,
+            lfn_offsets: lfn_offsets.to_vec(),
+        })
+    }
+
+    /// Finds `count` consecutive free slots in the directory, growing it if necessary
+    fn free_slots(
+        &mut self,
+        dir: DirLocation,
+        mut slots: Vec<(u64, bool)>,
+        count: usize,
+    ) -> Result<Vec<u64>, io::Error> {
+        loop {
+            let mut run = 0;
+            for (i, &(_, free)) in slots.iter().enumerate() {
+                run = if free { run + 1 } else { 0 };
+                if run == count {
+                    return Ok(slots[i + 1 - count..=i].iter().map(|s| s.0).collect());
+                }
+            }
+
+            // The root directory of FAT12/16 has a fixed size, and can't be extended
+            let DirLocation::Chain(first) = dir else {
+                return Err(io::Error::NoSpace);
+            };
+            let last = *self.chain(first)?.last().ok_or(io::Error::InvalidData)?;
+            let cluster = self.allocate_cluster(Some(last), true)?;
+            let start = self.layout.cluster_offset(cluster);
+            let new_slots = self.layout.cluster_size as usize / ENTRY_SIZE;
+            slots.extend((0..new_slots).map(|i| (start + (i * ENTRY_SIZE) as u64, true)));
+        }
+    }
+
+    /// Marks the entry, and its long file name entries as deleted
+    ///
+    /// Does not free the clusters of the entry
+    pub fn remove_entry(&mut self, item: &DirItem) -> Result<(), io::Error> {
+        for &offset in item.lfn_offsets.iter().chain([&item.offset]) {
+            self.write_at(offset, &[DELETED_MARKER])?;
+        }
+        Ok(())
+    }
+
+    /// Creates a new, empty directory with `.` and `..` entries
+    ///
+    /// Returns the first cluster of the new directory
+    pub fn create_dir_cluster(&mut self, parent: DirLocation) -> Result<u32, io::Error> {
+        let cluster = self.allocate_cluster(None, true)?;
+        let parent_cluster = self.parent_cluster(parent);
+
+        let start = self.layout.cluster_offset(cluster);
+        let dot = RawEntry::new(*b".          ", attr::DIRECTORY, cluster);
+        let dot_dot = RawEntry::new(*b"..         ", attr::DIRECTORY, parent_cluster);
+        self.write_entry(start, &dot)?;
+        self.write_entry(start + ENTRY_SIZE as u64, &dot_dot)?;
+
+        Ok(cluster)
+    }
+
+    /// Points the `..` entry of a moved directory at its new parent
+    pub fn set_parent(&mut self, cluster: u32, parent: DirLocation) -> Result<(), io::Error> {
+        let offset = self.layout.cluster_offset(cluster) + ENTRY_SIZE as u64;
+        let mut dot_dot = self.read_entry(offset)?;
+        dot_dot.set_first_cluster(self.parent_cluster(parent));
+        self.write_entry(offset, &dot_dot)
+    }
+
+    /// The cluster stored in `..` entries, the root directory is always cluster 0
+    fn parent_cluster(&self, parent: DirLocation) -> u32 {
+        match parent {
+            DirLocation::Chain(cluster) if parent != self.layout.root => cluster,
+            _ => 0,
+        }
+    }
+}
+
+/// Packs `time` into a FAT date and time, which have a two second resolution.
+/// Times outside of what FAT can store are clamped
+fn timestamp(time: SystemTime) -> (u16, u16) {
+    let mut date = DateTime::from(time);
+    if date.year < FIRST_YEAR {
+        date = DateTime {
+            year: FIRST_YEAR,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+    } else if date.year > LAST_YEAR {
+        date = DateTime {
+            year: LAST_YEAR,
+            month: 12,
+            day: 31,
+            hour: 23,
+            minute: 59,
+            second: 59,
+        };
+    }
+
+    let fat_date = ((date.year - FIRST_YEAR) << 9) | ((date.month as u16) << 5) | date.day as u16;
+    let fat_time =
+        ((date.hour as u16) << 11) | ((date.minute as u16) << 5) | (date.second as u16 / 2);
+    (fat_date, fat_time)
+}
+
+/// Checks that `name` is a valid long file name, and encodes it as UTF-16
+fn validate_name(name: &str) -> Result<Vec<u16>, io::Error> {
+    let invalid = |c: char| c < ' ' || "\"*/:<>?\\|".contains(c);
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.ends_with(['.', ' '])
+        || name.contains(invalid)
+    {
+        return Err(io::Error::InvalidPath);
+    }
+
+    let name: Vec<u16> = name.encode_utf16().collect();
+    if name.len() > MAX_NAME_LEN {
+        return Err(io::Error::InvalidPath);
+    }
+    Ok(name)
+}
+
+fn is_short_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || b"!#$%&'()-@^_`{}~".contains(&c)
+}
+
+/// Returns the 8.3 form of `name` if it can be stored without a long file name
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(is_short_char)
+    {
+        return None;
+    }
+
+    let mut short_name = [b' '; 11];
+    short_name[..base.len()].copy_from_slice(base.as_bytes());
+    short_name[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short_name)
+}
+
+/// Generates a unique `BASE~N.EXT` alias for a long file name
+fn generate_short_name(name: &str, existing: &[[u8; 11]]) -> Result<[u8; 11], io::Error> {
+    let to_short = |part: &str, max: usize| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let c = c.to_ascii_uppercase();
+                if c.is_ascii() && is_short_char(c as u8) {
+                    c as u8
+                } else {
+                    b'_'
+                }
+            })
+            .take(max)
+            .collect()
+    };
+
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rfind('.') {
+        Some(i) => (&trimmed[..i], &trimmed[i + 1..]),
+        None => (trimmed, ""),
+    };
+    let mut base = to_short(base, 8);
+    let ext = to_short(ext, 3);
+    if base.is_empty() {
+        base.push(b'_');
+    }
+
+    for n in 1..1_000_000u32 {
+        let tail = format!("~{n}");
+        let base_len = base.len().min(8 - tail.len());
+
+        let mut short_name = [b' '; 11];
+        short_name[..base_len].copy_from_slice(&base[..base_len]);
+        short_name[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+        short_name[8..8 + ext.len()].copy_from_slice(&ext);
+
+        if !existing.contains(&short_name) {
+            return Ok(short_name);
+        }
+    }
+
+    Err(io::Error::NoSpace)
+}
+
+/// Builds the long file name entries for `name`, in the order they are stored on disk
+fn lfn_entries(name: &[u16], checksum: u8) -> Vec<RawEntry> {
+    let count = name.len().div_ceil(LFN_CHARS_PER_ENTRY);
+
+    (1..=count)
+        .rev()
+        .map(|ordinal| {
+            let mut entry = RawEntry([0u8; ENTRY_SIZE]);
+            entry.0[0] = ordinal as u8 | if ordinal == count { LFN_LAST_ENTRY } else { 0 };
+            entry.0[11] = attr::LONG_NAME;
+            entry.0[13] = checksum;
+
+            let base = (ordinal - 1) * LFN_CHARS_PER_ENTRY;
+            for (i, &char_offset) in LFN_CHAR_OFFSETS.iter().enumerate() {
+                // The name is terminated by a NUL, and the rest is padded with 0xFFFF
+                let c = match (base + i).cmp(&name.len()) {
+                    Ordering::Less => name[base + i],
+                    Ordering::Equal => 0,
+                    Ordering::Greater => 0xFFFF,
+                };
+                entry.0[char_offset..char_offset + 2].copy_from_slice(&c.to_le_bytes());
+            }
+            entry
+        })
+        .collect()
+}
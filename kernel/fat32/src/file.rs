@@ -0,0 +1,318 @@
+use libk::{
+    alloc::{collections::BTreeMap, sync::Arc},
+    cmp::min,
+    io::{self, Read, Seek, SeekFrom, Write},
+    vec,
+    vec::Vec,
+    Mutex,
+};
+
+use crate::{dir::RawEntry, volume::Volume};
+
+/// An open regular file on a FAT volume
+///
+/// The directory entry is re-read on every operation, so multiple handles to
+/// the same file see each others changes
+pub struct FatFile<D> {
+    volume: Arc<Mutex<Volume<D>>>,
+    handle: u64,
+    position: u64,
+    read_only: bool,
+}
+
+/// Where the directory entry of every open file is
+///
+/// Handles look their entry up here instead of keeping its offset, so that they
+/// follow the file when it is renamed, and don't pick up a different file that
+/// is created in the slot of a removed one
+#[derive(Default)]
+pub(crate) struct OpenFiles {
+    next_handle: u64,
+    /// `None` once the file has been removed
+    offsets: BTreeMap<u64, Option<u64>>,
+}
+
+impl OpenFiles {
+    pub fn open(&mut self, offset: u64) -> u64 {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.offsets.insert(handle, Some(offset));
+        handle
+    }
+
+    fn close(&mut self, handle: u64) {
+        self.offsets.remove(&handle);
+    }
+
+    fn offset(&self, handle: u64) -> Result<u64, io::Error> {
+        self.offsets
+            .get(&handle)
+            .copied()
+            .flatten()
+            .ok_or(io::Error::NotFound)
+    }
+
+    /// Called when the entry at `from` has been moved to `to`
+    pub fn moved(&mut self, from: u64, to: u64) {
+        for offset in self.offsets.values_mut() {
+            if *offset == Some(from) {
+                *offset = Some(to);
+            }
+        }
+    }
+
+    /// Called when the entry at `offset` has been deleted
+    pub fn removed(&mut self, offset: u64) {
+        for entry in self.offsets.values_mut() {
+            if *entry == Some(offset) {
+                *entry = None;
+            }
+        }
+    }
+}
+
+impl<D> FatFile<D>
+where
+    D: Read + Write + Seek,
+{
+    /// `handle` comes from `OpenFiles::open`, and is closed when the file is dropped
+    pub(crate) fn new(volume: Arc<Mutex<Volume<D>>>, handle: u64, read_only: bool) -> Self {
+        Self {
+            volume,
+            handle,
+            position: 0,
+            read_only,
+        }
+    }
+
+    /// Size of the file in bytes
+    pub fn len(&self) -> Result<u64, io::Error> {
+        let mut volume = self.volume.lock();
+        let offset = volume.open_files.offset(self.handle)?;
+        Ok(volume.file_entry(offset)?.size() as u64)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, io::Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Truncates or extends the file to `len` bytes, new bytes are zeroed
+    pub fn set_len(&mut self, len: u64) -> Result<(), io::Error> {
+        if self.read_only {
+            return Err(io::Error::PermissionsError);
+        }
+        let mut volume = self.volume.lock();
+        let offset = volume.open_files.offset(self.handle)?;
+        volume.set_file_len(offset, len)
+    }
+}
+
+impl<D> Drop for FatFile<D> {
+    fn drop(&mut self) {
+        self.volume.lock().open_files.close(self.handle);
+    }
+}
+
+impl<D> Read for FatFile<D>
+where
+    D: Read + Write + Seek,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let mut volume = self.volume.lock();
+        let offset = volume.open_files.offset(self.handle)?;
+        let read = volume.read_file(offset, self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<D> Write for FatFile<D>
+where
+    D: Read + Write + Seek,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        if self.read_only {
+            return Err(io::Error::PermissionsError);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut volume = self.volume.lock();
+        let offset = volume.open_files.offset(self.handle)?;
+        volume.write_file(offset, self.position, buf)?;
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.volume.lock().flush()
+    }
+}
+
+impl<D> Seek for FatFile<D>
+where
+    D: Read + Write + Seek,
+{
+    fn seek(&mut self, seek_from: SeekFrom) -> Result<u64, io::Error> {
+        let new_pos = match seek_from {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => self.len()? as i64 + n,
+            SeekFrom::Current(n) => self.position as i64 + n,
+        };
+        if new_pos < 0 {
+            return Err(io::Error::NegativeSeekError);
+        }
+
+        self.position = new_pos as u64;
+        Ok(self.position)
+    }
+}
+
+impl<D> Volume<D>
+where
+    D: Read + Write + Seek,
+{
+    /// Reads the directory entry of an open file
+    pub(crate) fn file_entry(&mut self, offset: u64) -> Result<RawEntry, io::Error> {
+        let entry = self.read_entry(offset)?;
+        if entry.is_deleted() {
+            Err(io::Error::NotFound)
+        } else if entry.is_dir() {
+            Err(io::Error::IsDirectory)
+        } else {
+            Ok(entry)
+        }
+    }
+
+    /// Splits the byte range of a file into contiguous ranges on disk
+    fn extents(
+        &self,
+        chain: &[u32],
+        position: u64,
+        len: usize,
+    ) -> Result<Vec<(u64, usize)>, io::Error> {
+        let cluster_size = self.layout.cluster_size as u64;
+        let mut extents: Vec<(u64, usize)> = Vec::new();
+        let mut position = position;
+        let mut remaining = len;
+
+        while remaining > 0 {
+            let cluster = *chain
+                .get((position / cluster_size) as usize)
+                .ok_or(io::Error::InvalidData)?;
+            let within = position % cluster_size;
+            let len = min(remaining as u64, cluster_size - within) as usize;
+            let offset = self.layout.cluster_offset(cluster) + within;
+
+            match extents.last_mut() {
+                Some((start, extent_len)) if *start + *extent_len as u64 == offset => {
+                    *extent_len += len
+                }
+                _ => extents.push((offset, len)),
+            }
+
+            position += len as u64;
+            remaining -= len;
+        }
+
+        Ok(extents)
+    }
+
+    pub(crate) fn read_file(
+        &mut self,
+        offset: u64,
+        position: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, io::Error> {
+        let entry = self.file_entry(offset)?;
+        let size = entry.size() as u64;
+        if position >= size {
+            return Ok(0);
+        }
+
+        let len = min(buf.len() as u64, size - position) as usize;
+        let chain = self.chain(entry.first_cluster())?;
+
+        let mut done = 0;
+        for (disk_offset, extent_len) in self.extents(&chain, position, len)? {
+            self.read_at(disk_offset, &mut buf[done..done + extent_len])?;
+            done += extent_len;
+        }
+        Ok(len)
+    }
+
+    pub(crate) fn write_file(
+        &mut self,
+        offset: u64,
+        position: u64,
+        buf: &[u8],
+    ) -> Result<(), io::Error> {
+        let mut entry = self.file_entry(offset)?;
+        let size = entry.size() as u64;
+
+        // Sizes are stored as 32 bit integers
+        let end = position
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= u32::MAX as u64)
+            .ok_or(io::Error::NoSpace)?;
+
+        let mut chain = self.chain(entry.first_cluster())?;
+        let needed = end.div_ceil(self.layout.cluster_size as u64) as usize;
+        if needed > chain.len() {
+            let old_len = chain.len();
+            match self.resize_chain(&mut chain, needed) {
+                Ok(first) => entry.set_first_cluster(first),
+                Err(e) => {
+                    // Give back whatever was allocated before running out of space
+                    self.resize_chain(&mut chain, old_len)?;
+                    return Err(e);
+                }
+            }
+        }
+
+        // Writing past the end of the file leaves a gap, which has to be zeroed
+        if position > size {
+            let zeroes = vec![0u8; min(position - size, self.layout.cluster_size as u64) as usize];
+            let mut gap_start = size;
+            while gap_start < position {
+                let len = min(position - gap_start, zeroes.len() as u64) as usize;
+                self.write_extents(&chain, gap_start, &zeroes[..len])?;
+                gap_start += len as u64;
+            }
+        }
+        self.write_extents(&chain, position, buf)?;
+
+        if end > size {
+            entry.set_size(end as u32);
+        }
+        entry.touch();
+        self.write_entry(offset, &entry)
+    }
+
+    fn write_extents(&mut self, chain: &[u32], position: u64, buf: &[u8]) -> Result<(), io::Error> {
+        let mut done = 0;
+        for (disk_offset, extent_len) in self.extents(chain, position, buf.len())? {
+            self.write_at(disk_offset, &buf[done..done + extent_len])?;
+            done += extent_len;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_file_len(&mut self, offset: u64, len: u64) -> Result<(), io::Error> {
+        let mut entry = self.file_entry(offset)?;
+        if len > entry.size() as u64 {
+            // Extending is the same as an empty write at the new end of the file
+            return self.write_file(offset, len, &[]);
+        }
+
+        let mut chain = self.chain(entry.first_cluster())?;
+        let needed = len.div_ceil(self.layout.cluster_size as u64) as usize;
+        let first = self.resize_chain(&mut chain, needed)?;
+
+        entry.set_first_cluster(first);
+        entry.set_size(len as u32);
+        entry.touch();
+        self.write_entry(offset, &entry)
+    }
+}
@@ -1,4 +1,318 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-// Temporarily moved to driver_tester for testing convenience
+//! FAT12/16/32 filesystem driver
+//!
+//! Works on any seekable byte stream, such as a partition of a disk, or
+//! a `RamFile` holding an image when testing on the host
 
+mod bpb;
+mod dir;
+mod file;
+mod volume;
+
+pub use bpb::{Fat16EBPB, Fat32EBPB, FatBPB, FatInfo, FatType};
+pub use file::FatFile;
+
+use dir::{attr, RawEntry};
+use libk::{
+    alloc::sync::Arc,
+    boxed::Box,
+    fs::{DirEntry, Directory, File, Metadata},
+    io::{self, Path, Read, Seek, Write},
+    string::String,
+    vec::Vec,
+    Mutex,
+};
+use volume::{DirLocation, Volume};
+
+/// A mounted FAT volume
+///
+/// Cloning is cheap, and all clones refer to the same volume
+pub struct FatFs<D> {
+    volume: Arc<Mutex<Volume<D>>>,
+}
+
+impl<D> Clone for FatFs<D> {
+    fn clone(&self) -> Self {
+        Self {
+            volume: self.volume.clone(),
+        }
+    }
+}
+
+/// Splits a path into its non-empty segments
+fn segments(path: &Path) -> Vec<String> {
+    path.segments
+        .iter()
+        .filter(|segment| !segment.is_empty())
+        .cloned()
+        .collect()
+}
+
+impl<D> FatFs<D>
+where
+    D: Read + Write + Seek,
+{
+    /// Mount the FAT volume starting at offset 0 of `disk`
+    pub fn new(disk: D) -> Result<Self, io::Error> {
+        Ok(Self {
+            volume: Arc::new(Mutex::new(Volume::new(disk)?)),
+        })
+    }
+
+    pub fn info(&self) -> FatInfo {
+        self.volume.lock().info.clone()
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.volume.lock().layout.fat_type
+    }
+
+    /// Same as `Directory::open`, but returns the concrete file type
+    pub fn open_file<T>(&self, path: T, read_only: bool) -> Result<FatFile<D>, io::Error>
+    where
+        T: Into<Path>,
+    {
+        self.open_inner(segments(&path.into()), read_only, false)
+    }
+
+    /// Same as `Directory::create`, but returns the concrete file type
+    pub fn create_file<T>(&self, path: T) -> Result<FatFile<D>, io::Error>
+    where
+        T: Into<Path>,
+    {
+        self.open_inner(segments(&path.into()), false, true)
+    }
+
+    fn open_inner(
+        &self,
+        path: Vec<String>,
+        read_only: bool,
+        create: bool,
+    ) -> Result<FatFile<D>, io::Error> {
+        let (name, parent) = path.split_last().ok_or(io::Error::IsDirectory)?;
+
+        let mut volume = self.volume.lock();
+        let dir = volume.resolve_dir(parent)?;
+
+        let offset = match volume.find(dir, name)? {
+            Some(item) if item.entry.is_dir() => return Err(io::Error::IsDirectory),
+            Some(item) => {
+                if !read_only && item.entry.attributes() & attr::READ_ONLY != 0 {
+                    return Err(io::Error::PermissionsError);
+                }
+                if create {
+                    volume.set_file_len(item.offset, 0)?;
+                }
+                item.offset
+            }
+            None if create => {
+                let entry = RawEntry::new([b' '; 11], attr::ARCHIVE, 0);
+                volume.insert_entry(dir, name, entry)?.offset
+            }
+            None => return Err(io::Error::NotFound),
+        };
+
+        let handle = volume.open_files.open(offset);
+        Ok(FatFile::new(self.volume.clone(), handle, read_only))
+    }
+}
+
+impl<D> Directory for FatFs<D>
+where
+    D: Read + Write + Seek + Send + 'static,
+{
+    /// Creates the directory, along with any missing parent directories
+    fn mkdir(&self, path: &Path) -> Result<(), io::Error> {
+        let path = segments(path);
+        let mut volume = self.volume.lock();
+        let root = volume.layout.root;
+        let mut dir = root;
+
+        for segment in &path {
+            dir = match volume.find(dir, segment)? {
+                Some(item) if item.entry.is_dir() => item.entry.dir_location(root),
+                Some(_) => return Err(io::Error::NotADirectory),
+                None => {
+                    let cluster = volume.create_dir_cluster(dir)?;
+                    let entry = RawEntry::new([b' '; 11], attr::DIRECTORY, cluster);
+                    if let Err(e) = volume.insert_entry(dir, segment, entry) {
+                        volume.free_chain(cluster)?;
+                        return Err(e);
+                    }
+                    DirLocation::Chain(cluster)
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    fn open(&self, path: &Path, read_only: bool) -> Result<Box<dyn File>, io::Error> {
+        let file = self.open_inner(segments(path), read_only, false)?;
+        Ok(Box::new(file))
+    }
+
+    /// Opens the file for writing, creating it if it doesn't exist and truncating it if it does
+    fn create(&self, path: &Path) -> Result<Box<dyn File>, io::Error> {
+        let file = self.open_inner(segments(path), false, true)?;
+        Ok(Box::new(file))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, io::Error> {
+        let path = segments(path);
+        let mut volume = self.volume.lock();
+        let dir = volume.resolve_dir(&path)?;
+
+        let entries = volume
+            .read_dir_items(dir)?
+            .into_iter()
+            .map(|item| DirEntry {
+                metadata: item.entry.metadata(),
+                name: item.name,
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, io::Error> {
+        let path = segments(path);
+        let Some((name, parent)) = path.split_last() else {
+            return Ok(Metadata::directory());
+        };
+
+        let mut volume = self.volume.lock();
+        let dir = volume.resolve_dir(parent)?;
+        let item = volume.find(dir, name)?.ok_or(io::Error::NotFound)?;
+        Ok(item.entry.metadata())
+    }
+
+    /// Names are case insensitive, and files also have a short name, so the entries
+    /// the paths lead to are compared. Paths that don't exist are never the same
+    fn is_same(&self, a: &Path, b: &Path) -> Result<bool, io::Error> {
+        let (a, b) = (segments(a), segments(b));
+        if a.is_empty() || b.is_empty() {
+            return Ok(a.is_empty() && b.is_empty());
+        }
+
+        let mut volume = self.volume.lock();
+        let mut entry_offset = |path: &[String]| {
+            let (name, parent) = path.split_last().expect("Path is not the root");
+            match volume.resolve_dir(parent) {
+                Ok(dir) => Ok(volume.find(dir, name)?.map(|item| item.offset)),
+                Err(io::Error::NotFound | io::Error::NotADirectory) => Ok(None),
+                Err(e) => Err(e),
+            }
+        };
+        let a = entry_offset(&a)?;
+        let b = entry_offset(&b)?;
+        Ok(a.is_some() && a == b)
+    }
+
+    fn touch(&self, path: &Path) -> Result<(), io::Error> {
+        let path = segments(path);
+        // The root directory has no entry to keep its timestamps in
+        let Some((name, parent)) = path.split_last() else {
+            return Ok(());
+        };
+
+        let mut volume = self.volume.lock();
+        let dir = volume.resolve_dir(parent)?;
+        let mut item = volume.find(dir, name)?.ok_or(io::Error::NotFound)?;
+        item.entry.touch();
+        volume.write_entry(item.offset, &item.entry)
+    }
+
+    /// Deletes a regular file, and frees its clusters
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error> {
+        let path = segments(path);
+        let (name, parent) = path.split_last().ok_or(io::Error::IsDirectory)?;
+
+        let mut volume = self.volume.lock();
+        let dir = volume.resolve_dir(parent)?;
+        let item = volume.find(dir, name)?.ok_or(io::Error::NotFound)?;
+        if item.entry.is_dir() {
+            return Err(io::Error::IsDirectory);
+        }
+
+        volume.remove_entry(&item)?;
+        volume.open_files.removed(item.offset);
+        volume.free_chain(item.entry.first_cluster())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error> {
+        let path = segments(path);
+        // The root directory can't be removed
+        let (name, parent) = path.split_last().ok_or(io::Error::InvalidPath)?;
+
+        let mut volume = self.volume.lock();
+        let root = volume.layout.root;
+        let dir = volume.resolve_dir(parent)?;
+        let item = volume.find(dir, name)?.ok_or(io::Error::NotFound)?;
+        if !item.entry.is_dir() {
+            return Err(io::Error::NotADirectory);
+        }
+        if !volume
+            .read_dir_items(item.entry.dir_location(root))?
+            .is_empty()
+        {
+            return Err(io::Error::DirectoryNotEmpty);
+        }
+
+        volume.remove_entry(&item)?;
+        volume.free_chain(item.entry.first_cluster())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        let from = segments(from);
+        let to = segments(to);
+        let (from_name, from_parent) = from.split_last().ok_or(io::Error::InvalidPath)?;
+        let (to_name, to_parent) = to.split_last().ok_or(io::Error::InvalidPath)?;
+
+        let mut volume = self.volume.lock();
+        let root = volume.layout.root;
+        let from_dir = volume.resolve_dir(from_parent)?;
+        let item = volume
+            .find(from_dir, from_name)?
+            .ok_or(io::Error::NotFound)?;
+        let to_dir = volume.resolve_dir(to_parent)?;
+
+        if item.entry.is_dir() {
+            // A directory can't be moved inside of itself
+            let moved = item.entry.dir_location(root);
+            for depth in 0..=to_parent.len() {
+                if volume.resolve_dir(&to_parent[..depth])? == moved {
+                    return Err(io::Error::InvalidPath);
+                }
+            }
+        }
+
+        let moved = match volume.find(to_dir, to_name)? {
+            // Names are case insensitive, so this only changes the case of the name
+            Some(existing) if existing.offset == item.offset => {
+                volume.remove_entry(&item)?;
+                match volume.insert_entry(to_dir, to_name, item.entry.clone()) {
+                    Ok(moved) => moved,
+                    Err(e) => {
+                        let restored =
+                            volume.insert_entry(from_dir, &item.name, item.entry.clone())?;
+                        volume.open_files.moved(item.offset, restored.offset);
+                        return Err(e);
+                    }
+                }
+            }
+            Some(_) => return Err(io::Error::AlreadyExists),
+            None => {
+                let moved = volume.insert_entry(to_dir, to_name, item.entry.clone())?;
+                volume.remove_entry(&item)?;
+                moved
+            }
+        };
+        volume.open_files.moved(item.offset, moved.offset);
+
+        if item.entry.is_dir() && to_dir != from_dir {
+            volume.set_parent(item.entry.first_cluster(), to_dir)?;
+        }
+        Ok(())
+    }
+}
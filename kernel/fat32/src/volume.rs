@@ -0,0 +1,383 @@
+use libk::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    vec,
+    vec::Vec,
+};
+
+use crate::bpb::{self, le_u32, FatInfo, FatType, BOOT_SECTOR_SIZE};
+use crate::file::OpenFiles;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+const FSINFO_FREE_COUNT_OFFSET: u64 = 488;
+const FSINFO_NEXT_FREE_OFFSET: u64 = 492;
+
+/// The first cluster number that refers to the data region
+pub(crate) const FIRST_CLUSTER: u32 = 2;
+
+/// Location of a directory's entries on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DirLocation {
+    /// The fixed size root directory region of FAT12/16 volumes
+    FixedRoot,
+    /// A directory stored in the cluster chain starting at the given cluster
+    Chain(u32),
+}
+
+/// Byte offsets of the regions on the volume, derived from the BPB
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+    pub fat_type: FatType,
+    pub cluster_size: u32,
+    pub fat_start: u64,
+    pub fat_size: u64,
+    pub fat_count: u32,
+    pub root_dir_start: u64,
+    pub root_dir_size: u64,
+    pub data_start: u64,
+    pub cluster_count: u32,
+    pub root: DirLocation,
+    pub fsinfo: Option<u64>,
+}
+
+impl Layout {
+    fn new(info: &FatInfo) -> Self {
+        let bpb = info.get_bpb();
+        let bytes_per_sector = bpb.bytes_per_sector as u64;
+        let sectors_per_fat = info.sectors_per_fat() as u64;
+        let root_dir_sectors = bpb::root_dir_sectors(bpb) as u64;
+
+        let fat_start = bpb.reserved_sector_count as u64 * bytes_per_sector;
+        let fat_size = sectors_per_fat * bytes_per_sector;
+        let root_dir_start = fat_start + fat_size * bpb.table_count as u64;
+        let root_dir_size = root_dir_sectors * bytes_per_sector;
+        let data_start = root_dir_start + root_dir_size;
+
+        let data_sectors = bpb.total_sectors() as u64 - data_start / bytes_per_sector;
+        let cluster_count = (data_sectors / bpb.sectors_per_cluster as u64) as u32;
+
+        let (root, fsinfo) = match info {
+            FatInfo::Fat32 { ebpb, .. } => (
+                DirLocation::Chain(ebpb.root_cluster),
+                match ebpb.fsinfo {
+                    0 | 0xFFFF => None,
+                    sector => Some(sector as u64 * bytes_per_sector),
+                },
+            ),
+            _ => (DirLocation::FixedRoot, None),
+        };
+
+        Self {
+            fat_type: info.fat_type(),
+            cluster_size: bpb.sectors_per_cluster as u32 * bpb.bytes_per_sector as u32,
+            fat_start,
+            fat_size,
+            fat_count: bpb.table_count as u32,
+            root_dir_start,
+            root_dir_size,
+            data_start,
+            cluster_count,
+            root,
+            fsinfo,
+        }
+    }
+
+    pub fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster - FIRST_CLUSTER) as u64 * self.cluster_size as u64
+    }
+
+    pub fn is_valid_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..self.cluster_count + FIRST_CLUSTER).contains(&cluster)
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn is_end_of_chain(&self, entry: u32) -> bool {
+        entry >= self.end_of_chain() - 7
+    }
+}
+
+/// State of a mounted FAT volume, shared by all handles through a lock
+pub(crate) struct Volume<D> {
+    disk: D,
+    pub info: FatInfo,
+    pub layout: Layout,
+    next_free: u32,
+    fsinfo_invalidated: bool,
+    pub open_files: OpenFiles,
+}
+
+impl<D> Volume<D>
+where
+    D: Read + Write + Seek,
+{
+    pub fn new(mut disk: D) -> Result<Self, io::Error> {
+        let mut boot_sector = [0u8; BOOT_SECTOR_SIZE];
+        disk.seek(SeekFrom::Start(0))?;
+        disk.read_exact(&mut boot_sector)?;
+
+        let info = FatInfo::parse(&boot_sector)?;
+        let layout = Layout::new(&info);
+
+        if let DirLocation::Chain(root) = layout.root {
+            if !layout.is_valid_cluster(root) {
+                return Err(io::Error::InvalidData);
+            }
+        }
+
+        let mut volume = Self {
+            disk,
+            info,
+            layout,
+            next_free: FIRST_CLUSTER,
+            fsinfo_invalidated: false,
+            open_files: OpenFiles::default(),
+        };
+        volume.next_free = volume.read_fsinfo_hint().unwrap_or(FIRST_CLUSTER);
+
+        Ok(volume)
+    }
+
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+        self.disk.seek(SeekFrom::Start(offset))?;
+        self.disk.read_exact(buf)
+    }
+
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> Result<(), io::Error> {
+        self.disk.seek(SeekFrom::Start(offset))?;
+        self.disk.write_all(buf)
+    }
+
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.disk.flush()
+    }
+
+    /// Reads the next free cluster hint from the FAT32 FSInfo sector
+    fn read_fsinfo_hint(&mut self) -> Option<u32> {
+        let fsinfo = self.layout.fsinfo?;
+        let mut sector = [0u8; BOOT_SECTOR_SIZE];
+        self.read_at(fsinfo, &mut sector).ok()?;
+
+        if le_u32(&sector, 0) != FSINFO_LEAD_SIGNATURE
+            || le_u32(&sector, 484) != FSINFO_STRUCT_SIGNATURE
+        {
+            return None;
+        }
+
+        let hint = le_u32(&sector, FSINFO_NEXT_FREE_OFFSET as usize);
+        self.layout.is_valid_cluster(hint).then_some(hint)
+    }
+
+    /// Marks the free cluster count in FSInfo as unknown
+    ///
+    /// We don't keep the count up to date, so it has to be invalidated before
+    /// the first change to the FAT to avoid other systems trusting a stale value
+    fn invalidate_fsinfo(&mut self) -> Result<(), io::Error> {
+        if self.fsinfo_invalidated {
+            return Ok(());
+        }
+        self.fsinfo_invalidated = true;
+
+        if let Some(fsinfo) = self.layout.fsinfo {
+            if self.read_fsinfo_hint().is_some() {
+                self.write_at(fsinfo + FSINFO_FREE_COUNT_OFFSET, &u32::MAX.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the raw FAT entry for `cluster`
+    pub fn fat_entry(&mut self, cluster: u32) -> Result<u32, io::Error> {
+        let fat_start = self.layout.fat_start;
+        match self.layout.fat_type {
+            FatType::Fat12 => {
+                let mut buf = [0u8; 2];
+                self.read_at(fat_start + (cluster + cluster / 2) as u64, &mut buf)?;
+                let value = u16::from_le_bytes(buf);
+                Ok(if cluster & 1 == 0 {
+                    value & 0x0FFF
+                } else {
+                    value >> 4
+                } as u32)
+            }
+            FatType::Fat16 => {
+                let mut buf = [0u8; 2];
+                self.read_at(fat_start + cluster as u64 * 2, &mut buf)?;
+                Ok(u16::from_le_bytes(buf) as u32)
+            }
+            FatType::Fat32 => {
+                let mut buf = [0u8; 4];
+                self.read_at(fat_start + cluster as u64 * 4, &mut buf)?;
+                Ok(u32::from_le_bytes(buf) & 0x0FFF_FFFF)
+            }
+        }
+    }
+
+    /// Writes the FAT entry for `cluster` in every copy of the FAT
+    fn set_fat_entry(&mut self, cluster: u32, value: u32) -> Result<(), io::Error> {
+        self.invalidate_fsinfo()?;
+
+        for i in 0..self.layout.fat_count as u64 {
+            let fat_start = self.layout.fat_start + i * self.layout.fat_size;
+            match self.layout.fat_type {
+                FatType::Fat12 => {
+                    let offset = fat_start + (cluster + cluster / 2) as u64;
+                    let mut buf = [0u8; 2];
+                    self.read_at(offset, &mut buf)?;
+                    let old = u16::from_le_bytes(buf);
+                    let value = value as u16 & 0x0FFF;
+                    let new = if cluster & 1 == 0 {
+                        (old & 0xF000) | value
+                    } else {
+                        (old & 0x000F) | (value << 4)
+                    };
+                    self.write_at(offset, &new.to_le_bytes())?;
+                }
+                FatType::Fat16 => {
+                    let offset = fat_start + cluster as u64 * 2;
+                    self.write_at(offset, &(value as u16).to_le_bytes())?;
+                }
+                FatType::Fat32 => {
+                    // The upper 4 bits are reserved and have to be preserved
+                    let offset = fat_start + cluster as u64 * 4;
+                    let mut buf = [0u8; 4];
+                    self.read_at(offset, &mut buf)?;
+                    let new = (u32::from_le_bytes(buf) & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                    self.write_at(offset, &new.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster after `cluster` in its chain, or None at the end of the chain
+    pub fn next_cluster(&mut self, cluster: u32) -> Result<Option<u32>, io::Error> {
+        let entry = self.fat_entry(cluster)?;
+        if self.layout.is_end_of_chain(entry) {
+            Ok(None)
+        } else if self.layout.is_valid_cluster(entry) {
+            Ok(Some(entry))
+        } else {
+            // Free or bad clusters should never be part of a chain
+            Err(io::Error::InvalidData)
+        }
+    }
+
+    /// Collects the cluster chain starting at `first`
+    ///
+    /// A first cluster of 0 is used by empty files, and results in an empty chain
+    pub fn chain(&mut self, first: u32) -> Result<Vec<u32>, io::Error> {
+        let mut chain = Vec::new();
+        if first == 0 {
+            return Ok(chain);
+        }
+        if !self.layout.is_valid_cluster(first) {
+            return Err(io::Error::InvalidData);
+        }
+
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            // A chain can't be longer than the volume, anything else is a loop
+            if chain.len() > self.layout.cluster_count as usize {
+                return Err(io::Error::InvalidData);
+            }
+            chain.push(current);
+            cluster = self.next_cluster(current)?;
+        }
+        Ok(chain)
+    }
+
+    /// Allocates a free cluster, and appends it to the chain ending in `previous`
+    ///
+    /// Directories must be zero filled, so `zero` should be set for them
+    pub fn allocate_cluster(
+        &mut self,
+        previous: Option<u32>,
+        zero: bool,
+    ) -> Result<u32, io::Error> {
+        let count = self.layout.cluster_count;
+        let start = if self.layout.is_valid_cluster(self.next_free) {
+            self.next_free
+        } else {
+            FIRST_CLUSTER
+        };
+
+        let mut cluster = None;
+        for i in 0..count {
+            let candidate = FIRST_CLUSTER + (start - FIRST_CLUSTER + i) % count;
+            if self.fat_entry(candidate)? == 0 {
+                cluster = Some(candidate);
+                break;
+            }
+        }
+        let cluster = cluster.ok_or(io::Error::NoSpace)?;
+
+        let end_of_chain = self.layout.end_of_chain();
+        self.set_fat_entry(cluster, end_of_chain)?;
+        if let Some(previous) = previous {
+            self.set_fat_entry(previous, cluster)?;
+        }
+        self.next_free = cluster + 1;
+
+        if zero {
+            let zeroes = vec![0u8; self.layout.cluster_size as usize];
+            self.write_at(self.layout.cluster_offset(cluster), &zeroes)?;
+        }
+
+        Ok(cluster)
+    }
+
+    /// Makes `chain` exactly `len` clusters long, allocating or freeing clusters as needed
+    ///
+    /// Returns the new first cluster of the chain, which is 0 for empty chains
+    pub fn resize_chain(&mut self, chain: &mut Vec<u32>, len: usize) -> Result<u32, io::Error> {
+        while chain.len() < len {
+            let cluster = self.allocate_cluster(chain.last().copied(), false)?;
+            chain.push(cluster);
+        }
+
+        if chain.len() > len {
+            for &cluster in &chain[len..] {
+                self.set_fat_entry(cluster, 0)?;
+            }
+            chain.truncate(len);
+            if let Some(&last) = chain.last() {
+                let end_of_chain = self.layout.end_of_chain();
+                self.set_fat_entry(last, end_of_chain)?;
+            }
+        }
+
+        Ok(chain.first().copied().unwrap_or(0))
+    }
+
+    /// Frees every cluster in the chain starting at `first`
+    pub fn free_chain(&mut self, first: u32) -> Result<(), io::Error> {
+        let mut chain = self.chain(first)?;
+        self.resize_chain(&mut chain, 0)?;
+        Ok(())
+    }
+
+    /// Returns the contiguous byte regions on disk that hold the directory
+    pub fn dir_regions(&mut self, dir: DirLocation) -> Result<Vec<(u64, u64)>, io::Error> {
+        match dir {
+            DirLocation::FixedRoot => Ok(vec![(
+                self.layout.root_dir_start,
+                self.layout.root_dir_size,
+            )]),
+            DirLocation::Chain(first) => {
+                let cluster_size = self.layout.cluster_size as u64;
+                Ok(self
+                    .chain(first)?
+                    .into_iter()
+                    .map(|cluster| (self.layout.cluster_offset(cluster), cluster_size))
+                    .collect())
+            }
+        }
+    }
+}
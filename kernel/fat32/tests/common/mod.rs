@@ -0,0 +1,450 @@
+//! Golden FAT images for the integration tests
+//!
+//! The images are laid out byte by byte here instead of with the driver, so the
+//! tests check the driver against the on-disk format and not against itself
+
+#![allow(dead_code)]
+
+use fat32::{FatFs, FatType};
+use libk::io::ramfile::RamFile;
+use libk::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const SECTOR_SIZE: usize = 512;
+pub const ALL_TYPES: [FatType; 3] = [FatType::Fat12, FatType::Fat16, FatType::Fat32];
+
+pub const README: &[u8] = b"Hello from a golden image\n";
+pub const LOWER: &[u8] = b"stored as LOWER.TXT with the NTRes lowercase flags\n";
+pub const UNICODE: &[u8] = b"long names are UTF-16\n";
+
+pub type Image = Arc<Mutex<Vec<u8>>>;
+pub type Fs = FatFs<RamFile<Image>>;
+
+/// Contents of the fragmented file in the golden image, which spans three clusters
+pub fn long_file_contents(fat_type: FatType) -> Vec<u8> {
+    let len = Geometry::of(fat_type).cluster_size() * 2 + 100;
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+/// Mounts a copy of `image`, returning the shared buffer so it can be inspected and remounted
+pub fn mount(image: Vec<u8>) -> (Image, Fs) {
+    let image = Arc::new(Mutex::new(image));
+    let fs = remount(&image);
+    (image, fs)
+}
+
+pub fn remount(image: &Image) -> Fs {
+    FatFs::new(RamFile::new(image.clone(), false)).unwrap()
+}
+
+/// The smallest sensible layout for every FAT variant
+#[derive(Debug, Clone, Copy)]
+pub struct Geometry {
+    pub total_sectors: u32,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub root_entries: u16,
+    pub fat_size: u32,
+}
+
+impl Geometry {
+    pub fn of(fat_type: FatType) -> Self {
+        let (total_sectors, sectors_per_cluster, reserved_sectors, root_entries, fat_size) =
+            match fat_type {
+                // A 1.44MB floppy
+                FatType::Fat12 => (2880, 1, 1, 224, 9),
+                FatType::Fat16 => (65536, 4, 4, 512, 64),
+                FatType::Fat32 => (131072, 1, 32, 0, 1016),
+            };
+        Self {
+            total_sectors,
+            sectors_per_cluster,
+            reserved_sectors,
+            root_entries,
+            fat_size,
+        }
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster as usize * SECTOR_SIZE
+    }
+
+    pub fn fat_offset(&self, copy: u32) -> usize {
+        (self.reserved_sectors as u32 + copy * self.fat_size) as usize * SECTOR_SIZE
+    }
+
+    pub fn root_offset(&self) -> usize {
+        self.fat_offset(2)
+    }
+
+    pub fn data_offset(&self) -> usize {
+        self.root_offset() + self.root_entries as usize * 32
+    }
+
+    pub fn cluster_offset(&self, cluster: u32) -> usize {
+        self.data_offset() + (cluster as usize - 2) * self.cluster_size()
+    }
+}
+
+fn put16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// A freshly formatted, empty volume
+pub fn format(fat_type: FatType) -> Vec<u8> {
+    ImageBuilder::new(fat_type).finish()
+}
+
+/// A directory in an image that is being built
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dir {
+    /// The fixed root directory of FAT12/16, or the root cluster on FAT32
+    Root,
+    Cluster(u32),
+}
+
+/// Writes a volume by hand, entry by entry and cluster by cluster
+pub struct ImageBuilder {
+    pub fat_type: FatType,
+    pub geometry: Geometry,
+    image: Vec<u8>,
+    next_cluster: u32,
+    used_slots: HashMap<Dir, usize>,
+}
+
+impl ImageBuilder {
+    pub fn new(fat_type: FatType) -> Self {
+        let geometry = Geometry::of(fat_type);
+        let mut image = vec![0u8; geometry.total_sectors as usize * SECTOR_SIZE];
+
+        let boot = &mut image[..SECTOR_SIZE];
+        boot[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        boot[3..11].copy_from_slice(b"MSWIN4.1");
+        put16(boot, 11, SECTOR_SIZE as u16);
+        boot[13] = geometry.sectors_per_cluster;
+        put16(boot, 14, geometry.reserved_sectors);
+        boot[16] = 2;
+        put16(boot, 17, geometry.root_entries);
+        if geometry.total_sectors < 0x10000 {
+            put16(boot, 19, geometry.total_sectors as u16);
+        } else {
+            put32(boot, 32, geometry.total_sectors);
+        }
+        boot[21] = 0xF8;
+        if fat_type == FatType::Fat32 {
+            put32(boot, 36, geometry.fat_size);
+            // Root directory cluster, FSInfo sector and backup boot sector
+            put32(boot, 44, 2);
+            put16(boot, 48, 1);
+            put16(boot, 50, 6);
+            boot[66] = 0x29;
+            boot[71..82].copy_from_slice(b"NO NAME    ");
+            boot[82..90].copy_from_slice(b"FAT32   ");
+        } else {
+            put16(boot, 22, geometry.fat_size as u16);
+            boot[38] = 0x29;
+            boot[43..54].copy_from_slice(b"NO NAME    ");
+            boot[54..62].copy_from_slice(if fat_type == FatType::Fat12 {
+                b"FAT12   "
+            } else {
+                b"FAT16   "
+            });
+        }
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+
+        let mut builder = Self {
+            fat_type,
+            geometry,
+            image,
+            next_cluster: 2,
+            used_slots: HashMap::new(),
+        };
+
+        // The first two FAT entries hold the media type and the end of chain marker
+        builder.set_fat(0, 0x0FFF_FFF8);
+        builder.set_fat(1, 0x0FFF_FFFF);
+        if fat_type == FatType::Fat32 {
+            let root = builder.alloc_chain(1, 1)[0];
+            assert_eq!(root, 2);
+        }
+        builder
+    }
+
+    /// Writes the FSInfo sector of FAT32 volumes, and returns the finished image
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.fat_type == FatType::Fat32 {
+            let fsinfo = &mut self.image[SECTOR_SIZE..2 * SECTOR_SIZE];
+            put32(fsinfo, 0, 0x4161_5252);
+            put32(fsinfo, 484, 0x6141_7272);
+            put32(fsinfo, 488, u32::MAX);
+            put32(fsinfo, 492, self.next_cluster);
+            fsinfo[510] = 0x55;
+            fsinfo[511] = 0xAA;
+        }
+        self.image
+    }
+
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u32) {
+        for copy in 0..2 {
+            let fat = self.geometry.fat_offset(copy);
+            match self.fat_type {
+                FatType::Fat12 => {
+                    let offset = fat + (cluster + cluster / 2) as usize;
+                    let old = u16::from_le_bytes([self.image[offset], self.image[offset + 1]]);
+                    let value = value as u16 & 0x0FFF;
+                    let new = if cluster & 1 == 0 {
+                        (old & 0xF000) | value
+                    } else {
+                        (old & 0x000F) | (value << 4)
+                    };
+                    put16(&mut self.image, offset, new);
+                }
+                FatType::Fat16 => put16(&mut self.image, fat + cluster as usize * 2, value as u16),
+                FatType::Fat32 => put32(&mut self.image, fat + cluster as usize * 4, value),
+            }
+        }
+    }
+
+    /// Allocates a chain of `count` clusters, `stride` apart to leave free gaps between them
+    fn alloc_chain(&mut self, count: usize, stride: u32) -> Vec<u32> {
+        let chain: Vec<u32> = (0..count as u32)
+            .map(|i| self.next_cluster + i * stride)
+            .collect();
+        self.next_cluster = chain.last().map_or(self.next_cluster, |last| last + 1);
+
+        for pair in chain.windows(2) {
+            self.set_fat(pair[0], pair[1]);
+        }
+        if let Some(&last) = chain.last() {
+            self.set_fat(last, self.end_of_chain());
+        }
+        chain
+    }
+
+    fn write_chain(&mut self, chain: &[u32], contents: &[u8]) {
+        let cluster_size = self.geometry.cluster_size();
+        for (&cluster, chunk) in chain.iter().zip(contents.chunks(cluster_size)) {
+            let offset = self.geometry.cluster_offset(cluster);
+            self.image[offset..offset + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+
+    /// Appends a raw 32 byte entry to `dir`
+    fn push_entry(&mut self, dir: Dir, entry: &[u8; 32]) {
+        let slot = self.used_slots.entry(dir).or_default();
+        let offset = match dir {
+            Dir::Root if self.fat_type != FatType::Fat32 => {
+                assert!(*slot < self.geometry.root_entries as usize);
+                self.geometry.root_offset()
+            }
+            Dir::Root => self.geometry.cluster_offset(2),
+            Dir::Cluster(cluster) => self.geometry.cluster_offset(cluster),
+        } + *slot * 32;
+        *slot += 1;
+
+        self.image[offset..offset + 32].copy_from_slice(entry);
+    }
+
+    fn short_entry(
+        short_name: &[u8; 11],
+        attributes: u8,
+        ntres: u8,
+        cluster: u32,
+        size: u32,
+    ) -> [u8; 32] {
+        let mut entry = [0u8; 32];
+        entry[..11].copy_from_slice(short_name);
+        entry[11] = attributes;
+        entry[12] = ntres;
+        // 2024-06-15 12:30:00
+        let date = ((2024 - 1980) << 9) | (6 << 5) | 15;
+        let time = (12 << 11) | (30 << 5);
+        for offset in [14, 22] {
+            put16(&mut entry, offset, time);
+        }
+        for offset in [16, 18, 24] {
+            put16(&mut entry, offset, date);
+        }
+        put16(&mut entry, 20, (cluster >> 16) as u16);
+        put16(&mut entry, 26, cluster as u16);
+        put32(&mut entry, 28, size);
+        entry
+    }
+
+    /// Writes the long file name entries for `name`, which belong to the short entry `short_name`
+    fn push_long_name(&mut self, dir: Dir, name: &str, short_name: &[u8; 11]) {
+        let checksum = short_name.iter().fold(0u8, |sum, &c| {
+            ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c)
+        });
+        let mut chars: Vec<u16> = name.encode_utf16().collect();
+        if !chars.len().is_multiple_of(13) {
+            chars.push(0);
+        }
+        chars.resize(chars.len().div_ceil(13) * 13, 0xFFFF);
+
+        let count = chars.len() / 13;
+        for ordinal in (1..=count).rev() {
+            let mut entry = [0u8; 32];
+            entry[0] = ordinal as u8 | if ordinal == count { 0x40 } else { 0 };
+            entry[11] = 0x0F;
+            entry[13] = checksum;
+            let offsets = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+            for (i, offset) in offsets.into_iter().enumerate() {
+                put16(&mut entry, offset, chars[(ordinal - 1) * 13 + i]);
+            }
+            self.push_entry(dir, &entry);
+        }
+    }
+
+    /// Adds a file with an 8.3 name, `ntres` holds the lowercase flags
+    pub fn add_file(&mut self, dir: Dir, short_name: &[u8; 11], ntres: u8, contents: &[u8]) {
+        self.add_file_inner(dir, None, short_name, ntres, contents, 1);
+    }
+
+    /// Adds a file with a long name, with its clusters spread out over the disk
+    pub fn add_long_file(&mut self, dir: Dir, name: &str, short_name: &[u8; 11], contents: &[u8]) {
+        self.add_file_inner(dir, Some(name), short_name, 0, contents, 2);
+    }
+
+    fn add_file_inner(
+        &mut self,
+        dir: Dir,
+        long_name: Option<&str>,
+        short_name: &[u8; 11],
+        ntres: u8,
+        contents: &[u8],
+        stride: u32,
+    ) {
+        let clusters = contents.len().div_ceil(self.geometry.cluster_size());
+        let chain = self.alloc_chain(clusters, stride);
+        self.write_chain(&chain, contents);
+
+        if let Some(name) = long_name {
+            self.push_long_name(dir, name, short_name);
+        }
+        let first = chain.first().copied().unwrap_or(0);
+        let entry = Self::short_entry(short_name, 0x20, ntres, first, contents.len() as u32);
+        self.push_entry(dir, &entry);
+    }
+
+    /// Adds an empty directory with its `.` and `..` entries
+    pub fn add_dir(&mut self, parent: Dir, short_name: &[u8; 11]) -> Dir {
+        let cluster = self.alloc_chain(1, 1)[0];
+        let entry = Self::short_entry(short_name, 0x10, 0, cluster, 0);
+        self.push_entry(parent, &entry);
+
+        let dir = Dir::Cluster(cluster);
+        // `..` points at cluster 0 when the parent is the root, even on FAT32
+        let parent_cluster = match parent {
+            Dir::Root => 0,
+            Dir::Cluster(cluster) => cluster,
+        };
+        self.push_entry(dir, &Self::short_entry(b".          ", 0x10, 0, cluster, 0));
+        self.push_entry(
+            dir,
+            &Self::short_entry(b"..         ", 0x10, 0, parent_cluster, 0),
+        );
+        dir
+    }
+
+    /// Adds an entry that has been deleted, which has to be skipped
+    pub fn add_deleted(&mut self, dir: Dir, short_name: &[u8; 11]) {
+        let mut entry = Self::short_entry(short_name, 0x20, 0, 0, 0);
+        entry[0] = 0xE5;
+        self.push_entry(dir, &entry);
+    }
+
+    pub fn add_volume_label(&mut self, label: &[u8; 11]) {
+        let entry = Self::short_entry(label, 0x08, 0, 0, 0);
+        self.push_entry(Dir::Root, &entry);
+    }
+}
+
+/// The golden image used by most tests
+///
+/// ```text
+/// /
+/// ├── README.TXT
+/// ├── lower.txt
+/// └── DOCS/
+///     ├── A long file name.txt (fragmented, ALONGF~1.TXT)
+///     ├── Ünïcödé.txt
+///     └── EMPTY.DAT
+/// ```
+///
+/// The root also holds a volume label and a deleted entry, neither of which is listed
+pub fn golden(fat_type: FatType) -> Vec<u8> {
+    let mut builder = ImageBuilder::new(fat_type);
+    builder.add_volume_label(b"GOLDEN     ");
+    builder.add_file(Dir::Root, b"README  TXT", 0, README);
+    builder.add_deleted(Dir::Root, b"GONE    TXT");
+    builder.add_file(Dir::Root, b"LOWER   TXT", 0x18, LOWER);
+
+    let docs = builder.add_dir(Dir::Root, b"DOCS       ");
+    builder.add_long_file(
+        docs,
+        "A long file name.txt",
+        b"ALONGF~1TXT",
+        &long_file_contents(fat_type),
+    );
+    builder.add_long_file(docs, "Ünïcödé.txt", b"NICD~1  TXT", UNICODE);
+    builder.add_file(docs, b"EMPTY   DAT", 0, b"");
+    builder.finish()
+}
+
+/// Reads a raw FAT entry straight from the image
+pub fn fat_entry(image: &[u8], fat_type: FatType, cluster: u32) -> u32 {
+    let fat = Geometry::of(fat_type).fat_offset(0);
+    match fat_type {
+        FatType::Fat12 => {
+            let offset = fat + (cluster + cluster / 2) as usize;
+            let value = u16::from_le_bytes([image[offset], image[offset + 1]]);
+            (if cluster & 1 == 0 {
+                value & 0x0FFF
+            } else {
+                value >> 4
+            }) as u32
+        }
+        FatType::Fat16 => {
+            let offset = fat + cluster as usize * 2;
+            u16::from_le_bytes([image[offset], image[offset + 1]]) as u32
+        }
+        FatType::Fat32 => {
+            let offset = fat + cluster as usize * 4;
+            u32::from_le_bytes(image[offset..offset + 4].try_into().unwrap()) & 0x0FFF_FFFF
+        }
+    }
+}
+
+/// Finds the short entry named `short_name` in the root directory of the image
+pub fn root_entry(image: &[u8], fat_type: FatType, short_name: &[u8; 11]) -> Option<[u8; 32]> {
+    let geometry = Geometry::of(fat_type);
+    let (offset, len) = match fat_type {
+        FatType::Fat32 => (geometry.cluster_offset(2), geometry.cluster_size()),
+        _ => (geometry.root_offset(), geometry.root_entries as usize * 32),
+    };
+
+    image[offset..offset + len]
+        .chunks_exact(32)
+        .find(|entry| &entry[..11] == short_name && entry[11] != 0x0F)
+        .map(|entry| entry.try_into().unwrap())
+}
+
+pub fn first_cluster(entry: &[u8; 32]) -> u32 {
+    (u16::from_le_bytes([entry[20], entry[21]]) as u32) << 16
+        | u16::from_le_bytes([entry[26], entry[27]]) as u32
+}
@@ -0,0 +1,285 @@
+//! Reads and modifies the golden images built by `common`, on every FAT variant
+
+mod common;
+
+use common::*;
+use fat32::{FatFs, FatType};
+use libk::fs::{Directory, File, Metadata};
+use libk::io::{self, ramfile::RamFile, Path, SeekFrom};
+use libk::time::{set_system_time, Duration, UNIX_EPOCH};
+use std::sync::Arc;
+
+fn read_all(mut file: Box<dyn File>) -> Vec<u8> {
+    let mut contents = Vec::new();
+    let mut buf = [0u8; 700];
+    loop {
+        match file.read(&mut buf).unwrap() {
+            0 => return contents,
+            n => contents.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+fn read_path(fs: &Fs, path: &str) -> Vec<u8> {
+    read_all(fs.open(&Path::from(path), true).unwrap())
+}
+
+fn names(fs: &Fs, path: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs
+        .read_dir(&Path::from(path))
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn detects_fat_type() {
+    for fat_type in ALL_TYPES {
+        let (_, fs) = mount(format(fat_type));
+        assert_eq!(fs.fat_type(), fat_type);
+        assert!(fs.read_dir(&Path::root()).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn rejects_missing_boot_signature() {
+    let mut image = format(FatType::Fat16);
+    image[510] = 0;
+    let image = Arc::new(libk::Mutex::new(image));
+
+    assert!(matches!(
+        FatFs::new(RamFile::new(image, false)),
+        Err(io::Error::InvalidData)
+    ));
+}
+
+#[test]
+fn reads_golden_tree() {
+    for fat_type in ALL_TYPES {
+        let (_, fs) = mount(golden(fat_type));
+
+        assert_eq!(names(&fs, ""), ["DOCS", "README.TXT", "lower.txt"]);
+        assert_eq!(
+            names(&fs, "DOCS"),
+            ["A long file name.txt", "EMPTY.DAT", "Ünïcödé.txt"]
+        );
+
+        let long_len = long_file_contents(fat_type).len() as u64;
+        assert_eq!(
+            fs.metadata(&Path::from("DOCS")).unwrap(),
+            Metadata::directory()
+        );
+        assert_eq!(
+            fs.metadata(&Path::from("DOCS/A long file name.txt"))
+                .unwrap(),
+            Metadata::file(long_len)
+        );
+        assert_eq!(
+            fs.metadata(&Path::from("DOCS/EMPTY.DAT")).unwrap(),
+            Metadata::file(0)
+        );
+
+        assert_eq!(read_path(&fs, "README.TXT"), README);
+        assert_eq!(read_path(&fs, "lower.txt"), LOWER);
+        assert_eq!(read_path(&fs, "DOCS/Ünïcödé.txt"), UNICODE);
+        assert!(read_path(&fs, "DOCS/EMPTY.DAT").is_empty());
+    }
+}
+
+#[test]
+fn names_are_case_insensitive_and_have_short_aliases() {
+    for fat_type in ALL_TYPES {
+        let (_, fs) = mount(golden(fat_type));
+        let contents = long_file_contents(fat_type);
+
+        assert_eq!(read_path(&fs, "docs/a LONG file NAME.TXT"), contents);
+        assert_eq!(read_path(&fs, "DOCS/ALONGF~1.TXT"), contents);
+        assert_eq!(read_path(&fs, "LOWER.TXT"), LOWER);
+        assert!(matches!(
+            fs.open(&Path::from("GONE.TXT"), true),
+            Err(io::Error::NotFound)
+        ));
+        assert!(matches!(
+            fs.open(&Path::from("README.TXT/x"), true),
+            Err(io::Error::NotFound | io::Error::NotADirectory)
+        ));
+    }
+}
+
+#[test]
+fn seeks_across_fragmented_clusters() {
+    for fat_type in ALL_TYPES {
+        let (_, fs) = mount(golden(fat_type));
+        let contents = long_file_contents(fat_type);
+        let cluster_size = Geometry::of(fat_type).cluster_size();
+        let mut file = fs
+            .open(&Path::from("DOCS/A long file name.txt"), true)
+            .unwrap();
+
+        // Straddle the gap between the first and second cluster
+        let start = cluster_size - 10;
+        file.seek(SeekFrom::Start(start as u64)).unwrap();
+        let mut buf = [0u8; 20];
+        file.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, contents[start..start + 20]);
+
+        file.seek(SeekFrom::End(-5)).unwrap();
+        assert_eq!(read_all(file), contents[contents.len() - 5..]);
+    }
+}
+
+#[test]
+fn new_entries_use_the_on_disk_format() {
+    for fat_type in ALL_TYPES {
+        let (image, fs) = mount(format(fat_type));
+        fs.create(&Path::from("NEW.TXT"))
+            .unwrap()
+            .write_all(b"fresh")
+            .unwrap();
+        fs.create(&Path::from("Mixed Case.txt")).unwrap();
+        fs.mkdir(&Path::from("SUB")).unwrap();
+
+        let image = image.lock();
+        let entry = root_entry(&image, fat_type, b"NEW     TXT").unwrap();
+        assert_eq!(entry[11], 0x20);
+        assert_eq!(u32::from_le_bytes(entry[28..32].try_into().unwrap()), 5);
+        let cluster = first_cluster(&entry);
+        let offset = Geometry::of(fat_type).cluster_offset(cluster);
+        assert_eq!(&image[offset..offset + 5], b"fresh");
+        assert!(fat_entry(&image, fat_type, cluster) >= 0xFF8);
+
+        let entry = root_entry(&image, fat_type, b"MIXEDC~1TXT").unwrap();
+        assert_eq!(first_cluster(&entry), 0);
+
+        let entry = root_entry(&image, fat_type, b"SUB        ").unwrap();
+        assert_eq!(entry[11], 0x10);
+        let offset = Geometry::of(fat_type).cluster_offset(first_cluster(&entry));
+        assert_eq!(&image[offset..offset + 11], b".          ");
+        assert_eq!(&image[offset + 32..offset + 43], b"..         ");
+        // `..` refers to the root as cluster 0
+        assert_eq!(
+            first_cluster(image[offset + 32..offset + 64].try_into().unwrap()),
+            0
+        );
+    }
+}
+
+#[test]
+fn removing_frees_clusters() {
+    for fat_type in ALL_TYPES {
+        let (image, fs) = mount(golden(fat_type));
+        let cluster = first_cluster(&root_entry(&image.lock(), fat_type, b"README  TXT").unwrap());
+
+        fs.remove_file(&Path::from("README.TXT")).unwrap();
+        assert_eq!(fat_entry(&image.lock(), fat_type, cluster), 0);
+        assert!(root_entry(&image.lock(), fat_type, b"README  TXT").is_none());
+
+        assert!(matches!(
+            fs.remove_dir(&Path::from("DOCS")),
+            Err(io::Error::DirectoryNotEmpty)
+        ));
+    }
+}
+
+#[test]
+fn changes_survive_remount() {
+    for fat_type in ALL_TYPES {
+        let (image, fs) = mount(golden(fat_type));
+        let payload: Vec<u8> = (0..10_000u32).map(|i| (i * 7) as u8).collect();
+
+        fs.mkdir(&Path::from("new/nested")).unwrap();
+        fs.create(&Path::from("new/nested/Payload.bin"))
+            .unwrap()
+            .write_all(&payload)
+            .unwrap();
+        fs.rename(
+            &Path::from("DOCS/A long file name.txt"),
+            &Path::from("new/Moved and renamed.txt"),
+        )
+        .unwrap();
+        fs.rename(&Path::from("lower.txt"), &Path::from("LOWER.txt"))
+            .unwrap();
+        fs.remove_file(&Path::from("DOCS/EMPTY.DAT")).unwrap();
+        drop(fs);
+
+        let fs = remount(&image);
+        assert_eq!(names(&fs, ""), ["DOCS", "LOWER.txt", "README.TXT", "new"]);
+        assert_eq!(names(&fs, "DOCS"), ["Ünïcödé.txt"]);
+        assert_eq!(names(&fs, "new"), ["Moved and renamed.txt", "nested"]);
+        assert_eq!(read_path(&fs, "new/nested/payload.bin"), payload);
+        assert_eq!(
+            read_path(&fs, "new/Moved and renamed.txt"),
+            long_file_contents(fat_type)
+        );
+        assert_eq!(read_path(&fs, "README.TXT"), README);
+        assert_eq!(read_path(&fs, "LOWER.txt"), LOWER);
+    }
+}
+
+#[test]
+fn open_handles_follow_their_file() {
+    for fat_type in ALL_TYPES {
+        let (_, fs) = mount(golden(fat_type));
+        let renamed = fs.open(&Path::from("README.TXT"), true).unwrap();
+        fs.rename(&Path::from("README.TXT"), &Path::from("DOCS/Renamed.txt"))
+            .unwrap();
+        assert_eq!(read_all(renamed), README);
+
+        // The new file takes the slot of the removed one, but isn't the same file
+        let (_, fs) = mount(format(fat_type));
+        fs.create(&Path::from("a.txt")).unwrap();
+        let mut removed = fs.open(&Path::from("a.txt"), false).unwrap();
+        fs.remove_file(&Path::from("a.txt")).unwrap();
+        fs.create(&Path::from("b.txt"))
+            .unwrap()
+            .write_all(b"other")
+            .unwrap();
+        assert!(matches!(removed.write(b"x"), Err(io::Error::NotFound)));
+        assert!(matches!(
+            removed.seek(SeekFrom::End(0)),
+            Err(io::Error::NotFound)
+        ));
+        assert_eq!(read_path(&fs, "b.txt"), b"other");
+    }
+}
+
+#[test]
+fn touch_sets_the_modification_time() {
+    // 2030-06-15 12:00:00 UTC
+    set_system_time(UNIX_EPOCH + Duration::from_secs(1_907_755_200));
+    let date = ((2030 - 1980) << 9 | 6 << 5 | 15u16).to_le_bytes();
+    let time = (12u16 << 11).to_le_bytes();
+
+    for fat_type in ALL_TYPES {
+        let (image, fs) = mount(golden(fat_type));
+        fs.touch(&Path::from("README.TXT")).unwrap();
+
+        let entry = root_entry(&image.lock(), fat_type, b"README  TXT").unwrap();
+        assert_eq!(entry[22..24], time);
+        assert_eq!(entry[24..26], date);
+        assert_eq!(read_path(&fs, "README.TXT"), README);
+        assert!(matches!(
+            fs.touch(&Path::from("missing")),
+            Err(io::Error::NotFound)
+        ));
+    }
+}
+
+#[test]
+fn same_entries_compare_by_case_and_alias() {
+    for fat_type in ALL_TYPES {
+        let (_, fs) = mount(golden(fat_type));
+        let same = |a: &str, b: &str| fs.is_same(&Path::from(a), &Path::from(b)).unwrap();
+
+        assert!(same("README.TXT", "readme.txt"));
+        assert!(same("docs/a long file name.txt", "DOCS/ALONGF~1.TXT"));
+        assert!(same("", ""));
+        assert!(!same("README.TXT", "LOWER.TXT"));
+        assert!(!same("DOCS", ""));
+        assert!(!same("missing", "MISSING"));
+        assert!(!same("README.TXT/x", "README.TXT/x"));
+    }
+}
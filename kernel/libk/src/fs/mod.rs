@@ -0,0 +1,91 @@
+use crate::boxed::Box;
+use crate::io::{self, Path, Read, Seek, Write};
+use crate::string::String;
+use crate::vec::Vec;
+
+/// A directory tree that files can be opened and created in
+///
+/// Implemented by every filesystem driver, paths are relative to the directory.
+/// The trait is object safe, so filesystems can be mounted as `dyn Directory`
+pub trait Directory: Send + Sync {
+    fn mkdir(&self, path: &Path) -> Result<(), io::Error>;
+
+    fn open(&self, path: &Path, read_only: bool) -> Result<Box<dyn File>, io::Error>;
+
+    fn create(&self, path: &Path) -> Result<Box<dyn File>, io::Error>;
+
+    /// Lists the entries of the directory at `path`, without `.` and `..`
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, io::Error>;
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, io::Error>;
+
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error>;
+
+    /// Removes a directory, fails with `DirectoryNotEmpty` if it still has entries
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error>;
+
+    /// Moves a file or directory, fails with `AlreadyExists` if `to` exists
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error>;
+
+    /// Checks if `a` and `b` name the same file or directory
+    ///
+    /// Filesystems that ignore the case of names, or have other names for entries,
+    /// compare what the paths refer to. By default only equal paths are the same
+    fn is_same(&self, a: &Path, b: &Path) -> Result<bool, io::Error> {
+        Ok(a == b)
+    }
+
+    /// Sets the modification time of `path` to now
+    ///
+    /// Filesystems that don't keep timestamps only check that it exists
+    fn touch(&self, path: &Path) -> Result<(), io::Error> {
+        self.metadata(path).map(|_| ())
+    }
+}
+
+pub trait File: Read + Write + Seek + Send {}
+impl<T> File for T where T: Read + Write + Seek + Send {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub file_type: FileType,
+    /// Size in bytes, always 0 for directories
+    pub len: u64,
+}
+
+impl Metadata {
+    pub fn directory() -> Self {
+        Self {
+            file_type: FileType::Directory,
+            len: 0,
+        }
+    }
+
+    pub fn file(len: u64) -> Self {
+        Self {
+            file_type: FileType::File,
+            len,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+}
+
+/// A single entry returned by `Directory::read_dir`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub metadata: Metadata,
+}
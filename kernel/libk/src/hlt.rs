@@ -1,6 +1,17 @@
-use core::arch::asm;
+/// Waits for the next interrupt
+#[cfg(target_os = "none")]
+pub fn hlt() {
+    unsafe { core::arch::asm!("hlt") }
+}
+
+/// Hosted builds can't halt, under `cargo test` other threads play the part of
+/// interrupt handlers, so give them a chance to run
+#[cfg(all(not(target_os = "none"), test))]
+pub fn hlt() {
+    std::thread::yield_now()
+}
 
-// TODO explicitly check if platform is x86
+#[cfg(all(not(target_os = "none"), not(test)))]
 pub fn hlt() {
-    unsafe { asm!("hlt") }
+    core::hint::spin_loop()
 }
@@ -1,8 +1,407 @@
-use alloc::vec::Vec;
+use crate::{
+    alloc::sync::Arc,
+    boxed::Box,
+    cmp::min,
+    io::{self, Read, Seek, SeekFrom, Write},
+    vec,
+    vec::Vec,
+    Mutex,
+};
 
-use super::ramfile::RamFile;
+/// A device that is read and written in whole sectors, like a disk
+///
+/// Buffers passed to `read_sectors` and `write_sectors` must be a multiple of
+/// the sector size, and transfer that many consecutive sectors
+pub trait BlockDevice {
+    /// Size of a single sector in bytes
+    fn sector_size(&self) -> usize;
 
-pub enum BlockSize {
-    Bytes512,
-    
-}
\ No newline at end of file
+    /// Total number of sectors on the device
+    fn sector_count(&self) -> u64;
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<(), io::Error>;
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> Result<(), io::Error>;
+
+    fn flush(&mut self) -> Result<(), io::Error>;
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// Size of the device in bytes
+    fn size(&self) -> u64 {
+        self.sector_count() * self.sector_size() as u64
+    }
+}
+
+/// Checks that a transfer of `len` bytes at `start` is sector aligned and fits on the device
+///
+/// Meant to be used by `BlockDevice` implementations before touching the hardware
+pub fn check_transfer<B>(device: &B, start: u64, len: usize) -> Result<(), io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    let sector_size = device.sector_size();
+    if !len.is_multiple_of(sector_size) {
+        return Err(io::Error::Unaligned);
+    }
+
+    let count = (len / sector_size) as u64;
+    match start.checked_add(count) {
+        Some(end) if end <= device.sector_count() => Ok(()),
+        _ => Err(io::Error::OutOfBounds),
+    }
+}
+
+impl<B> BlockDevice for &mut B
+where
+    B: BlockDevice + ?Sized,
+{
+    fn sector_size(&self) -> usize {
+        (**self).sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        (**self).sector_count()
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+        (**self).read_sectors(start, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> Result<(), io::Error> {
+        (**self).write_sectors(start, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        (**self).flush()
+    }
+
+    fn is_read_only(&self) -> bool {
+        (**self).is_read_only()
+    }
+}
+
+impl<B> BlockDevice for Box<B>
+where
+    B: BlockDevice + ?Sized,
+{
+    fn sector_size(&self) -> usize {
+        (**self).sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        (**self).sector_count()
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+        (**self).read_sectors(start, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> Result<(), io::Error> {
+        (**self).write_sectors(start, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        (**self).flush()
+    }
+
+    fn is_read_only(&self) -> bool {
+        (**self).is_read_only()
+    }
+}
+
+/// Allows several users, like the partitions of a disk, to share one device
+impl<B> BlockDevice for Arc<Mutex<B>>
+where
+    B: BlockDevice + ?Sized,
+{
+    fn sector_size(&self) -> usize {
+        self.lock().sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.lock().sector_count()
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+        self.lock().read_sectors(start, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> Result<(), io::Error> {
+        self.lock().write_sectors(start, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.lock().flush()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.lock().is_read_only()
+    }
+}
+
+/// Byte level `Read + Write + Seek` access to a `BlockDevice`
+///
+/// Partial sectors are handled with read-modify-write, whole sectors are
+/// transferred directly
+pub struct BlockStream<B> {
+    device: B,
+    position: u64,
+    sector: Vec<u8>,
+}
+
+impl<B> BlockStream<B>
+where
+    B: BlockDevice,
+{
+    pub fn new(device: B) -> Self {
+        let sector = vec![0u8; device.sector_size()];
+        Self {
+            device,
+            position: 0,
+            sector,
+        }
+    }
+
+    pub fn device(&self) -> &B {
+        &self.device
+    }
+
+    pub fn into_inner(self) -> B {
+        self.device
+    }
+}
+
+impl<B> Read for BlockStream<B>
+where
+    B: BlockDevice,
+{
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let size = self.device.size();
+        if self.position >= size {
+            return Ok(0);
+        }
+
+        let sector_size = self.sector.len() as u64;
+        let len = min(buf.len() as u64, size - self.position) as usize;
+        let mut done = 0;
+
+        while done < len {
+            let sector = self.position / sector_size;
+            let within = (self.position % sector_size) as usize;
+            let remaining = len - done;
+
+            let n = if within == 0 && remaining >= sector_size as usize {
+                // Read as many whole sectors as possible straight into the buffer
+                let n = remaining - remaining % sector_size as usize;
+                self.device.read_sectors(sector, &mut buf[done..done + n])?;
+                n
+            } else {
+                let n = min(remaining, sector_size as usize - within);
+                self.device.read_sectors(sector, &mut self.sector)?;
+                buf[done..done + n].copy_from_slice(&self.sector[within..within + n]);
+                n
+            };
+
+            done += n;
+            self.position += n as u64;
+        }
+
+        Ok(len)
+    }
+}
+
+impl<B> Write for BlockStream<B>
+where
+    B: BlockDevice,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        if self.device.is_read_only() {
+            return Err(io::Error::PermissionsError);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let size = self.device.size();
+        if self.position >= size {
+            return Err(io::Error::NoSpace);
+        }
+
+        let sector_size = self.sector.len() as u64;
+        let len = min(buf.len() as u64, size - self.position) as usize;
+        let mut done = 0;
+
+        while done < len {
+            let sector = self.position / sector_size;
+            let within = (self.position % sector_size) as usize;
+            let remaining = len - done;
+
+            let n = if within == 0 && remaining >= sector_size as usize {
+                let n = remaining - remaining % sector_size as usize;
+                self.device.write_sectors(sector, &buf[done..done + n])?;
+                n
+            } else {
+                let n = min(remaining, sector_size as usize - within);
+                self.device.read_sectors(sector, &mut self.sector)?;
+                self.sector[within..within + n].copy_from_slice(&buf[done..done + n]);
+                self.device.write_sectors(sector, &self.sector)?;
+                n
+            };
+
+            done += n;
+            self.position += n as u64;
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.device.flush()
+    }
+}
+
+impl<B> Seek for BlockStream<B>
+where
+    B: BlockDevice,
+{
+    fn seek(&mut self, seek_from: SeekFrom) -> Result<u64, io::Error> {
+        let new_pos = match seek_from {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => self.device.size() as i64 + n,
+            SeekFrom::Current(n) => self.position as i64 + n,
+        };
+        if new_pos < 0 {
+            return Err(io::Error::NegativeSeekError);
+        }
+
+        self.position = new_pos as u64;
+        Ok(self.position)
+    }
+}
+
+/// Exposes a byte stream, such as a `RamFile` holding a disk image, as a `BlockDevice`
+///
+/// The sector count is taken from the length of the stream when it is created
+pub struct StreamBlockDevice<S> {
+    stream: S,
+    sector_size: usize,
+    sector_count: u64,
+    read_only: bool,
+}
+
+impl<S> StreamBlockDevice<S>
+where
+    S: Read + Write + Seek,
+{
+    pub fn new(mut stream: S, sector_size: usize, read_only: bool) -> Result<Self, io::Error> {
+        if sector_size == 0 {
+            return Err(io::Error::Unaligned);
+        }
+        let len = stream.seek(SeekFrom::End(0))?;
+
+        Ok(Self {
+            stream,
+            sector_size,
+            sector_count: len / sector_size as u64,
+            read_only,
+        })
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> BlockDevice for StreamBlockDevice<S>
+where
+    S: Read + Write + Seek,
+{
+    fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+        check_transfer(self, start, buf.len())?;
+        self.stream
+            .seek(SeekFrom::Start(start * self.sector_size as u64))?;
+        self.stream.read_exact(buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> Result<(), io::Error> {
+        if self.read_only {
+            return Err(io::Error::PermissionsError);
+        }
+        check_transfer(self, start, buf.len())?;
+        self.stream
+            .seek(SeekFrom::Start(start * self.sector_size as u64))?;
+        self.stream.write_all(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.stream.flush()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::ramfile::RamFile;
+
+    fn disk(sectors: usize) -> Mutex<Vec<u8>> {
+        Mutex::new((0..sectors * 512).map(|i| (i / 512) as u8).collect())
+    }
+
+    #[test]
+    fn unaligned_access_only_touches_the_covered_bytes() {
+        let contents = disk(4);
+        let device = StreamBlockDevice::new(RamFile::new(&contents, false), 512, false).unwrap();
+        let mut stream = BlockStream::new(device);
+
+        stream.seek(SeekFrom::Start(510)).unwrap();
+        stream.write_all(&[0xAA; 1030]).unwrap();
+
+        let contents = contents.lock();
+        assert_eq!(&contents[508..510], &[0, 0]);
+        assert!(contents[510..1540].iter().all(|&b| b == 0xAA));
+        assert_eq!(&contents[1540..1542], &[3, 3]);
+    }
+
+    #[test]
+    fn reads_stop_at_the_end_of_the_device() {
+        let contents = disk(2);
+        let device = StreamBlockDevice::new(RamFile::new(&contents, true), 512, true).unwrap();
+        let mut stream = BlockStream::new(device);
+
+        stream.seek(SeekFrom::End(-3)).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], &[1, 1, 1]);
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+        assert!(matches!(
+            stream.write(&[0]),
+            Err(io::Error::PermissionsError)
+        ));
+    }
+
+    #[test]
+    fn transfers_are_checked() {
+        let contents = disk(2);
+        let mut device =
+            StreamBlockDevice::new(RamFile::new(&contents, false), 512, false).unwrap();
+
+        assert!(device.read_sectors(0, &mut [0u8; 100]).is_err());
+        assert!(device.read_sectors(1, &mut [0u8; 1024]).is_err());
+        assert!(device.write_sectors(2, &[0u8; 512]).is_err());
+    }
+}
@@ -1,5 +0,0 @@
-
-pub trait SectoredReader {
-    pub fn get_sector_size() -> usize;
-
-}
\ No newline at end of file
@@ -0,0 +1,116 @@
+//! Key events
+//!
+//! Input drivers push events from their interrupt handlers, and stdin reads them
+
+use super::ring::RingBuffer;
+
+/// Events that haven't been read yet, newer events are dropped when it is full
+pub static INPUT: RingBuffer<KeyEvent, 256> = RingBuffer::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A key that produces text, as translated by the keyboard layout
+    Char(char),
+    Enter,
+    Backspace,
+    Tab,
+    Escape,
+    Delete,
+    Insert,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// F1 to F12
+    Function(u8),
+    /// Shift, Ctrl, Alt, and the lock keys
+    Modifier,
+    Unknown,
+}
+
+impl Key {
+    /// The character the key types, control characters for the editing keys
+    pub fn to_char(self) -> Option<char> {
+        Some(match self {
+            Key::Char(c) => c,
+            Key::Enter => '\n',
+            Key::Backspace => '\x08',
+            Key::Tab => '\t',
+            Key::Escape => '\x1b',
+            Key::Delete => '\x7f',
+            _ => return None,
+        })
+    }
+}
+
+impl From<char> for Key {
+    /// Control characters become the key that types them
+    fn from(c: char) -> Self {
+        match c {
+            '\n' | '\r' => Key::Enter,
+            '\x08' => Key::Backspace,
+            '\t' => Key::Tab,
+            '\x1b' => Key::Escape,
+            '\x7f' => Key::Delete,
+            c => Key::Char(c),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+/// Modifier keys held down, and lock keys that are on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub alt_gr: bool,
+    pub caps_lock: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub state: KeyState,
+    pub modifiers: Modifiers,
+}
+
+impl KeyEvent {
+    /// A press of `key` without modifiers
+    pub fn pressed(key: Key) -> Self {
+        Self {
+            key,
+            state: KeyState::Pressed,
+            modifiers: Modifiers::default(),
+        }
+    }
+}
+
+/// Adds `event` to the input, returns false if it was dropped because the input is full
+pub fn push(event: KeyEvent) -> bool {
+    INPUT.push(event).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_characters_become_keys() {
+        for c in ['\n', '\x08', '\t', '\x1b', '\x7f', 'a', 'é'] {
+            let key = Key::from(c);
+            assert_eq!(key.to_char(), Some(c));
+        }
+        assert_eq!(Key::from('\r'), Key::Enter);
+        assert_eq!(Key::Up.to_char(), None);
+    }
+}
@@ -0,0 +1,459 @@
+//! Line editor
+//!
+//! Reads a line from stdin with cursor movement, history and tab completion.
+//! The line is redrawn with ANSI escape sequences, which the console and serial
+//! terminals both understand
+
+use alloc::collections::VecDeque;
+use alloc::format;
+
+use super::input::{Key, KeyEvent, KeyState};
+use super::stdin;
+use crate::print;
+use crate::string::String;
+use crate::vec::Vec;
+
+/// Called with the text before the cursor when Tab is pressed
+pub type Completer = fn(&str) -> Completions;
+
+/// What the word before the cursor can be completed to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completions {
+    /// Byte offset of the word in the text given to the completer
+    pub start: usize,
+    /// Everything the word could be replaced with, candidates ending in `/` are
+    /// completed without a space after them so a path can be continued
+    pub candidates: Vec<String>,
+}
+
+pub struct LineEditor {
+    /// Oldest line first
+    history: VecDeque<String>,
+    history_len: usize,
+    completer: Option<Completer>,
+}
+
+impl LineEditor {
+    /// An editor that remembers the last `history_len` lines
+    pub fn new(history_len: usize) -> Self {
+        Self {
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            completer: None,
+        }
+    }
+
+    pub fn set_completer(&mut self, completer: Completer) {
+        self.completer = Some(completer);
+    }
+
+    /// Lines in the history, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &str> {
+        self.history.iter().map(String::as_str)
+    }
+
+    /// Prints `prompt`, and reads a line from stdin without the newline
+    ///
+    /// Ctrl-C gives up on the line and returns an empty one
+    pub fn read_line(&mut self, prompt: &str) -> String {
+        self.read_line_with(prompt, stdin::read_event, |s| print!("{s}"))
+    }
+
+    fn read_line_with(
+        &mut self,
+        prompt: &str,
+        mut next_event: impl FnMut() -> KeyEvent,
+        mut output: impl FnMut(&str),
+    ) -> String {
+        let mut state = State {
+            line: Line::default(),
+            history_index: self.history.len(),
+            unsent: Vec::new(),
+            out: String::from(prompt),
+        };
+
+        loop {
+            output(&state.out);
+            state.out.clear();
+
+            let event = next_event();
+            if event.state != KeyState::Pressed {
+                continue;
+            }
+
+            match self.handle(&mut state, prompt, event) {
+                Action::Continue => state.line.draw(&mut state.out),
+                Action::Cancel => {
+                    output("^C\n");
+                    return String::new();
+                }
+                Action::Submit => {
+                    state.line.move_to(&mut state.out, state.line.chars.len());
+                    state.out.push('\n');
+                    output(&state.out);
+                    break;
+                }
+            }
+        }
+
+        let line: String = state.line.chars.iter().collect();
+        self.add_history(&line);
+        line
+    }
+
+    fn handle(&self, state: &mut State, prompt: &str, event: KeyEvent) -> Action {
+        let line = &mut state.line;
+
+        match event.key {
+            Key::Enter => return Action::Submit,
+            Key::Char(c) if event.modifiers.ctrl => match c {
+                'a' => line.cursor = 0,
+                'e' => line.cursor = line.chars.len(),
+                'u' => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                'w' => {
+                    let start = line.word_start();
+                    line.chars.drain(start..line.cursor);
+                    line.cursor = start;
+                }
+                'c' => return Action::Cancel,
+                _ => {}
+            },
+            Key::Char(c) => {
+                line.chars.insert(line.cursor, c);
+                line.cursor += 1;
+            }
+            Key::Backspace if line.cursor > 0 => {
+                line.cursor -= 1;
+                line.chars.remove(line.cursor);
+            }
+            Key::Delete if line.cursor < line.chars.len() => {
+                line.chars.remove(line.cursor);
+            }
+            Key::Left => line.cursor = line.cursor.saturating_sub(1),
+            Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+            Key::Home => line.cursor = 0,
+            Key::End => line.cursor = line.chars.len(),
+            Key::Up if state.history_index > 0 => {
+                if state.history_index == self.history.len() {
+                    state.unsent = line.chars.clone();
+                }
+                state.history_index -= 1;
+                line.set(self.history[state.history_index].chars().collect());
+            }
+            Key::Down if state.history_index < self.history.len() => {
+                state.history_index += 1;
+                match self.history.get(state.history_index) {
+                    Some(entry) => line.set(entry.chars().collect()),
+                    None => line.set(core::mem::take(&mut state.unsent)),
+                }
+            }
+            Key::Tab => {
+                if let Some(completer) = self.completer {
+                    complete(state, prompt, completer);
+                }
+            }
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    fn add_history(&mut self, line: &str) {
+        if self.history_len == 0
+            || line.trim().is_empty()
+            || self.history.back().is_some_and(|last| last == line)
+        {
+            return;
+        }
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(String::from(line));
+    }
+}
+
+enum Action {
+    Continue,
+    Submit,
+    Cancel,
+}
+
+/// The line being read
+struct State {
+    line: Line,
+    /// Index of the history entry being edited, the length of the history for a new line
+    history_index: usize,
+    /// The new line, kept while browsing the history
+    unsent: Vec<char>,
+    /// Output to print before waiting for the next key
+    out: String,
+}
+
+/// The text being edited, and what of it is on the screen
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+    drawn: Vec<char>,
+    drawn_cursor: usize,
+}
+
+impl Line {
+    fn set(&mut self, chars: Vec<char>) {
+        self.cursor = chars.len();
+        self.chars = chars;
+    }
+
+    /// Where Ctrl-W deletes back to, the start of the word before the cursor
+    fn word_start(&self) -> usize {
+        let before = &self.chars[..self.cursor];
+        let end = before
+            .iter()
+            .rposition(|c| !c.is_whitespace())
+            .map_or(0, |i| i + 1);
+        before[..end]
+            .iter()
+            .rposition(|c| c.is_whitespace())
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Updates the screen to show the line, only redrawing what changed
+    fn draw(&mut self, out: &mut String) {
+        let unchanged = self
+            .chars
+            .iter()
+            .zip(&self.drawn)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        if unchanged < self.chars.len() || unchanged < self.drawn.len() {
+            self.move_to(out, unchanged);
+            out.extend(&self.chars[unchanged..]);
+            if self.drawn.len() > self.chars.len() {
+                out.push_str("\x1b[K");
+            }
+            self.drawn.clone_from(&self.chars);
+            self.drawn_cursor = self.chars.len();
+        }
+        self.move_to(out, self.cursor);
+    }
+
+    /// Moves the cursor on the screen to after the first `position` characters
+    fn move_to(&mut self, out: &mut String, position: usize) {
+        use crate::fmt::Write;
+
+        let _ = match position.cmp(&self.drawn_cursor) {
+            core::cmp::Ordering::Less => write!(out, "\x1b[{}D", self.drawn_cursor - position),
+            core::cmp::Ordering::Greater => {
+                write!(out, "\x1b[{}C", position - self.drawn_cursor)
+            }
+            core::cmp::Ordering::Equal => Ok(()),
+        };
+        self.drawn_cursor = position;
+    }
+
+    /// Forgets what is on the screen, after the line was printed again from the start
+    fn forget_drawn(&mut self) {
+        self.drawn.clear();
+        self.drawn_cursor = 0;
+    }
+}
+
+/// Completes the word before the cursor, listing the candidates if it is ambiguous
+fn complete(state: &mut State, prompt: &str, completer: Completer) {
+    let line = &mut state.line;
+    let before: String = line.chars[..line.cursor].iter().collect();
+    let completions = completer(&before);
+    let Some(word) = before.get(completions.start..) else {
+        return;
+    };
+    let start = line.cursor - word.chars().count();
+
+    let replacement = match completions.candidates.as_slice() {
+        [] => return,
+        [only] if only.ends_with('/') => only.clone(),
+        [only] => format!("{only} "),
+        candidates => {
+            let prefix = common_prefix(candidates);
+            if prefix.chars().count() > word.chars().count() {
+                prefix
+            } else {
+                // Nothing more can be filled in, so show what there is to choose from
+                line.move_to(&mut state.out, line.chars.len());
+                state.out.push('\n');
+                state.out.push_str(&candidates.join("  "));
+                state.out.push('\n');
+                state.out.push_str(prompt);
+                line.forget_drawn();
+                return;
+            }
+        }
+    };
+
+    line.chars.splice(start..line.cursor, replacement.chars());
+    line.cursor = start + replacement.chars().count();
+}
+
+/// The longest string all of `candidates` start with
+fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix = candidates[0].as_str();
+    for candidate in &candidates[1..] {
+        let len = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, a), _)| i + a.len_utf8());
+        prefix = &prefix[..len];
+    }
+    String::from(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::input::Modifiers;
+
+    fn key(key: Key) -> KeyEvent {
+        KeyEvent::pressed(key)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent {
+            modifiers: Modifiers {
+                ctrl: true,
+                ..Modifiers::default()
+            },
+            ..KeyEvent::pressed(Key::Char(c))
+        }
+    }
+
+    fn text(s: &str) -> Vec<KeyEvent> {
+        s.chars().map(|c| key(Key::from(c))).collect()
+    }
+
+    /// Reads a line from `events`, returns it and everything that was printed
+    fn read(editor: &mut LineEditor, events: Vec<KeyEvent>) -> (String, String) {
+        let mut events = events.into_iter();
+        let mut output = String::new();
+        let line = editor.read_line_with(
+            "$ ",
+            || events.next().expect("line wasn't submitted"),
+            |s| output.push_str(s),
+        );
+        (line, output)
+    }
+
+    #[test]
+    fn edits_in_the_middle_of_the_line() {
+        let mut editor = LineEditor::new(0);
+        let mut events = text("ech");
+        events.extend([key(Key::Home), key(Key::Delete), key(Key::End)]);
+        events.extend(text("o hi"));
+        events.extend([key(Key::Left), key(Key::Left), key(Key::Backspace)]);
+        events.extend([key(Key::Right), key(Key::Enter)]);
+
+        assert_eq!(read(&mut editor, events).0, "chohi");
+    }
+
+    #[test]
+    fn only_redraws_what_changed() {
+        let mut editor = LineEditor::new(0);
+        let mut events = text("ab");
+        events.extend([key(Key::Left), key(Key::Backspace), key(Key::Enter)]);
+
+        let (line, output) = read(&mut editor, events);
+        assert_eq!(line, "b");
+        assert_eq!(output, "$ ab\x1b[1D\x1b[1Db\x1b[K\x1b[1D\x1b[1C\n");
+    }
+
+    #[test]
+    fn deletes_words_and_lines() {
+        let mut editor = LineEditor::new(0);
+        let mut events = text("cat  some/file  ");
+        events.extend([ctrl('w'), ctrl('w')]);
+        events.extend(text("x"));
+        events.push(key(Key::Enter));
+        assert_eq!(read(&mut editor, events).0, "x");
+
+        let mut events = text("rm -rf /");
+        events.extend([key(Key::Left), ctrl('u')]);
+        events.extend(text("ls "));
+        events.push(key(Key::Enter));
+        assert_eq!(read(&mut editor, events).0, "ls /");
+    }
+
+    #[test]
+    fn browses_history() {
+        let mut editor = LineEditor::new(2);
+        for line in ["one", "two", "two", "", "three"] {
+            let mut events = text(line);
+            events.push(key(Key::Enter));
+            read(&mut editor, events);
+        }
+        assert_eq!(editor.history().collect::<Vec<_>>(), ["two", "three"]);
+
+        let mut events = text("new");
+        events.extend([key(Key::Up), key(Key::Up), key(Key::Up)]);
+        events.extend(text("!"));
+        events.push(key(Key::Enter));
+        assert_eq!(read(&mut editor, events).0, "two!");
+
+        let mut events = text("new");
+        events.extend([key(Key::Up), key(Key::Down), key(Key::Down)]);
+        events.push(key(Key::Enter));
+        assert_eq!(read(&mut editor, events).0, "new");
+    }
+
+    #[test]
+    fn cancels_with_ctrl_c() {
+        let mut editor = LineEditor::new(4);
+        let mut events = text("oops");
+        events.push(ctrl('c'));
+        assert_eq!(read(&mut editor, events).0, "");
+        assert_eq!(editor.history().count(), 0);
+    }
+
+    #[test]
+    fn completes_words() {
+        fn completer(before: &str) -> Completions {
+            let start = before.rfind(' ').map_or(0, |i| i + 1);
+            let word = &before[start..];
+            let candidates = ["dev/", "docs/", "document.txt", "data"]
+                .into_iter()
+                .filter(|c| c.starts_with(word))
+                .map(String::from)
+                .collect();
+            Completions { start, candidates }
+        }
+
+        let mut editor = LineEditor::new(0);
+        editor.set_completer(completer);
+
+        let mut events = text("cat da");
+        events.extend([key(Key::Tab), key(Key::Enter)]);
+        assert_eq!(read(&mut editor, events).0, "cat data ");
+
+        let mut events = text("cd de");
+        events.extend([key(Key::Tab), key(Key::Enter)]);
+        assert_eq!(read(&mut editor, events).0, "cd dev/");
+
+        let mut events = text("cat do");
+        events.extend([key(Key::Tab), key(Key::Tab)]);
+        events.extend(text("s"));
+        events.push(key(Key::Enter));
+        let (line, output) = read(&mut editor, events);
+        assert_eq!(line, "cat docs");
+        assert!(output.contains("\ndocs/  document.txt\n$ cat doc"));
+    }
+
+    #[test]
+    fn finds_common_prefixes() {
+        let strings = |s: &[&str]| s.iter().map(|s| String::from(*s)).collect::<Vec<_>>();
+        assert_eq!(common_prefix(&strings(&["abc", "abd"])), "ab");
+        assert_eq!(common_prefix(&strings(&["été", "étage"])), "ét");
+        assert_eq!(common_prefix(&strings(&["x", "y"])), "");
+    }
+}
@@ -1,9 +1,15 @@
+pub mod block;
+pub mod input;
+pub mod line_editor;
+pub mod partition;
+pub mod ramfile;
+pub mod ring;
 pub mod stderr;
 pub mod stdin;
 pub mod stdout;
-pub mod ramfile;
-pub mod block;
+pub mod terminal;
 
+use crate::fmt;
 use crate::string::{String, ToString};
 use crate::vec::Vec;
 use snafu::Snafu;
@@ -18,15 +24,62 @@ pub enum Error {
     InvalidString,
     ReadError,
     WriteError,
+    UnexpectedEof,
+    InvalidData,
+    NoSpace,
+    Unaligned,
+    OutOfBounds,
+    NotADirectory,
+    AlreadyExists,
+    DirectoryNotEmpty,
+    CrossesDevices,
 }
 
 pub trait Read {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+
+    /// Read until `buf` is completely filled
+    ///
+    /// Fails with `UnexpectedEof` if the reader runs out of data first
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            match self.read(buf)? {
+                0 => return Err(Error::UnexpectedEof),
+                n => buf = &mut buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Read until the reader runs out of data, appending it to `buf`
+    ///
+    /// Returns the number of bytes read
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        let start = buf.len();
+        let mut chunk = [0; 512];
+        loop {
+            match self.read(&mut chunk)? {
+                0 => return Ok(buf.len() - start),
+                n => buf.extend_from_slice(&chunk[..n]),
+            }
+        }
+    }
 }
 
 pub trait Write {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
     fn flush(&mut self) -> Result<(), Error>;
+
+    /// Write the entire buffer, retrying on short writes
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::WriteError),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -40,10 +93,72 @@ pub trait Seek {
     fn seek(&mut self, seek_from: SeekFrom) -> Result<u64, Error>;
 }
 
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Path {
     pub segments: Vec<String>,
 }
 
+impl Path {
+    /// The path with no segments, the root of a directory tree
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Resolves `path` relative to this path
+    ///
+    /// A leading `/` makes `path` start from the root instead. Empty and `.`
+    /// segments are skipped, and `..` removes the last segment, stopping at the root
+    pub fn join(&self, path: &str) -> Path {
+        let mut segments = if path.starts_with('/') {
+            Vec::new()
+        } else {
+            self.segments.clone()
+        };
+
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                segment => segments.push(segment.to_string()),
+            }
+        }
+
+        Self { segments }
+    }
+
+    /// Splits the path into its parent and last segment, `None` for the root
+    pub fn split_last(&self) -> Option<(Path, &str)> {
+        let (last, parent) = self.segments.split_last()?;
+        Some((Path::from(parent), last.as_str()))
+    }
+
+    /// Returns the rest of the path if it is inside `prefix`
+    pub fn strip_prefix(&self, prefix: &Path) -> Option<Path> {
+        self.segments
+            .strip_prefix(prefix.segments.as_slice())
+            .map(Path::from)
+    }
+}
+
+/// Displays the path as an absolute path
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.segments.is_empty() {
+            return f.write_str("/");
+        }
+        for segment in &self.segments {
+            write!(f, "/{segment}")?;
+        }
+        Ok(())
+    }
+}
+
 impl From<&str> for Path {
     fn from(value: &str) -> Self {
         Self {
@@ -62,3 +177,35 @@ impl From<&[String]> for Path {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_normalizes() {
+        let cwd = Path::from("home/user");
+        assert_eq!(cwd.join("docs/./a.txt"), Path::from("home/user/docs/a.txt"));
+        assert_eq!(cwd.join("../other//b"), Path::from("home/other/b"));
+        assert_eq!(cwd.join("/etc"), Path::from("etc"));
+        assert_eq!(cwd.join("../../../.."), Path::root());
+    }
+
+    #[test]
+    fn display_is_absolute() {
+        assert_eq!(Path::root().to_string(), "/");
+        assert_eq!(Path::root().join("a/b").to_string(), "/a/b");
+    }
+
+    #[test]
+    fn strip_prefix_and_split_last() {
+        let path = Path::root().join("mnt/disk/file");
+        let mount = Path::root().join("mnt/disk");
+
+        assert_eq!(path.strip_prefix(&mount), Some(Path::from("file")));
+        assert_eq!(mount.strip_prefix(&path), None);
+        assert_eq!(path.strip_prefix(&Path::root()), Some(path.clone()));
+        assert_eq!(path.split_last(), Some((mount, "file")));
+        assert_eq!(Path::root().split_last(), None);
+    }
+}
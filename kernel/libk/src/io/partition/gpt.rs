@@ -0,0 +1,328 @@
+use super::{Partition, PartitionKind};
+use crate::{
+    char::decode_utf16,
+    fmt,
+    io::{self, block::BlockDevice},
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+const MIN_HEADER_SIZE: usize = 92;
+const MIN_ENTRY_SIZE: usize = 128;
+/// Upper bound for the entry array, the usual size is 16KiB
+const MAX_ENTRIES_SIZE: usize = 1024 * 1024;
+const NAME_OFFSET: usize = 56;
+const NAME_LEN: usize = 36;
+
+/// A GUID, stored in the mixed endian format used on disk
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub const ZERO: Guid = Guid([0; 16]);
+    pub const EFI_SYSTEM: Guid = Guid::from_fields(
+        0xC12A7328,
+        0xF81F,
+        0x11D2,
+        [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+    );
+    pub const BASIC_DATA: Guid = Guid::from_fields(
+        0xEBD0A0A2,
+        0xB9E5,
+        0x4433,
+        [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+    );
+
+    /// Builds a GUID from the fields in the order they are written in text form
+    pub const fn from_fields(a: u32, b: u16, c: u16, d: [u8; 8]) -> Self {
+        let a = a.to_le_bytes();
+        let b = b.to_le_bytes();
+        let c = c.to_le_bytes();
+        Guid([
+            a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5],
+            d[6], d[7],
+        ])
+    }
+
+    fn parse(raw: &[u8]) -> Self {
+        let mut guid = [0u8; 16];
+        guid.copy_from_slice(&raw[..16]);
+        Guid(guid)
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let g = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            u32::from_le_bytes([g[0], g[1], g[2], g[3]]),
+            u16::from_le_bytes([g[4], g[5]]),
+            u16::from_le_bytes([g[6], g[7]]),
+            g[8],
+            g[9]
+        )?;
+        for byte in &g[10..] {
+            write!(f, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// CRC32 as used by GPT (IEEE 802.3, reflected)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn le_u32(raw: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&raw[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn le_u64(raw: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&raw[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+struct GptHeader {
+    first_usable: u64,
+    last_usable: u64,
+    entries_lba: u64,
+    entry_count: usize,
+    entry_size: usize,
+    entries_crc: u32,
+}
+
+impl GptHeader {
+    /// Parses and validates the header stored at `lba`
+    fn parse(sector: &[u8], lba: u64) -> Option<Self> {
+        if &sector[..8] != SIGNATURE {
+            return None;
+        }
+
+        let header_size = le_u32(sector, 12) as usize;
+        if !(MIN_HEADER_SIZE..=sector.len()).contains(&header_size) {
+            return None;
+        }
+
+        // The CRC is calculated with the CRC field itself zeroed
+        let mut header = sector[..header_size].to_vec();
+        header[16..20].fill(0);
+        if crc32(&header) != le_u32(sector, 16) || le_u64(sector, 24) != lba {
+            return None;
+        }
+
+        let entry_count = le_u32(sector, 80) as usize;
+        let entry_size = le_u32(sector, 84) as usize;
+        if entry_size < MIN_ENTRY_SIZE
+            || !entry_size.is_multiple_of(8)
+            || entry_count.checked_mul(entry_size)? > MAX_ENTRIES_SIZE
+        {
+            return None;
+        }
+
+        Some(Self {
+            first_usable: le_u64(sector, 40),
+            last_usable: le_u64(sector, 48),
+            entries_lba: le_u64(sector, 72),
+            entry_count,
+            entry_size,
+            entries_crc: le_u32(sector, 88),
+        })
+    }
+}
+
+/// Reads the header at `lba` and its partition entries, or None if any of it is corrupted
+fn read_table<B>(disk: &mut B, lba: u64) -> Result<Option<Vec<Partition>>, io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    let sector_size = disk.sector_size();
+    let mut sector = vec![0u8; sector_size];
+    disk.read_sectors(lba, &mut sector)?;
+
+    let Some(header) = GptHeader::parse(&sector, lba) else {
+        return Ok(None);
+    };
+
+    let entries_len = header.entry_count * header.entry_size;
+    let sectors = entries_len.div_ceil(sector_size) as u64;
+    if header.entries_lba.saturating_add(sectors) > disk.sector_count() {
+        return Ok(None);
+    }
+
+    let mut entries = vec![0u8; sectors as usize * sector_size];
+    disk.read_sectors(header.entries_lba, &mut entries)?;
+    let entries = &entries[..entries_len];
+    if crc32(entries) != header.entries_crc {
+        return Ok(None);
+    }
+
+    let mut partitions = Vec::new();
+    for (i, raw) in entries.chunks_exact(header.entry_size).enumerate() {
+        let type_guid = Guid::parse(&raw[0..16]);
+        if type_guid == Guid::ZERO {
+            continue;
+        }
+
+        let first = le_u64(raw, 32);
+        let last = le_u64(raw, 40);
+        if first > last || first < header.first_usable || last > header.last_usable {
+            return Ok(None);
+        }
+
+        let name: Vec<u16> = (0..NAME_LEN)
+            .map(|c| u16::from_le_bytes([raw[NAME_OFFSET + c * 2], raw[NAME_OFFSET + c * 2 + 1]]))
+            .take_while(|&c| c != 0)
+            .collect();
+        let name: String = decode_utf16(name)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        partitions.push(Partition {
+            number: i + 1,
+            start: first,
+            sector_count: last - first + 1,
+            kind: PartitionKind::Gpt {
+                type_guid,
+                unique_guid: Guid::parse(&raw[16..32]),
+                name,
+                attributes: le_u64(raw, 48),
+            },
+        });
+    }
+
+    Ok(Some(partitions))
+}
+
+/// Reads the primary GPT, using the backup at the end of the disk if the primary is corrupted
+pub(super) fn read_partitions<B>(disk: &mut B) -> Result<Vec<Partition>, io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    if let Some(partitions) = read_table(disk, 1)? {
+        return Ok(partitions);
+    }
+
+    let backup_lba = disk
+        .sector_count()
+        .checked_sub(1)
+        .ok_or(io::Error::InvalidData)?;
+    read_table(disk, backup_lba)?.ok_or(io::Error::InvalidData)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::{block::StreamBlockDevice, ramfile::RamFile};
+    use crate::Mutex;
+
+    const SECTORS: usize = 128;
+    const ENTRY_COUNT: usize = 4;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    /// Writes a GPT header at `lba`, with its entry array at `entries_lba`
+    fn write_header(image: &mut [u8], lba: u64, backup_lba: u64, entries_lba: u64, crc: u32) {
+        let header = &mut image[lba as usize * 512..][..512];
+        header[..8].copy_from_slice(SIGNATURE);
+        header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92u32.to_le_bytes());
+        header[24..32].copy_from_slice(&lba.to_le_bytes());
+        header[32..40].copy_from_slice(&backup_lba.to_le_bytes());
+        header[40..48].copy_from_slice(&34u64.to_le_bytes());
+        header[48..56].copy_from_slice(&(SECTORS as u64 - 34).to_le_bytes());
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&(ENTRY_COUNT as u32).to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&crc.to_le_bytes());
+        let header_crc = crc32(&header[..92]);
+        header[16..20].copy_from_slice(&header_crc.to_le_bytes());
+    }
+
+    /// A disk with a protective MBR and an EFI System Partition named "EFI", plus
+    /// both the primary and the backup GPT
+    fn image() -> Vec<u8> {
+        let mut image = vec![0u8; SECTORS * 512];
+        image[446 + 4] = 0xEE;
+        image[446 + 8..446 + 12].copy_from_slice(&1u32.to_le_bytes());
+        image[446 + 12..446 + 16].copy_from_slice(&(SECTORS as u32 - 1).to_le_bytes());
+        image[510] = 0x55;
+        image[511] = 0xAA;
+
+        let mut entries = vec![0u8; ENTRY_COUNT * 128];
+        entries[0..16].copy_from_slice(&Guid::EFI_SYSTEM.0);
+        entries[16] = 1;
+        entries[32..40].copy_from_slice(&34u64.to_le_bytes());
+        entries[40..48].copy_from_slice(&40u64.to_le_bytes());
+        for (i, c) in "EFI".encode_utf16().enumerate() {
+            entries[NAME_OFFSET + i * 2..NAME_OFFSET + i * 2 + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        let crc = crc32(&entries);
+
+        let last = SECTORS as u64 - 1;
+        image[2 * 512..][..entries.len()].copy_from_slice(&entries);
+        image[(last as usize - 1) * 512..][..entries.len()].copy_from_slice(&entries);
+        write_header(&mut image, 1, last, 2, crc);
+        write_header(&mut image, last, 1, last - 1, crc);
+        image
+    }
+
+    fn partitions(image: Vec<u8>) -> Result<Vec<Partition>, io::Error> {
+        let image = Mutex::new(image);
+        let mut disk = StreamBlockDevice::new(RamFile::new(&image, true), 512, true).unwrap();
+        super::super::read_partitions(&mut disk)
+    }
+
+    #[test]
+    fn reads_primary_table() {
+        let partitions = partitions(image()).unwrap();
+
+        assert_eq!(partitions.len(), 1);
+        let esp = &partitions[0];
+        assert!(esp.is_efi_system());
+        assert_eq!((esp.number, esp.start, esp.sector_count), (1, 34, 7));
+        assert!(matches!(&esp.kind, PartitionKind::Gpt { name, .. } if name == "EFI"));
+    }
+
+    #[test]
+    fn falls_back_to_backup_table() {
+        let mut image = image();
+        image[2 * 512] ^= 0xFF;
+        let partitions = partitions(image).unwrap();
+
+        assert_eq!(partitions.len(), 1);
+        assert_eq!(partitions[0].start, 34);
+    }
+
+    #[test]
+    fn both_tables_corrupted() {
+        let mut image = image();
+        image[512] ^= 0xFF;
+        image[(SECTORS - 1) * 512] ^= 0xFF;
+
+        assert!(matches!(partitions(image), Err(io::Error::InvalidData)));
+    }
+}
@@ -0,0 +1,146 @@
+use super::{Partition, PartitionKind};
+use crate::{
+    io::{self, block::BlockDevice},
+    vec,
+    vec::Vec,
+};
+
+/// Size of the MBR, which always fits in the first sector
+pub(super) const MBR_SIZE: usize = 512;
+pub(super) const EFI_SYSTEM_ID: u8 = 0xEF;
+
+const ENTRIES_OFFSET: usize = 446;
+const ENTRY_SIZE: usize = 16;
+const PROTECTIVE_ID: u8 = 0xEE;
+const EXTENDED_IDS: [u8; 3] = [0x05, 0x0F, 0x85];
+const BOOTABLE_FLAG: u8 = 0x80;
+
+/// Logical partitions are a linked list on disk, stop following it at some point
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+#[derive(Debug, Clone, Copy)]
+pub(super) struct MbrEntry {
+    pub bootable: bool,
+    pub system_id: u8,
+    pub start: u32,
+    pub sector_count: u32,
+}
+
+impl MbrEntry {
+    fn parse(raw: &[u8]) -> Self {
+        Self {
+            bootable: raw[0] & BOOTABLE_FLAG != 0,
+            system_id: raw[4],
+            start: u32::from_le_bytes([raw[8], raw[9], raw[10], raw[11]]),
+            sector_count: u32::from_le_bytes([raw[12], raw[13], raw[14], raw[15]]),
+        }
+    }
+
+    fn is_used(&self) -> bool {
+        self.system_id != 0 && self.sector_count != 0
+    }
+
+    fn is_extended(&self) -> bool {
+        EXTENDED_IDS.contains(&self.system_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct MasterBootRecord {
+    pub entries: [MbrEntry; 4],
+}
+
+impl MasterBootRecord {
+    /// Parses the partition entries of a boot sector, or None if it has no boot signature
+    pub fn parse(sector: &[u8]) -> Option<Self> {
+        if sector[510] != 0x55 || sector[511] != 0xAA {
+            return None;
+        }
+
+        let entry = |i: usize| {
+            let offset = ENTRIES_OFFSET + i * ENTRY_SIZE;
+            MbrEntry::parse(&sector[offset..offset + ENTRY_SIZE])
+        };
+        Some(Self {
+            entries: [entry(0), entry(1), entry(2), entry(3)],
+        })
+    }
+
+    /// A protective MBR covers the disk with a single 0xEE partition, to guard GPT disks
+    /// from tools that don't understand GPT
+    pub fn is_protective(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.system_id == PROTECTIVE_ID)
+    }
+}
+
+fn to_partition(number: usize, base: u64, entry: &MbrEntry) -> Partition {
+    Partition {
+        number,
+        start: base + entry.start as u64,
+        sector_count: entry.sector_count as u64,
+        kind: PartitionKind::Mbr {
+            system_id: entry.system_id,
+            bootable: entry.bootable,
+        },
+    }
+}
+
+pub(super) fn read_partitions<B>(
+    disk: &mut B,
+    table: &MasterBootRecord,
+) -> Result<Vec<Partition>, io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    let mut partitions = Vec::new();
+
+    for (i, entry) in table.entries.iter().enumerate() {
+        if !entry.is_used() {
+            continue;
+        }
+        if entry.is_extended() {
+            read_logical_partitions(disk, entry.start as u64, &mut partitions)?;
+        } else {
+            partitions.push(to_partition(i + 1, 0, entry));
+        }
+    }
+
+    Ok(partitions)
+}
+
+/// Follows the chain of Extended Boot Records in an extended partition
+///
+/// The first entry of every EBR is relative to the EBR itself, while the link
+/// to the next EBR is relative to the start of the extended partition
+fn read_logical_partitions<B>(
+    disk: &mut B,
+    extended_start: u64,
+    partitions: &mut Vec<Partition>,
+) -> Result<(), io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    let mut sector = vec![0u8; disk.sector_size()];
+    let mut ebr = extended_start;
+
+    for number in 5..5 + MAX_LOGICAL_PARTITIONS {
+        if ebr >= disk.sector_count() {
+            return Err(io::Error::InvalidData);
+        }
+        disk.read_sectors(ebr, &mut sector)?;
+        let table = MasterBootRecord::parse(&sector).ok_or(io::Error::InvalidData)?;
+
+        let [logical, next, ..] = table.entries;
+        if logical.is_used() {
+            partitions.push(to_partition(number, ebr, &logical));
+        }
+        if !next.is_used() {
+            return Ok(());
+        }
+        ebr = extended_start + next.start as u64;
+    }
+
+    Ok(())
+}
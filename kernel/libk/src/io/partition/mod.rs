@@ -0,0 +1,226 @@
+//! Partition table parsing
+//!
+//! Supports GPT disks (including the protective MBR written by tools like sgdisk),
+//! and legacy MBR disks with extended partitions
+
+mod gpt;
+mod mbr;
+
+pub use gpt::Guid;
+
+use crate::{
+    io::{
+        self,
+        block::{check_transfer, BlockDevice},
+    },
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    Gpt {
+        type_guid: Guid,
+        unique_guid: Guid,
+        name: String,
+        attributes: u64,
+    },
+    Mbr {
+        system_id: u8,
+        bootable: bool,
+    },
+}
+
+/// A single partition, with its location in sectors of the underlying device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    /// Partition number, starting at 1. Logical MBR partitions start at 5
+    pub number: usize,
+    pub start: u64,
+    pub sector_count: u64,
+    pub kind: PartitionKind,
+}
+
+impl Partition {
+    /// Whether this is an EFI System Partition, which holds the bootloader and kernel
+    pub fn is_efi_system(&self) -> bool {
+        match &self.kind {
+            PartitionKind::Gpt { type_guid, .. } => *type_guid == Guid::EFI_SYSTEM,
+            PartitionKind::Mbr { system_id, .. } => *system_id == mbr::EFI_SYSTEM_ID,
+        }
+    }
+}
+
+/// Reads the partition table of `disk`
+///
+/// GPT is preferred when a protective MBR is present, falling back to the
+/// backup GPT header if the primary one is corrupted
+///
+/// Returns `NotFound` if the disk has no partition table at all
+pub fn read_partitions<B>(disk: &mut B) -> Result<Vec<Partition>, io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    let sector_size = disk.sector_size();
+    if sector_size < mbr::MBR_SIZE {
+        return Err(io::Error::Unaligned);
+    }
+
+    let mut sector = vec![0u8; sector_size];
+    disk.read_sectors(0, &mut sector)?;
+    let table = mbr::MasterBootRecord::parse(&sector).ok_or(io::Error::NotFound)?;
+
+    if table.is_protective() {
+        gpt::read_partitions(disk)
+    } else {
+        mbr::read_partitions(disk, &table)
+    }
+}
+
+/// Reads the partition table of `disk`, and returns its EFI System Partition
+pub fn find_efi_system_partition<B>(disk: &mut B) -> Result<Partition, io::Error>
+where
+    B: BlockDevice + ?Sized,
+{
+    read_partitions(disk)?
+        .into_iter()
+        .find(Partition::is_efi_system)
+        .ok_or(io::Error::NotFound)
+}
+
+/// A range of sectors on another device, exposed as a device of its own
+///
+/// Sector 0 of the partition device is the first sector of the partition, and
+/// accesses past the end of the partition are rejected
+pub struct PartitionDevice<B> {
+    disk: B,
+    start: u64,
+    sector_count: u64,
+}
+
+impl<B> PartitionDevice<B>
+where
+    B: BlockDevice,
+{
+    pub fn new(disk: B, partition: &Partition) -> Result<Self, io::Error> {
+        Self::from_range(disk, partition.start, partition.sector_count)
+    }
+
+    pub fn from_range(disk: B, start: u64, sector_count: u64) -> Result<Self, io::Error> {
+        match start.checked_add(sector_count) {
+            Some(end) if end <= disk.sector_count() => Ok(Self {
+                disk,
+                start,
+                sector_count,
+            }),
+            _ => Err(io::Error::OutOfBounds),
+        }
+    }
+
+    pub fn into_inner(self) -> B {
+        self.disk
+    }
+}
+
+impl<B> BlockDevice for PartitionDevice<B>
+where
+    B: BlockDevice,
+{
+    fn sector_size(&self) -> usize {
+        self.disk.sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sector_count
+    }
+
+    fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<(), io::Error> {
+        check_transfer(self, start, buf.len())?;
+        self.disk.read_sectors(self.start + start, buf)
+    }
+
+    fn write_sectors(&mut self, start: u64, buf: &[u8]) -> Result<(), io::Error> {
+        check_transfer(self, start, buf.len())?;
+        self.disk.write_sectors(self.start + start, buf)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.disk.flush()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.disk.is_read_only()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::{block::StreamBlockDevice, ramfile::RamFile};
+    use crate::Mutex;
+
+    fn put_entry(sector: &mut [u8], i: usize, system_id: u8, start: u32, count: u32) {
+        let entry = &mut sector[446 + i * 16..446 + (i + 1) * 16];
+        entry[4] = system_id;
+        entry[8..12].copy_from_slice(&start.to_le_bytes());
+        entry[12..16].copy_from_slice(&count.to_le_bytes());
+    }
+
+    fn boot_signature(sector: &mut [u8]) {
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+    }
+
+    #[test]
+    fn mbr_with_logical_partitions() {
+        let mut image = vec![0u8; 64 * 512];
+        put_entry(&mut image[..512], 0, 0xEF, 2, 10);
+        put_entry(&mut image[..512], 1, 0x0F, 20, 40);
+        boot_signature(&mut image[..512]);
+
+        // First EBR at the start of the extended partition, the second one 20 sectors in
+        let ebr = &mut image[20 * 512..21 * 512];
+        put_entry(ebr, 0, 0x83, 1, 5);
+        put_entry(ebr, 1, 0x05, 20, 20);
+        boot_signature(ebr);
+        let ebr = &mut image[40 * 512..41 * 512];
+        put_entry(ebr, 0, 0x83, 2, 10);
+        boot_signature(ebr);
+
+        let image = Mutex::new(image);
+        let mut disk = StreamBlockDevice::new(RamFile::new(&image, true), 512, true).unwrap();
+        let partitions = read_partitions(&mut disk).unwrap();
+
+        let layout: Vec<_> = partitions
+            .iter()
+            .map(|p| (p.number, p.start, p.sector_count))
+            .collect();
+        assert_eq!(layout, [(1, 2, 10), (5, 21, 5), (6, 42, 10)]);
+        assert_eq!(find_efi_system_partition(&mut disk).unwrap().number, 1);
+    }
+
+    #[test]
+    fn no_partition_table() {
+        let image = Mutex::new(vec![0u8; 4 * 512]);
+        let mut disk = StreamBlockDevice::new(RamFile::new(&image, true), 512, true).unwrap();
+
+        assert!(matches!(
+            read_partitions(&mut disk),
+            Err(io::Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn partition_device_is_bounded() {
+        let image = Mutex::new(vec![0u8; 8 * 512]);
+        let mut disk = StreamBlockDevice::new(RamFile::new(&image, false), 512, false).unwrap();
+        assert!(PartitionDevice::from_range(&mut disk, 4, 5).is_err());
+
+        let mut partition = PartitionDevice::from_range(disk, 4, 4).unwrap();
+        partition.write_sectors(0, &[7u8; 512]).unwrap();
+        assert!(partition.write_sectors(4, &[7u8; 512]).is_err());
+        assert_eq!(image.lock()[4 * 512], 7);
+        assert_eq!(image.lock()[4 * 512 - 1], 0);
+    }
+}
@@ -1,19 +1,27 @@
 use crate::{
     cmp::min,
     io::{self, Read, Seek, SeekFrom, Write},
+    ops::Deref,
     vec::Vec,
     Mutex,
 };
 
+/// A file backed by a buffer in memory
+///
+/// `C` is anything that derefs to the buffer, like a `&Mutex<Vec<u8>>`
+/// or an `Arc<Mutex<Vec<u8>>>` for a handle that owns its contents
 #[derive(Debug)]
-pub struct RamFile<'a> {
-    contents: &'a Mutex<Vec<u8>>,
+pub struct RamFile<C> {
+    contents: C,
     position: usize,
     read_only: bool,
 }
 
-impl<'a> RamFile<'a> {
-    pub fn new(contents: &'a Mutex<Vec<u8>>, read_only: bool) -> Self {
+impl<C> RamFile<C>
+where
+    C: Deref<Target = Mutex<Vec<u8>>>,
+{
+    pub fn new(contents: C, read_only: bool) -> Self {
         Self {
             contents,
             read_only,
@@ -22,33 +30,41 @@ impl<'a> RamFile<'a> {
     }
 }
 
-impl Read for RamFile<'_> {
+impl<C> Read for RamFile<C>
+where
+    C: Deref<Target = Mutex<Vec<u8>>>,
+{
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
         let contents = self.contents.lock();
+        if self.position >= contents.len() {
+            return Ok(0);
+        }
         let end = min(self.position + buf.len(), contents.len());
+        let read = end - self.position;
 
-        for (i, el) in contents[self.position..end].iter().enumerate() {
-            buf[i] = *el;
-        }
-        Ok(end - self.position)
+        buf[..read].copy_from_slice(&contents[self.position..end]);
+        self.position = end;
+        Ok(read)
     }
 }
 
-impl Write for RamFile<'_> {
+impl<C> Write for RamFile<C>
+where
+    C: Deref<Target = Mutex<Vec<u8>>>,
+{
     fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
         if self.read_only {
             return Err(io::Error::PermissionsError);
         }
 
         let mut contents = self.contents.lock();
-        for byte in buf {
-            if self.position < contents.len() {
-                contents[self.position] = *byte;
-            } else {
-                contents.push(*byte);
-            }
-            self.position += 1;
+        let end = self.position + buf.len();
+        if end > contents.len() {
+            // Writing after seeking past the end fills the gap with zeroes
+            contents.resize(end, 0);
         }
+        contents[self.position..end].copy_from_slice(buf);
+        self.position = end;
         Ok(buf.len())
     }
 
@@ -58,40 +74,50 @@ impl Write for RamFile<'_> {
     }
 }
 
-impl Seek for RamFile<'_> {
-    // TODO: This code is horrible, refactor this
+impl<C> Seek for RamFile<C>
+where
+    C: Deref<Target = Mutex<Vec<u8>>>,
+{
+    /// Seeking past the end is allowed, the file is only extended once written to
     fn seek(&mut self, seek_from: SeekFrom) -> Result<u64, io::Error> {
-        let len = self.contents.lock().len();
-        match seek_from {
-            SeekFrom::Start(n) => {
-                self.position = n as usize;
-                if self.position > len {
-                    self.position -= len;
-                }
-                Ok(self.position as u64)
-            }
-            SeekFrom::End(n) => {
-                let new_pos = len as i64 + n;
-                if new_pos < 0 {
-                    return Err(io::Error::NegativeSeekError);
-                }
-                self.position = new_pos as usize;
-                if self.position > len {
-                    self.position -= len;
-                }
-                Ok(self.position as u64)
-            }
-            SeekFrom::Current(n) => {
-                let new_pos = self.position as i64 + n;
-                if new_pos < 0 {
-                    return Err(io::Error::NegativeSeekError);
-                }
-                self.position = new_pos as usize;
-                if self.position > len {
-                    self.position -= len;
-                }
-                Ok(self.position as u64)
-            }
+        let new_pos = match seek_from {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => self.contents.lock().len() as i64 + n,
+            SeekFrom::Current(n) => self.position as i64 + n,
+        };
+        if new_pos < 0 {
+            return Err(io::Error::NegativeSeekError);
         }
+
+        self.position = new_pos as usize;
+        Ok(self.position as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writing_past_the_end_fills_the_gap_with_zeroes() {
+        let contents = Mutex::new(Vec::from(*b"abc"));
+        let mut file = RamFile::new(&contents, false);
+
+        file.seek(SeekFrom::End(2)).unwrap();
+        file.write_all(b"z").unwrap();
+        assert_eq!(*contents.lock(), b"abc\0\0z");
+
+        file.seek(SeekFrom::Start(1)).unwrap();
+        let mut buf = [0u8; 8];
+        assert_eq!(file.read(&mut buf).unwrap(), 5);
+        assert_eq!(&buf[..5], b"bc\0\0z");
+    }
+
+    #[test]
+    fn read_only_rejects_writes() {
+        let contents = Mutex::new(Vec::new());
+        let mut file = RamFile::new(&contents, true);
+
+        assert!(matches!(file.write(b"x"), Err(io::Error::PermissionsError)));
     }
 }
@@ -0,0 +1,133 @@
+//! Lock-free ring buffer
+//!
+//! Lets interrupt handlers hand data to the code that consumes it without a lock,
+//! which they could deadlock on by interrupting its owner
+
+use crate::cell::UnsafeCell;
+use crate::mem::MaybeUninit;
+use crate::sync::atomic::{AtomicUsize, Ordering};
+
+/// A queue of up to `N` values, for one producer and one consumer at a time
+///
+/// Interrupt handlers on a single CPU count as one producer, as they don't
+/// interrupt each other
+pub struct RingBuffer<T, const N: usize> {
+    slots: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// Number of values popped so far, only changed by the consumer
+    head: AtomicUsize,
+    /// Number of values pushed so far, only changed by the producer
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `value` to the end, or gives it back if the buffer is full
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == N {
+            return Err(value);
+        }
+
+        // Only this slot is touched, the consumer may be reading another one
+        unsafe { self.slot(tail).write(MaybeUninit::new(value)) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Removes the value at the front
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { self.slot(head).read().assume_init() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        unsafe { self.slots.get().cast::<MaybeUninit<T>>().add(index % N) }
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn keeps_order_and_capacity() {
+        let ring = RingBuffer::<u32, 3>::new();
+        assert_eq!(ring.pop(), None);
+
+        for round in 0..4 {
+            for i in 0..3 {
+                ring.push(round * 10 + i).unwrap();
+            }
+            assert_eq!(ring.push(99), Err(99));
+            assert_eq!(ring.len(), 3);
+
+            for i in 0..3 {
+                assert_eq!(ring.pop(), Some(round * 10 + i));
+            }
+            assert!(ring.is_empty());
+        }
+    }
+
+    #[test]
+    fn hands_values_across_threads() {
+        const COUNT: u32 = 10_000;
+        let ring = Arc::new(RingBuffer::<u32, 16>::new());
+
+        let producer = {
+            let ring = ring.clone();
+            thread::spawn(move || {
+                for i in 0..COUNT {
+                    while ring.push(i).is_err() {
+                        thread::yield_now();
+                    }
+                }
+            })
+        };
+
+        let mut expected = 0;
+        while expected < COUNT {
+            match ring.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -1,9 +1,9 @@
+use super::input::{KeyEvent, KeyState, INPUT};
+use super::line_editor::LineEditor;
 use super::{Error, Read};
 use crate::hlt::hlt;
 use crate::print;
 use crate::string::String;
-use crate::vec::Vec;
-use spin::Mutex;
 
 #[derive(Clone)]
 pub struct Stdin;
@@ -19,19 +19,11 @@ impl Read for Stdin {
 }
 
 impl Stdin {
+    /// Reads a line, including the newline, with the editing keys of [`LineEditor`]
+    /// but without history
     pub fn read_line(&self, buf: &mut String) -> Result<usize, Error> {
-        let mut line = Vec::new();
-
-        while line.last() != Some(&'\n') {
-            let c = getchar();
-            if c as u8 == 8 {
-                line.pop();
-            } else {
-                line.push(c);
-            }
-        }
-
-        *buf = line.into_iter().collect();
+        *buf = LineEditor::new(0).read_line("");
+        buf.push('\n');
         Ok(buf.len())
     }
 }
@@ -40,18 +32,56 @@ pub fn stdin() -> Stdin {
     Stdin
 }
 
-// Will be set by the interrupt handler
-pub static CUR_CHAR: Mutex<Option<char>> = Mutex::new(None);
+/// Waits for the next key event
+pub fn read_event() -> KeyEvent {
+    loop {
+        if let Some(event) = INPUT.pop() {
+            return event;
+        }
+        hlt();
+    }
+}
 
-// TODO this is platform specific code and should probably be in a separate module
+/// Waits for the next key press that types a character, and echoes it
 pub fn getchar() -> char {
-    while CUR_CHAR.lock().is_none() {
-        hlt();
+    loop {
+        let event = read_event();
+        if event.state != KeyState::Pressed {
+            continue;
+        }
+
+        if let Some(c) = event.key.to_char() {
+            print!("{c}");
+            return c;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::input::{self, Key};
+    use std::thread;
+
+    /// Feeds `input` to stdin one key press at a time, like the keyboard interrupt would
+    fn type_chars(input: &'static str) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            for c in input.chars() {
+                while !input::push(KeyEvent::pressed(Key::from(c))) {
+                    thread::yield_now();
+                }
+            }
+        })
     }
 
-    let c = CUR_CHAR.lock().unwrap();
-    *CUR_CHAR.lock() = None;
+    #[test]
+    fn read_line_handles_backspace() {
+        let typist = type_chars("lx\x08s -a\n");
+        let mut line = String::new();
+        let len = stdin().read_line(&mut line).unwrap();
+        typist.join().unwrap();
 
-    print!("{c}");
-    c
+        assert_eq!(line, "ls -a\n");
+        assert_eq!(len, 6);
+    }
 }
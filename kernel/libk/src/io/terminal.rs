@@ -0,0 +1,288 @@
+//! Decoding of terminal input
+//!
+//! Turns the bytes a terminal sends over a serial line into key presses, so it
+//! can be used like a keyboard
+
+use super::input::{Key, KeyEvent, KeyState, Modifiers};
+
+/// Longest UTF-8 encoded character
+const MAX_UTF8_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After an ESC
+    Escape,
+    /// Inside an `ESC [` control sequence, with the first parameter so far
+    Csi {
+        param: u16,
+        more_params: bool,
+    },
+    /// After `ESC O`, which some terminals send for arrows and F1 to F4
+    Ss3,
+    /// Inside a multi-byte UTF-8 character
+    Utf8 {
+        len: usize,
+        needed: usize,
+    },
+}
+
+/// Decodes the bytes from a terminal, one at a time
+///
+/// Terminals only report presses, so only press events are produced
+#[derive(Debug, Clone)]
+pub struct TerminalDecoder {
+    state: State,
+    utf8: [u8; MAX_UTF8_LEN],
+    /// Whether the last byte was a CR, so an LF right after it isn't another Enter
+    after_cr: bool,
+}
+
+impl TerminalDecoder {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            utf8: [0; MAX_UTF8_LEN],
+            after_cr: false,
+        }
+    }
+
+    /// Adds the next byte, returns the key press it completes if any
+    ///
+    /// A lone ESC is only reported once the byte after it arrives, as it could be
+    /// the start of a control sequence
+    pub fn push(&mut self, byte: u8) -> Option<KeyEvent> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+
+        match self.state {
+            State::Ground => self.ground(byte, after_cr),
+            State::Escape => {
+                self.state = State::Ground;
+                match byte {
+                    b'[' => {
+                        self.state = State::Csi {
+                            param: 0,
+                            more_params: false,
+                        }
+                    }
+                    b'O' => self.state = State::Ss3,
+                    0x1b => return Some(KeyEvent::pressed(Key::Escape)),
+                    // Terminals send ESC before a key to mean Alt
+                    _ => {
+                        return self.ground(byte, after_cr).map(|mut event| {
+                            event.modifiers.alt = true;
+                            event
+                        })
+                    }
+                }
+                None
+            }
+            State::Csi { param, more_params } => match byte {
+                b'0'..=b'9' if !more_params => {
+                    let digit = (byte - b'0') as u16;
+                    let param = param.saturating_mul(10).saturating_add(digit);
+                    self.state = State::Csi { param, more_params };
+                    None
+                }
+                // Only the first parameter matters, the others are modifiers
+                b'0'..=b'9' | b';' => {
+                    self.state = State::Csi {
+                        param,
+                        more_params: true,
+                    };
+                    None
+                }
+                // Final byte
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    csi_key(param, byte).map(KeyEvent::pressed)
+                }
+                // Intermediate bytes are ignored
+                0x20..=0x3f => None,
+                // Not a valid sequence, start over
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+            State::Ss3 => {
+                self.state = State::Ground;
+                ss3_key(byte).map(KeyEvent::pressed)
+            }
+            State::Utf8 { len, needed } => {
+                if byte & 0xC0 != 0x80 {
+                    // The character was cut short, so it is dropped
+                    self.state = State::Ground;
+                    return self.ground(byte, after_cr);
+                }
+
+                self.utf8[len] = byte;
+                let len = len + 1;
+                if len < needed {
+                    self.state = State::Utf8 { len, needed };
+                    return None;
+                }
+
+                self.state = State::Ground;
+                core::str::from_utf8(&self.utf8[..len])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map(|c| KeyEvent::pressed(Key::Char(c)))
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8, after_cr: bool) -> Option<KeyEvent> {
+        let key = match byte {
+            0x1b => {
+                self.state = State::Escape;
+                return None;
+            }
+            b'\r' => Key::Enter,
+            b'\n' if after_cr => return None,
+            b'\n' => Key::Enter,
+            // Terminals send DEL for the backspace key
+            0x08 | 0x7f => Key::Backspace,
+            b'\t' => Key::Tab,
+            // Ctrl+A to Ctrl+Z, reported like the keyboard does
+            0x01..=0x1a => {
+                return Some(KeyEvent {
+                    key: Key::Char((b'a' + byte - 1) as char),
+                    state: KeyState::Pressed,
+                    modifiers: Modifiers {
+                        ctrl: true,
+                        ..Modifiers::default()
+                    },
+                })
+            }
+            0x00..=0x1f => return None,
+            0x20..=0x7e => Key::Char(byte as char),
+            _ => {
+                let needed = match byte {
+                    0xC2..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF4 => 4,
+                    _ => return None,
+                };
+                self.utf8[0] = byte;
+                self.state = State::Utf8 { len: 1, needed };
+                return None;
+            }
+        };
+        Some(KeyEvent::pressed(key))
+    }
+}
+
+impl Default for TerminalDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The key for `ESC [ param final`, as sent by xterm and VT220 compatible terminals
+fn csi_key(param: u16, final_byte: u8) -> Option<Key> {
+    Some(match final_byte {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'~' => match param {
+            1 | 7 => Key::Home,
+            2 => Key::Insert,
+            3 => Key::Delete,
+            4 | 8 => Key::End,
+            5 => Key::PageUp,
+            6 => Key::PageDown,
+            11..=15 => Key::Function((param - 10) as u8),
+            17..=21 => Key::Function((param - 11) as u8),
+            23 | 24 => Key::Function((param - 12) as u8),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+/// The key for `ESC O final`
+fn ss3_key(final_byte: u8) -> Option<Key> {
+    Some(match final_byte {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'P'..=b'S' => Key::Function(final_byte - b'P' + 1),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec::Vec;
+
+    fn decode(bytes: &[u8]) -> Vec<KeyEvent> {
+        let mut decoder = TerminalDecoder::new();
+        bytes.iter().filter_map(|&b| decoder.push(b)).collect()
+    }
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        decode(bytes).into_iter().map(|event| event.key).collect()
+    }
+
+    #[test]
+    fn translates_line_endings_and_backspace() {
+        assert_eq!(
+            keys(b"ls\r\n\r\rx\n\x7f\x08\t"),
+            [
+                Key::Char('l'),
+                Key::Char('s'),
+                Key::Enter,
+                Key::Enter,
+                Key::Enter,
+                Key::Char('x'),
+                Key::Enter,
+                Key::Backspace,
+                Key::Backspace,
+                Key::Tab,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_escape_sequences() {
+        assert_eq!(
+            keys(b"\x1b[A\x1b[D\x1bOB\x1b[3~\x1b[1;5C\x1b[15~\x1bOP\x1b\x1b"),
+            [
+                Key::Up,
+                Key::Left,
+                Key::Down,
+                Key::Delete,
+                Key::Right,
+                Key::Function(5),
+                Key::Function(1),
+                Key::Escape,
+            ]
+        );
+
+        let alt_x = decode(b"\x1bx");
+        assert_eq!(alt_x[0].key, Key::Char('x'));
+        assert!(alt_x[0].modifiers.alt);
+
+        let ctrl_c = decode(b"\x03");
+        assert_eq!(ctrl_c[0].key, Key::Char('c'));
+        assert!(ctrl_c[0].modifiers.ctrl);
+    }
+
+    #[test]
+    fn decodes_utf8() {
+        assert_eq!(
+            keys("é€😀".as_bytes()),
+            [Key::Char('é'), Key::Char('€'), Key::Char('😀')]
+        );
+        // A character that is cut short is dropped
+        assert_eq!(keys(b"\xC3a"), [Key::Char('a')]);
+    }
+}
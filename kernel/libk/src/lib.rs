@@ -1,15 +1,17 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 pub extern crate alloc;
 pub extern crate core;
 
-pub use alloc::{rc, slice, str, string, vec};
+pub use alloc::{boxed, rc, slice, str, string, vec};
 pub use core::*;
 
+pub mod fs;
 pub mod io;
+pub mod time;
 
 mod hlt;
 mod utils;
 
 pub use hashbrown::{hash_map, hash_set, hash_table};
-pub use spin::{Mutex, MutexGuard};
+pub use spin::{Mutex, MutexGuard, Once};
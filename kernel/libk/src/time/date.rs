@@ -0,0 +1,161 @@
+use crate::fmt;
+
+use super::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A UTC calendar date and time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// The time this date refers to, None for dates before the epoch or that don't exist
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let days_in_month = match self.month {
+            2 if is_leap_year(self.year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            1..=12 => 31,
+            _ => return None,
+        };
+        if self.year < 1970
+            || !(1..=days_in_month).contains(&self.day)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+        {
+            return None;
+        }
+
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64) as u64;
+        let seconds = days * SECONDS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64;
+        UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+    }
+}
+
+impl From<SystemTime> for DateTime {
+    fn from(time: SystemTime) -> Self {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let (year, month, day) = civil_from_days((seconds / SECONDS_PER_DAY) as i64);
+        let seconds_of_day = seconds % SECONDS_PER_DAY;
+
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds_of_day / 3600) as u8,
+            minute: (seconds_of_day / 60 % 60) as u8,
+            second: (seconds_of_day % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+// Conversions between days since the epoch and dates in the proleptic Gregorian
+// calendar, from http://howardhinnant.github.io/date_algorithms.html. Years are
+// counted from March, so the leap day is at the end of the year, in 400 year eras
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::string::ToString;
+
+    fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        }
+    }
+
+    #[test]
+    fn converts_to_and_from_unix_time() {
+        let cases = [
+            (date(1970, 1, 1, 0, 0, 0), 0),
+            (date(2000, 2, 29, 12, 0, 0), 951_825_600),
+            (date(2024, 12, 31, 23, 59, 59), 1_735_689_599),
+            (date(2100, 3, 1, 0, 0, 1), 4_107_542_401),
+        ];
+
+        for (date, seconds) in cases {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(date.to_system_time(), Some(time));
+            assert_eq!(DateTime::from(time), date);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert_eq!(date(2023, 2, 29, 0, 0, 0).to_system_time(), None);
+        assert_eq!(date(2024, 13, 1, 0, 0, 0).to_system_time(), None);
+        assert_eq!(date(1969, 12, 31, 23, 59, 59).to_system_time(), None);
+        assert_eq!(date(2024, 1, 1, 24, 0, 0).to_system_time(), None);
+        assert!(date(2024, 2, 29, 0, 0, 0).to_system_time().is_some());
+    }
+
+    #[test]
+    fn displays_as_iso_8601() {
+        assert_eq!(
+            date(2024, 5, 7, 8, 9, 10).to_string(),
+            "2024-05-07 08:09:10"
+        );
+    }
+}
@@ -0,0 +1,149 @@
+//! Monotonic and wall-clock time
+//!
+//! `Instant` and `SystemTime` work like the ones in std. `Instant` reads the clock
+//! the kernel sets with `set_clock` once its clocksources are running, and
+//! `SystemTime` adds it to the wall-clock time at boot, set with `set_system_time`
+
+mod date;
+mod system;
+
+use crate::ops::{Add, AddAssign, Sub, SubAssign};
+use spin::Once;
+
+pub use core::time::*;
+pub use date::DateTime;
+pub use system::{set_system_time, SystemTime, SystemTimeError, UNIX_EPOCH};
+
+/// Returns the nanoseconds since boot
+pub type Clock = fn() -> u64;
+
+static CLOCK: Once<Clock> = Once::new();
+
+/// Sets the clock `Instant::now` reads, only the first call has an effect
+pub fn set_clock(clock: Clock) {
+    CLOCK.call_once(|| clock);
+}
+
+/// A point in monotonic time, measured from boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    nanos: u64,
+}
+
+impl Instant {
+    /// The current time, or boot if the clock hasn't been set yet
+    pub fn now() -> Instant {
+        Instant {
+            nanos: CLOCK.get().map_or(0, |clock| clock()),
+        }
+    }
+
+    /// Time between boot and this instant
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_nanos(self.nanos)
+    }
+
+    /// Time from `earlier` to this instant, zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.nanos
+            .checked_sub(earlier.nanos)
+            .map(Duration::from_nanos)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant {
+            nanos: self.nanos.checked_add(nanos)?,
+        })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Instant {
+            nanos: self.nanos.checked_sub(nanos)?,
+        })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::atomic::{AtomicU64, Ordering};
+
+    static NANOS: AtomicU64 = AtomicU64::new(0);
+
+    fn fake_clock() -> u64 {
+        NANOS.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn instants_follow_the_clock() {
+        set_clock(fake_clock);
+        NANOS.store(1_000, Ordering::Relaxed);
+        let start = Instant::now();
+        NANOS.store(1_000_000_000 + 1_000, Ordering::Relaxed);
+
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        assert_eq!(Instant::now() - start, Duration::from_secs(1));
+        assert_eq!(start.since_boot(), Duration::from_micros(1));
+    }
+
+    #[test]
+    fn arithmetic_saturates_or_checks() {
+        let start = Instant { nanos: 500 };
+        let later = start + Duration::from_nanos(250);
+
+        assert_eq!(later.nanos, 750);
+        assert_eq!(later - Duration::from_nanos(750), Instant { nanos: 0 });
+        assert_eq!(start - later, Duration::ZERO);
+        assert_eq!(start.checked_duration_since(later), None);
+        assert_eq!(start.checked_sub(Duration::from_nanos(501)), None);
+        assert_eq!(start.checked_add(Duration::MAX), None);
+    }
+}
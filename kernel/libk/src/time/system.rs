@@ -0,0 +1,107 @@
+use crate::fmt;
+use crate::ops::{Add, AddAssign, Sub, SubAssign};
+use crate::sync::atomic::{AtomicU64, Ordering};
+
+use super::{Duration, Instant};
+
+/// Wall-clock time at boot, in nanoseconds since the Unix epoch
+static BOOT_TIME: AtomicU64 = AtomicU64::new(0);
+
+pub const UNIX_EPOCH: SystemTime = SystemTime {
+    since_epoch: Duration::ZERO,
+};
+
+/// Sets the wall-clock time, later calls to `SystemTime::now` count on from `now`
+pub fn set_system_time(now: SystemTime) {
+    let boot_time = now.since_epoch.saturating_sub(Instant::now().since_boot());
+    BOOT_TIME.store(boot_time.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// A point in wall-clock time, which unlike `Instant` can jump when the time is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime {
+    since_epoch: Duration,
+}
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    /// The current time, counting from the epoch if the time hasn't been set
+    pub fn now() -> SystemTime {
+        let boot_time = Duration::from_nanos(BOOT_TIME.load(Ordering::Relaxed));
+        SystemTime {
+            since_epoch: boot_time + Instant::now().since_boot(),
+        }
+    }
+
+    /// Time from `earlier` to this time, fails with the time in the other direction
+    /// if `earlier` is later
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+        self.since_epoch
+            .checked_sub(earlier.since_epoch)
+            .ok_or_else(|| SystemTimeError(earlier.since_epoch - self.since_epoch))
+    }
+
+    pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        Some(SystemTime {
+            since_epoch: self.since_epoch.checked_add(duration)?,
+        })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        Some(SystemTime {
+            since_epoch: self.since_epoch.checked_sub(duration)?,
+        })
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, duration: Duration) -> SystemTime {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to system time")
+    }
+}
+
+impl AddAssign<Duration> for SystemTime {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, duration: Duration) -> SystemTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from system time")
+    }
+}
+
+impl SubAssign<Duration> for SystemTime {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+/// Returned by `SystemTime::duration_since` when the other time is later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemTimeError(Duration);
+
+impl SystemTimeError {
+    /// How much later the other time is
+    pub fn duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl fmt::Display for SystemTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "second time provided was later than self")
+    }
+}
@@ -0,0 +1,516 @@
+#![cfg_attr(not(test), no_std)]
+
+//! In-memory filesystem
+//!
+//! The directory tree sits behind a single lock, while every regular file is a
+//! reference counted inode with its own lock. Open handles share the inode, so
+//! they are `'static` and keep working after the file is removed
+
+use libk::{
+    alloc::sync::Arc,
+    boxed::Box,
+    fs::{DirEntry, Directory, File, Metadata},
+    hash_map::HashMap,
+    io::{self, ramfile::RamFile, Path},
+    string::{String, ToString},
+    vec::Vec,
+    Mutex,
+};
+
+/// Contents of a regular file, shared by the tree and every open handle
+type FileInode = Arc<Mutex<Vec<u8>>>;
+
+#[derive(Debug)]
+enum Inode {
+    Directory(DirInode),
+    File(FileInode),
+}
+
+impl Inode {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Inode::Directory(_) => Metadata::directory(),
+            Inode::File(contents) => Metadata::file(contents.lock().len() as u64),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct DirInode {
+    entries: HashMap<String, Inode>,
+}
+
+impl DirInode {
+    /// Walks `path` down from this directory
+    fn resolve(&self, path: &[&str]) -> Result<&DirInode, io::Error> {
+        let mut dir = self;
+        for name in path {
+            dir = match dir.entries.get(*name) {
+                Some(Inode::Directory(child)) => child,
+                Some(Inode::File(_)) => return Err(io::Error::NotADirectory),
+                None => return Err(io::Error::NotFound),
+            };
+        }
+        Ok(dir)
+    }
+
+    fn resolve_mut(&mut self, path: &[&str]) -> Result<&mut DirInode, io::Error> {
+        let mut dir = self;
+        for name in path {
+            dir = match dir.entries.get_mut(*name) {
+                Some(Inode::Directory(child)) => child,
+                Some(Inode::File(_)) => return Err(io::Error::NotADirectory),
+                None => return Err(io::Error::NotFound),
+            };
+        }
+        Ok(dir)
+    }
+}
+
+/// An in-memory directory tree
+///
+/// Cloning is cheap, and all clones refer to the same tree
+#[derive(Debug, Clone, Default)]
+pub struct RamFsDirectory {
+    root: Arc<Mutex<DirInode>>,
+}
+
+/// Splits a path into its non-empty segments
+fn segments(path: &Path) -> Vec<&str> {
+    path.segments
+        .iter()
+        .map(String::as_str)
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+impl RamFsDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn open_inner(
+        &self,
+        path: &Path,
+        read_only: bool,
+        create: bool,
+    ) -> Result<RamFile<FileInode>, io::Error> {
+        let path = segments(path);
+        let (name, parent) = path.split_last().ok_or(io::Error::IsDirectory)?;
+
+        let mut root = self.root.lock();
+        let dir = root.resolve_mut(parent)?;
+        let contents = match dir.entries.get(*name) {
+            Some(Inode::File(contents)) => {
+                if create {
+                    contents.lock().clear();
+                }
+                contents.clone()
+            }
+            Some(Inode::Directory(_)) => return Err(io::Error::IsDirectory),
+            None if create => {
+                let contents = FileInode::default();
+                dir.entries
+                    .insert(name.to_string(), Inode::File(contents.clone()));
+                contents
+            }
+            None => return Err(io::Error::NotFound),
+        };
+
+        Ok(RamFile::new(contents, read_only))
+    }
+}
+
+impl Directory for RamFsDirectory {
+    /// Creates the directory, along with any missing parent directories
+    fn mkdir(&self, path: &Path) -> Result<(), io::Error> {
+        let mut root = self.root.lock();
+        let mut dir = &mut *root;
+
+        for name in segments(path) {
+            let child = dir
+                .entries
+                .entry(name.to_string())
+                .or_insert_with(|| Inode::Directory(DirInode::default()));
+            dir = match child {
+                Inode::Directory(child) => child,
+                Inode::File(_) => return Err(io::Error::NotADirectory),
+            };
+        }
+
+        Ok(())
+    }
+
+    fn open(&self, path: &Path, read_only: bool) -> Result<Box<dyn File>, io::Error> {
+        let file = self.open_inner(path, read_only, false)?;
+        Ok(Box::new(file))
+    }
+
+    /// Opens the file for writing, creating it if it doesn't exist and truncating it if it does
+    fn create(&self, path: &Path) -> Result<Box<dyn File>, io::Error> {
+        let file = self.open_inner(path, false, true)?;
+        Ok(Box::new(file))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>, io::Error> {
+        let root = self.root.lock();
+        let dir = root.resolve(&segments(path))?;
+
+        let entries = dir
+            .entries
+            .iter()
+            .map(|(name, inode)| DirEntry {
+                name: name.clone(),
+                metadata: inode.metadata(),
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata, io::Error> {
+        let path = segments(path);
+        let Some((name, parent)) = path.split_last() else {
+            return Ok(Metadata::directory());
+        };
+
+        let root = self.root.lock();
+        let dir = root.resolve(parent)?;
+        dir.entries
+            .get(*name)
+            .map(Inode::metadata)
+            .ok_or(io::Error::NotFound)
+    }
+
+    /// Removes the file from the tree, open handles keep their contents
+    fn remove_file(&self, path: &Path) -> Result<(), io::Error> {
+        let path = segments(path);
+        let (name, parent) = path.split_last().ok_or(io::Error::IsDirectory)?;
+
+        let mut root = self.root.lock();
+        let dir = root.resolve_mut(parent)?;
+        match dir.entries.get(*name) {
+            Some(Inode::File(_)) => {
+                dir.entries.remove(*name);
+                Ok(())
+            }
+            Some(Inode::Directory(_)) => Err(io::Error::IsDirectory),
+            None => Err(io::Error::NotFound),
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<(), io::Error> {
+        let path = segments(path);
+        // The root directory can't be removed
+        let (name, parent) = path.split_last().ok_or(io::Error::InvalidPath)?;
+
+        let mut root = self.root.lock();
+        let dir = root.resolve_mut(parent)?;
+        match dir.entries.get(*name) {
+            Some(Inode::Directory(child)) if !child.entries.is_empty() => {
+                Err(io::Error::DirectoryNotEmpty)
+            }
+            Some(Inode::Directory(_)) => {
+                dir.entries.remove(*name);
+                Ok(())
+            }
+            Some(Inode::File(_)) => Err(io::Error::NotADirectory),
+            None => Err(io::Error::NotFound),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<(), io::Error> {
+        let from = segments(from);
+        let to = segments(to);
+        let (from_name, from_parent) = from.split_last().ok_or(io::Error::InvalidPath)?;
+        let (to_name, to_parent) = to.split_last().ok_or(io::Error::InvalidPath)?;
+
+        let mut root = self.root.lock();
+        if !root.resolve(from_parent)?.entries.contains_key(*from_name) {
+            return Err(io::Error::NotFound);
+        }
+        // A directory can't be moved inside of itself
+        if to.starts_with(&from) {
+            return Err(io::Error::InvalidPath);
+        }
+        if root.resolve(to_parent)?.entries.contains_key(*to_name) {
+            return Err(io::Error::AlreadyExists);
+        }
+
+        // Both directories exist and the tree is locked, so neither step can fail
+        let inode = root
+            .resolve_mut(from_parent)?
+            .entries
+            .remove(*from_name)
+            .ok_or(io::Error::NotFound)?;
+        root.resolve_mut(to_parent)?
+            .entries
+            .insert(to_name.to_string(), inode);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libk::io::SeekFrom;
+    use std::thread;
+
+    fn path(path: &str) -> Path {
+        Path::from(path)
+    }
+
+    fn read_all(file: &mut dyn File) -> Vec<u8> {
+        let mut contents = Vec::new();
+        let mut buf = [0u8; 16];
+        loop {
+            match file.read(&mut buf).unwrap() {
+                0 => return contents,
+                n => contents.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    fn names(fs: &RamFsDirectory, dir: &str) -> Vec<String> {
+        let mut names: Vec<String> = fs
+            .read_dir(&path(dir))
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn nested_create_and_open() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("a/b/c")).unwrap();
+
+        let mut file = fs.create(&path("a/b/c/file.txt")).unwrap();
+        file.write_all(b"hello world").unwrap();
+
+        let mut file = fs.open(&path("/a//b/c/file.txt"), true).unwrap();
+        assert_eq!(read_all(&mut *file), b"hello world");
+        assert_eq!(names(&fs, "a/b"), ["c"]);
+    }
+
+    #[test]
+    fn mkdir_creates_parents_and_keeps_existing() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("x/y")).unwrap();
+        fs.create(&path("x/y/file")).unwrap();
+
+        fs.mkdir(&path("x/y")).unwrap();
+        assert_eq!(names(&fs, "x/y"), ["file"]);
+        assert!(fs.metadata(&path("x")).unwrap().is_dir());
+    }
+
+    #[test]
+    fn open_errors() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("dir")).unwrap();
+        fs.create(&path("file")).unwrap();
+
+        assert!(matches!(
+            fs.open(&path("missing"), true),
+            Err(io::Error::NotFound)
+        ));
+        assert!(matches!(
+            fs.open(&path("missing/file"), true),
+            Err(io::Error::NotFound)
+        ));
+        assert!(matches!(
+            fs.open(&path("dir"), true),
+            Err(io::Error::IsDirectory)
+        ));
+        assert!(matches!(
+            fs.create(&path("file/nested")),
+            Err(io::Error::NotADirectory)
+        ));
+        assert!(matches!(
+            fs.mkdir(&path("file/nested")),
+            Err(io::Error::NotADirectory)
+        ));
+    }
+
+    #[test]
+    fn read_only_handles_reject_writes() {
+        let fs = RamFsDirectory::new();
+        fs.create(&path("file")).unwrap();
+
+        let mut file = fs.open(&path("file"), true).unwrap();
+        assert!(matches!(
+            file.write(b"data"),
+            Err(io::Error::PermissionsError)
+        ));
+    }
+
+    #[test]
+    fn create_truncates() {
+        let fs = RamFsDirectory::new();
+        fs.create(&path("file"))
+            .unwrap()
+            .write_all(b"long contents")
+            .unwrap();
+        fs.create(&path("file"))
+            .unwrap()
+            .write_all(b"short")
+            .unwrap();
+
+        let mut file = fs.open(&path("file"), true).unwrap();
+        assert_eq!(read_all(&mut *file), b"short");
+    }
+
+    #[test]
+    fn handles_share_contents() {
+        let fs = RamFsDirectory::new();
+        let mut writer = fs.create(&path("file")).unwrap();
+        let mut reader = fs.open(&path("file"), true).unwrap();
+
+        writer.write_all(b"abc").unwrap();
+        assert_eq!(read_all(&mut *reader), b"abc");
+
+        writer.seek(SeekFrom::Start(1)).unwrap();
+        writer.write_all(b"X").unwrap();
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(read_all(&mut *reader), b"aXc");
+    }
+
+    #[test]
+    fn handles_outlive_removal_and_the_tree() {
+        let fs = RamFsDirectory::new();
+        let mut file = fs.create(&path("file")).unwrap();
+        file.write_all(b"still here").unwrap();
+
+        fs.remove_file(&path("file")).unwrap();
+        assert!(matches!(
+            fs.open(&path("file"), true),
+            Err(io::Error::NotFound)
+        ));
+        drop(fs);
+
+        file.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(read_all(&mut *file), b"still here");
+    }
+
+    #[test]
+    fn read_dir_and_metadata() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("dir")).unwrap();
+        fs.create(&path("file"))
+            .unwrap()
+            .write_all(b"1234")
+            .unwrap();
+
+        let mut entries = fs.read_dir(&path("")).unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(
+            entries,
+            [
+                DirEntry {
+                    name: "dir".into(),
+                    metadata: Metadata::directory(),
+                },
+                DirEntry {
+                    name: "file".into(),
+                    metadata: Metadata::file(4),
+                },
+            ]
+        );
+        assert!(fs.metadata(&path("")).unwrap().is_dir());
+        assert!(matches!(
+            fs.read_dir(&path("file")),
+            Err(io::Error::NotADirectory)
+        ));
+    }
+
+    #[test]
+    fn remove() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("dir/sub")).unwrap();
+        fs.create(&path("file")).unwrap();
+
+        assert!(matches!(
+            fs.remove_file(&path("dir")),
+            Err(io::Error::IsDirectory)
+        ));
+        assert!(matches!(
+            fs.remove_dir(&path("file")),
+            Err(io::Error::NotADirectory)
+        ));
+        assert!(matches!(
+            fs.remove_dir(&path("dir")),
+            Err(io::Error::DirectoryNotEmpty)
+        ));
+
+        fs.remove_dir(&path("dir/sub")).unwrap();
+        fs.remove_dir(&path("dir")).unwrap();
+        fs.remove_file(&path("file")).unwrap();
+        assert!(names(&fs, "").is_empty());
+    }
+
+    #[test]
+    fn rename() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("a/b")).unwrap();
+        fs.create(&path("a/file"))
+            .unwrap()
+            .write_all(b"data")
+            .unwrap();
+        fs.create(&path("other")).unwrap();
+
+        fs.rename(&path("a/file"), &path("a/b/moved")).unwrap();
+        let mut file = fs.open(&path("a/b/moved"), true).unwrap();
+        assert_eq!(read_all(&mut *file), b"data");
+
+        fs.rename(&path("a"), &path("c")).unwrap();
+        assert_eq!(names(&fs, ""), ["c", "other"]);
+        assert_eq!(names(&fs, "c/b"), ["moved"]);
+
+        assert!(matches!(
+            fs.rename(&path("c"), &path("c/b/c")),
+            Err(io::Error::InvalidPath)
+        ));
+        assert!(matches!(
+            fs.rename(&path("other"), &path("c/b/moved")),
+            Err(io::Error::AlreadyExists)
+        ));
+        assert!(matches!(
+            fs.rename(&path("missing"), &path("c/missing")),
+            Err(io::Error::NotFound)
+        ));
+        assert!(matches!(
+            fs.rename(&path("other"), &path("missing/other")),
+            Err(io::Error::NotFound)
+        ));
+        assert_eq!(names(&fs, ""), ["c", "other"]);
+    }
+
+    #[test]
+    fn concurrent_creates() {
+        let fs = RamFsDirectory::new();
+        fs.mkdir(&path("dir")).unwrap();
+
+        let threads: Vec<_> = (0..8)
+            .map(|t| {
+                let fs = fs.clone();
+                thread::spawn(move || {
+                    for i in 0..50 {
+                        let name = format!("dir/{t}-{i}");
+                        fs.create(&path(&name))
+                            .unwrap()
+                            .write_all(name.as_bytes())
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(names(&fs, "dir").len(), 8 * 50);
+        let mut file = fs.open(&path("dir/7-49"), true).unwrap();
+        assert_eq!(read_all(&mut *file), b"dir/7-49");
+    }
+}